@@ -0,0 +1,9 @@
+//! Prints the JSON Schema of the interchange format
+//!
+//! Regenerate the checked-in schema with:
+//! cargo run --example interchange_schema > schema/graph-composition.schema.json
+
+fn main() {
+    let schema = cim_compose::interchange_schema();
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+}
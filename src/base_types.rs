@@ -3,6 +3,7 @@
 //! This module provides graph-specific types that work with
 //! the core domain types from cim-domain.
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
@@ -27,13 +28,23 @@ pub type BoundedContextId = EntityId<markers::BoundedContextMarker>;
 
 /// Node ID - only meaningful within a graph context
 /// These are NOT entities - they're local identifiers within a graph
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
 pub struct NodeId(Uuid);
 
 impl NodeId {
     pub fn new() -> Self {
         Self(Uuid::new_v4())
     }
+
+    pub fn from_uuid(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+
+    pub fn as_uuid(&self) -> &Uuid {
+        &self.0
+    }
 }
 
 impl Default for NodeId {
@@ -50,13 +61,23 @@ impl fmt::Display for NodeId {
 
 /// Edge ID - only meaningful within a graph context
 /// These are NOT entities - they're local identifiers within a graph
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
 pub struct EdgeId(Uuid);
 
 impl EdgeId {
     pub fn new() -> Self {
         Self(Uuid::new_v4())
     }
+
+    pub fn from_uuid(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+
+    pub fn as_uuid(&self) -> &Uuid {
+        &self.0
+    }
 }
 
 impl Default for EdgeId {
@@ -71,8 +92,68 @@ impl fmt::Display for EdgeId {
     }
 }
 
+/// Namespace under which name-derived ID namespaces are generated
+pub const CIM_COMPOSE_NAMESPACE: Uuid = Uuid::from_u128(0x6a1e_2f0c_8d3b_4c57_9e41_b2d7_c03f_5a18);
+
+/// How a graph generates IDs for the nodes and edges added to it
+///
+/// `Random` matches `NodeId::new()`. The other strategies are reproducible:
+/// building the same graph the same way yields the same IDs, which keeps
+/// snapshots and diffs stable.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+pub enum IdStrategy {
+    /// Random UUIDv4 IDs
+    #[default]
+    Random,
+    /// UUIDv5 IDs derived from a namespace and each element's name
+    Derived { namespace: Uuid },
+    /// IDs built from a seed and a counter
+    Sequential { seed: u64, next: u64 },
+}
+
+impl IdStrategy {
+    /// Derive IDs under an explicit namespace
+    pub fn derived(namespace: Uuid) -> Self {
+        IdStrategy::Derived { namespace }
+    }
+
+    /// Derive IDs under a namespace generated from a name
+    pub fn named(name: &str) -> Self {
+        Self::derived(Uuid::new_v5(&CIM_COMPOSE_NAMESPACE, name.as_bytes()))
+    }
+
+    /// Derive IDs from an aggregate's type and ID
+    pub fn for_aggregate(aggregate_type: &str, aggregate_id: &str) -> Self {
+        Self::named(&format!("{aggregate_type}/{aggregate_id}"))
+    }
+
+    /// Number IDs sequentially from a seed
+    pub fn sequential(seed: u64) -> Self {
+        IdStrategy::Sequential { seed, next: 0 }
+    }
+
+    pub fn is_random(&self) -> bool {
+        matches!(self, IdStrategy::Random)
+    }
+
+    /// Produce the UUID for an element with the given name
+    ///
+    /// Only `Derived` looks at the name; callers that may reuse a name must
+    /// make it unique, for instance by appending a counter.
+    pub fn generate(&mut self, name: &str) -> Uuid {
+        match self {
+            IdStrategy::Random => Uuid::new_v4(),
+            IdStrategy::Derived { namespace } => Uuid::new_v5(namespace, name.as_bytes()),
+            IdStrategy::Sequential { seed, next } => {
+                *next += 1;
+                Uuid::from_u64_pair(*seed, *next)
+            }
+        }
+    }
+}
+
 /// Base node types that can be extended
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub enum BaseNodeType {
     /// A simple value node
     Value,
@@ -108,7 +189,7 @@ impl fmt::Display for BaseNodeType {
 }
 
 /// Base relationship types that can be extended
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub enum BaseRelationshipType {
     /// Contains relationship (parent-child)
     Contains,
@@ -124,6 +205,8 @@ pub enum BaseRelationshipType {
     Choice,
     /// Hierarchy relationship (organizational)
     Hierarchy,
+    /// Loop relationship (back-edge to a loop header)
+    Loop,
     /// Custom relationship
     Custom(String),
 }
@@ -138,13 +221,107 @@ impl fmt::Display for BaseRelationshipType {
             BaseRelationshipType::Parallel => write!(f, "Parallel"),
             BaseRelationshipType::Choice => write!(f, "Choice"),
             BaseRelationshipType::Hierarchy => write!(f, "Hierarchy"),
+            BaseRelationshipType::Loop => write!(f, "Loop"),
             BaseRelationshipType::Custom(name) => write!(f, "Custom({name})"),
         }
     }
 }
 
+/// A node type that can stand at the root of a composition
+///
+/// The named constructors and the `parallel` and `choice` operators use these
+/// to create root nodes, so they work for any conforming node type. Only
+/// `composite_root` is required; the other roots default to it.
+pub trait CompositeRootType: Sized {
+    /// Root of a composite graph, and of the graphs `parallel` and `choice` build
+    fn composite_root() -> Self;
+
+    /// Root of an atomic graph holding a single value
+    fn value_root() -> Self {
+        Self::composite_root()
+    }
+
+    /// Root of an entity graph
+    fn entity_root() -> Self {
+        Self::composite_root()
+    }
+
+    /// Root of an aggregate graph
+    fn aggregate_root() -> Self {
+        Self::composite_root()
+    }
+}
+
+impl CompositeRootType for BaseNodeType {
+    fn composite_root() -> Self {
+        BaseNodeType::Aggregate
+    }
+
+    fn value_root() -> Self {
+        BaseNodeType::Value
+    }
+
+    fn entity_root() -> Self {
+        BaseNodeType::EntityReference
+    }
+
+    fn aggregate_root() -> Self {
+        BaseNodeType::Aggregate
+    }
+}
+
+/// A relationship type with the structural kinds the composition operators create
+///
+/// `then` links stages with `sequence`, `parallel` and `choice` link their new
+/// root to each branch, loops close with `loop_back`, and `remove_subgraph`
+/// follows `contains`.
+pub trait StructuralRelationship: PartialEq + Sized {
+    fn sequence() -> Self;
+    fn parallel() -> Self;
+    fn choice() -> Self;
+    fn contains() -> Self;
+
+    /// Back-edge from a loop body to its header; a sequence by default
+    fn loop_back() -> Self {
+        Self::sequence()
+    }
+
+    /// Whether this relationship nests its target inside its source
+    fn is_contains(&self) -> bool {
+        *self == Self::contains()
+    }
+}
+
+impl StructuralRelationship for BaseRelationshipType {
+    fn sequence() -> Self {
+        BaseRelationshipType::Sequence
+    }
+
+    fn parallel() -> Self {
+        BaseRelationshipType::Parallel
+    }
+
+    fn choice() -> Self {
+        BaseRelationshipType::Choice
+    }
+
+    fn contains() -> Self {
+        BaseRelationshipType::Contains
+    }
+
+    fn loop_back() -> Self {
+        BaseRelationshipType::Loop
+    }
+}
+
+/// Relationship metadata key marking the back-edge of an intentional loop
+///
+/// Cycle detection and topological ordering skip edges marked this way (see
+/// `Relationship::back_edge`).
+pub const BACK_EDGE_KEY: &str = "back_edge";
+
 /// A generic relationship between nodes within a graph
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Relationship<T = BaseRelationshipType> {
     pub relationship_type: T,
     pub metadata: HashMap<String, serde_json::Value>,
@@ -169,10 +346,20 @@ impl<T> Relationship<T> {
         self.metadata.insert(key, value);
         self
     }
+
+    /// Mark the relationship as the back-edge of an intentional loop
+    pub fn back_edge(self) -> Self {
+        self.with_metadata(BACK_EDGE_KEY.to_string(), serde_json::Value::Bool(true))
+    }
+
+    /// Whether the relationship closes an intentional loop
+    pub fn is_back_edge(&self) -> bool {
+        self.metadata.get(BACK_EDGE_KEY) == Some(&serde_json::Value::Bool(true))
+    }
 }
 
 /// Generic metadata that can be attached to graph elements
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, Default)]
 pub struct Metadata {
     pub name: String,
     pub description: Option<String>,
@@ -273,4 +460,25 @@ mod tests {
         assert_eq!(metadata.tags.len(), 2);
         assert_eq!(metadata.properties.get("priority"), Some(&serde_json::json!(5)));
     }
+
+    #[test]
+    fn test_id_strategies() {
+        let mut derived = IdStrategy::for_aggregate("Order", "order-1");
+        let mut again = IdStrategy::for_aggregate("Order", "order-1");
+        assert_eq!(derived.generate("total"), again.generate("total"));
+        assert_ne!(derived.generate("total"), derived.generate("status"));
+        assert_ne!(
+            IdStrategy::for_aggregate("Order", "order-2").generate("total"),
+            derived.generate("total")
+        );
+
+        let mut first = IdStrategy::sequential(7);
+        let mut second = IdStrategy::sequential(7);
+        let a = first.generate("x");
+        assert_eq!(a, second.generate("anything"));
+        assert_ne!(a, first.generate("x"));
+
+        let mut random = IdStrategy::Random;
+        assert_ne!(random.generate("x"), random.generate("x"));
+    }
 }
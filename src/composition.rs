@@ -5,9 +5,18 @@
 //! type-safe composition, and category theory-based transformations.
 
 use crate::base_types::*;
+use crate::guards::{
+    check_probabilities, Condition, Guard, BRANCH_KEY, GUARD_KEY, PROBABILITY_KEY,
+};
+use crate::interchange::{InterchangeDocument, SCHEMA_VERSION};
+use crate::invariants::{Invariant, InvariantRule, Violation};
+use crate::loops::{LoopKind, LOOP_KEY};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use std::collections::HashMap;
+use std::borrow::Borrow;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 
 /// Represents a composable graph structure that can be combined with other graphs
 pub trait Composable: Sized {
@@ -44,12 +53,51 @@ pub enum CompositionError {
     #[error("Node not found: {0}")]
     NodeNotFound(NodeId),
 
+    #[error("No node with label: {0}")]
+    LabelNotFound(String),
+
+    #[error("Label {0} is ambiguous: matches {1} nodes")]
+    AmbiguousLabel(String, usize),
+
+    #[error("Duplicate node ID: {0}")]
+    DuplicateNodeId(NodeId),
+
+    #[error("Duplicate edge ID: {0}")]
+    DuplicateEdgeId(EdgeId),
+
+    #[error("Conflicting data for node {0}")]
+    DataConflict(NodeId),
+
+    #[error("Duplicate label in unique-label graph: {0}")]
+    DuplicateLabel(String),
+
+    #[error("Edge {0} references missing node {1}")]
+    DanglingEdge(EdgeId, NodeId),
+
+    #[error("Node {0} still has {1} incident edges")]
+    NodeHasEdges(NodeId, usize),
+
+    #[error("Cannot remove composition root: {0}")]
+    RootRemoval(NodeId),
+
     #[error("Cycle detected in composition")]
     CycleDetected,
+
+    #[error("Edge {0} has an invalid weight: {1}")]
+    InvalidWeight(EdgeId, String),
+
+    #[error("Invalid choice probabilities: {0}")]
+    InvalidProbability(String),
+
+    #[error("Parse error: {0}")]
+    ParseError(String),
+
+    #[error("Unsupported interchange schema version: {0}")]
+    UnsupportedSchemaVersion(u64),
 }
 
 /// Types of graph composition
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum CompositionType {
     /// Single node, no edges - represents a value
     Atomic { value_type: String },
@@ -71,7 +119,7 @@ pub enum CompositionType {
 }
 
 /// Domain-specific composition types
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum DomainCompositionType {
     Entity { entity_type: String },
     ValueObject { value_type: String },
@@ -82,8 +130,42 @@ pub enum DomainCompositionType {
     BoundedContext { domain: String },
 }
 
+impl CompositionType {
+    /// Whether a graph of this type can absorb a graph of `other`'s type
+    ///
+    /// Composites absorb anything and anything absorbs a composite. Otherwise
+    /// the types must match, with these exceptions: a functor composes with
+    /// one whose source is its target, an aggregate absorbs entities and
+    /// value objects, and a bounded context absorbs any domain composition.
+    pub fn is_compatible_with(&self, other: &CompositionType) -> bool {
+        use DomainCompositionType as D;
+
+        match (self, other) {
+            (CompositionType::Composite { .. }, _) | (_, CompositionType::Composite { .. }) => true,
+            (
+                CompositionType::Functor {
+                    source_type,
+                    target_type,
+                },
+                CompositionType::Functor {
+                    source_type: other_source,
+                    target_type: other_target,
+                },
+            ) => {
+                (source_type == other_source && target_type == other_target)
+                    || target_type == other_source
+            }
+            (CompositionType::Domain(D::Aggregate { .. }), CompositionType::Domain(domain)) => {
+                matches!(domain, D::Entity { .. } | D::ValueObject { .. }) || self == other
+            }
+            (CompositionType::Domain(D::BoundedContext { .. }), CompositionType::Domain(_)) => true,
+            _ => self == other,
+        }
+    }
+}
+
 /// A node in the composition graph
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct CompositionNode<N = BaseNodeType> {
     pub id: NodeId,
     pub node_type: N,
@@ -130,7 +212,7 @@ impl CompositionNode<BaseNodeType> {
 }
 
 /// An edge in the composition graph
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct CompositionEdge<R = BaseRelationshipType> {
     pub id: EdgeId,
     pub source: NodeId,
@@ -149,8 +231,384 @@ impl<R> CompositionEdge<R> {
     }
 }
 
+/// Incoming and outgoing edge lists per node
+///
+/// Derived entirely from `GraphComposition::edges`, so it is never serialized
+/// and is rebuilt whenever a graph is deserialized.
+#[derive(Debug, Clone, Default)]
+struct AdjacencyIndex {
+    outgoing: HashMap<NodeId, Vec<EdgeId>>,
+    incoming: HashMap<NodeId, Vec<EdgeId>>,
+}
+
+impl AdjacencyIndex {
+    fn from_edges<R>(edges: &HashMap<EdgeId, CompositionEdge<R>>) -> Self {
+        let mut index = Self::default();
+        for edge in edges.values() {
+            index.insert(edge.id, edge.source, edge.target);
+        }
+        index
+    }
+
+    fn insert(&mut self, edge_id: EdgeId, source: NodeId, target: NodeId) {
+        self.outgoing.entry(source).or_default().push(edge_id);
+        self.incoming.entry(target).or_default().push(edge_id);
+    }
+
+    fn remove(&mut self, edge_id: EdgeId, source: NodeId, target: NodeId) {
+        if let Some(out) = self.outgoing.get_mut(&source) {
+            out.retain(|id| *id != edge_id);
+            if out.is_empty() {
+                self.outgoing.remove(&source);
+            }
+        }
+        if let Some(inc) = self.incoming.get_mut(&target) {
+            inc.retain(|id| *id != edge_id);
+            if inc.is_empty() {
+                self.incoming.remove(&target);
+            }
+        }
+    }
+
+    fn outgoing(&self, node_id: NodeId) -> &[EdgeId] {
+        self.outgoing.get(&node_id).map_or(&[], Vec::as_slice)
+    }
+
+    fn incoming(&self, node_id: NodeId) -> &[EdgeId] {
+        self.incoming.get(&node_id).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// How `remove_node` treats the edges attached to the removed node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeRemovalPolicy {
+    /// Remove incident edges together with the node
+    Cascade,
+    /// Keep incident edges; they dangle until removed or reconnected
+    Orphan,
+    /// Refuse to remove a node that still has incident edges
+    Refuse,
+}
+
+/// Nodes and edges taken out of a graph by a removal operation
+///
+/// Returned so callers can emit the corresponding domain events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemovedElements<N = BaseNodeType, R = BaseRelationshipType> {
+    pub nodes: Vec<CompositionNode<N>>,
+    pub edges: Vec<CompositionEdge<R>>,
+}
+
+impl<N, R> Default for RemovedElements<N, R> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+}
+
+impl<N, R> RemovedElements<N, R> {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.edges.is_empty()
+    }
+
+    fn extend(&mut self, other: RemovedElements<N, R>) {
+        self.nodes.extend(other.nodes);
+        self.edges.extend(other.edges);
+    }
+}
+
+/// How `compose_with` treats elements whose IDs occur in both graphs
+///
+/// Elements that are identical in both graphs are always shared; these
+/// strategies only govern real conflicts, where an ID is reused for
+/// different content. The default re-keys, so no element is lost.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ComposeStrategy {
+    /// Give the other graph's conflicting nodes and edges fresh IDs
+    #[default]
+    Rekey,
+    /// Treat nodes with the same ID as one node, combining their data
+    ///
+    /// The nodes must agree on type and label, or composing fails with
+    /// `IncompatibleTypes` or `DataConflict`; metadata is united, with `self`
+    /// winning. Conflicting edges are re-keyed.
+    MergeById(DataMerge),
+    /// Fail with `DuplicateNodeId` or `DuplicateEdgeId`
+    FailOnConflict,
+}
+
+/// How the data of two nodes merged into one is reconciled
+///
+/// Used by `ComposeStrategy::MergeById` and by `glue`.
+#[derive(Debug, Clone, Copy)]
+pub enum DataMerge {
+    /// Keep the data of `self`
+    KeepSelf,
+    /// Take the data of the other graph
+    KeepOther,
+    /// Merge JSON objects key by key, recursively; elsewhere the other graph wins
+    Deep,
+    /// Require both nodes to carry equal data
+    RequireEqual,
+    /// Combine `self`'s data with the other graph's using a function
+    With(fn(&JsonValue, &JsonValue) -> JsonValue),
+}
+
+impl DataMerge {
+    /// The reconciled data, or `None` if the two cannot be reconciled
+    pub fn merge(&self, ours: &JsonValue, theirs: &JsonValue) -> Option<JsonValue> {
+        match self {
+            DataMerge::KeepSelf => Some(ours.clone()),
+            DataMerge::KeepOther => Some(theirs.clone()),
+            DataMerge::Deep => Some(deep_merge(ours, theirs)),
+            DataMerge::RequireEqual => (ours == theirs).then(|| ours.clone()),
+            DataMerge::With(f) => Some(f(ours, theirs)),
+        }
+    }
+}
+
+/// Which nodes `glue` identifies between two graphs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Interface {
+    /// Pairs of (node in `self`, node in the other graph)
+    Ids(Vec<(NodeId, NodeId)>),
+    /// Pairs of labels or qualified label paths, resolved as in `node_by_label`
+    Labels(Vec<(String, String)>),
+    /// Every pair of nodes whose data carries the same [`ENTITY_ID_KEY`]
+    EntityIds,
+}
+
+impl Interface {
+    /// Identify the nodes carrying each of these labels in both graphs
+    pub fn shared_labels<I, S>(labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Interface::Labels(
+            labels
+                .into_iter()
+                .map(|label| {
+                    let label = label.into();
+                    (label.clone(), label)
+                })
+                .collect(),
+        )
+    }
+}
+
+fn deep_merge(ours: &JsonValue, theirs: &JsonValue) -> JsonValue {
+    match (ours, theirs) {
+        (JsonValue::Object(ours), JsonValue::Object(theirs)) => {
+            let mut merged = ours.clone();
+            for (key, value) in theirs {
+                let value = match ours.get(key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => value.clone(),
+                };
+                merged.insert(key.clone(), value);
+            }
+            JsonValue::Object(merged)
+        }
+        _ => theirs.clone(),
+    }
+}
+
+/// How the set operations (`union`, `intersection`, `difference`) decide
+/// that a node of one graph is a node of the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeIdentity {
+    /// The same node ID
+    Id,
+    /// The same qualified label path from the root (see `label_path`)
+    LabelPath,
+    /// The same `entity_id` field in the node data
+    EntityId,
+}
+
+/// Whether two elements serialize identically
+fn same_content<T: Serialize>(a: &T, b: &T) -> bool {
+    match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Default name of the port a graph is entered through
+pub const DEFAULT_INPUT_PORT: &str = "in";
+
+/// Default name of the port a graph is left through
+pub const DEFAULT_OUTPUT_PORT: &str = "out";
+
+/// Named input and output ports of a graph
+///
+/// A port names the nodes through which a graph is entered or left, so
+/// sequential composition can connect stages without guessing from leaves.
+/// A port may span several nodes.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Ports {
+    pub inputs: BTreeMap<String, Vec<NodeId>>,
+    pub outputs: BTreeMap<String, Vec<NodeId>>,
+}
+
+impl Ports {
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty() && self.outputs.is_empty()
+    }
+
+    fn add(ports: &mut BTreeMap<String, Vec<NodeId>>, name: &str, node_id: NodeId) {
+        let nodes = ports.entry(name.to_string()).or_default();
+        if !nodes.contains(&node_id) {
+            nodes.push(node_id);
+        }
+    }
+
+    /// Add every node of `other` to the port of the same name
+    fn merge(ports: &mut BTreeMap<String, Vec<NodeId>>, other: BTreeMap<String, Vec<NodeId>>) {
+        for (name, nodes) in other {
+            for node_id in nodes {
+                Self::add(ports, &name, node_id);
+            }
+        }
+    }
+
+    /// Drop a removed node from every port, and ports left empty
+    fn forget(&mut self, node_id: NodeId) {
+        self.retain(|id| id != node_id);
+    }
+
+    /// Keep only the nodes accepted by `keep`, dropping ports left empty
+    fn retain<F>(&mut self, keep: F)
+    where
+        F: Fn(NodeId) -> bool,
+    {
+        for ports in [&mut self.inputs, &mut self.outputs] {
+            for nodes in ports.values_mut() {
+                nodes.retain(|id| keep(*id));
+            }
+            ports.retain(|_, nodes| !nodes.is_empty());
+        }
+    }
+
+    /// Replace node IDs after re-keying
+    fn rekey(&mut self, ids: &HashMap<NodeId, NodeId>) {
+        for ports in [&mut self.inputs, &mut self.outputs] {
+            Self::rekey_nodes(ports, ids);
+        }
+    }
+
+    /// Replace node IDs in one side's ports after re-keying
+    fn rekey_nodes(ports: &mut BTreeMap<String, Vec<NodeId>>, ids: &HashMap<NodeId, NodeId>) {
+        for nodes in ports.values_mut() {
+            for node_id in nodes.iter_mut() {
+                *node_id = ids.get(node_id).copied().unwrap_or(*node_id);
+            }
+        }
+    }
+}
+
+/// Node data field holding the ID of the entity an `EntityReference` points to
+pub const ENTITY_ID_KEY: &str = "entity_id";
+
+/// Node metadata key naming the graph a root node was created for
+///
+/// Set by the named constructors (`atomic`, `composite`, `entity`, `aggregate`)
+/// so qualified label paths such as `"Order/total"` can address a subgraph
+/// after it has been composed into a larger graph.
+pub const GRAPH_NAME_KEY: &str = "graph_name";
+
+/// Node metadata key marking the root a fork was created with
+///
+/// Set by `parallel`, `choice` and their n-ary forms to `"Parallel"` or
+/// `"Choice"`, so fork roots are told apart from user graphs that happen to
+/// share the name.
+pub const FORK_KEY: &str = "fork";
+
+/// The kind of fork a node is the root of, if any (see [`FORK_KEY`])
+pub(crate) fn fork_kind<N>(node: &CompositionNode<N>) -> Option<&str> {
+    node.metadata.get(FORK_KEY).and_then(JsonValue::as_str)
+}
+
+/// Metadata property recording the graph a subgraph was extracted from
+///
+/// See [`GraphComposition::parent_graph`].
+pub const PARENT_GRAPH_KEY: &str = "parent_graph";
+
+/// Separator between segments of a qualified label path
+pub const LABEL_PATH_SEPARATOR: char = '/';
+
+/// Node IDs by label, and by graph name for composition roots
+#[derive(Debug, Clone, Default)]
+struct LabelIndex {
+    labels: HashMap<String, Vec<NodeId>>,
+    graph_names: HashMap<String, Vec<NodeId>>,
+}
+
+impl LabelIndex {
+    fn from_nodes<N>(nodes: &HashMap<NodeId, CompositionNode<N>>) -> Self {
+        let mut index = Self::default();
+        for node in nodes.values() {
+            index.insert(node);
+        }
+        index
+    }
+
+    fn insert<N>(&mut self, node: &CompositionNode<N>) {
+        self.labels
+            .entry(node.label.clone())
+            .or_default()
+            .push(node.id);
+        if let Some(name) = Self::graph_name(node) {
+            self.graph_names
+                .entry(name.to_string())
+                .or_default()
+                .push(node.id);
+        }
+    }
+
+    fn remove<N>(&mut self, node: &CompositionNode<N>) {
+        Self::remove_from(&mut self.labels, &node.label, node.id);
+        if let Some(name) = Self::graph_name(node) {
+            Self::remove_from(&mut self.graph_names, name, node.id);
+        }
+    }
+
+    fn remove_from(map: &mut HashMap<String, Vec<NodeId>>, key: &str, node_id: NodeId) {
+        if let Some(ids) = map.get_mut(key) {
+            ids.retain(|id| *id != node_id);
+            if ids.is_empty() {
+                map.remove(key);
+            }
+        }
+    }
+
+    fn graph_name<N>(node: &CompositionNode<N>) -> Option<&str> {
+        node.metadata
+            .get(GRAPH_NAME_KEY)
+            .and_then(JsonValue::as_str)
+    }
+
+    fn by_label(&self, label: &str) -> &[NodeId] {
+        self.labels.get(label).map_or(&[], Vec::as_slice)
+    }
+
+    fn by_graph_name(&self, name: &str) -> &[NodeId] {
+        self.graph_names.get(name).map_or(&[], Vec::as_slice)
+    }
+}
+
 /// The main GraphComposition structure
+///
+/// `nodes` and `edges` are public for reading; code that inserts or removes
+/// edges directly instead of going through `add_edge` and the composition
+/// operators must call [`GraphComposition::rebuild_indexes`] afterwards.
 #[derive(Serialize, Deserialize)]
+#[serde(
+    from = "GraphCompositionData<N, R>",
+    bound(deserialize = "N: Deserialize<'de>, R: Deserialize<'de>")
+)]
 pub struct GraphComposition<N = BaseNodeType, R = BaseRelationshipType> {
     pub id: GraphId,
     pub composition_root: NodeId,
@@ -158,8 +616,68 @@ pub struct GraphComposition<N = BaseNodeType, R = BaseRelationshipType> {
     pub nodes: HashMap<NodeId, CompositionNode<N>>,
     pub edges: HashMap<EdgeId, CompositionEdge<R>>,
     pub metadata: Metadata,
+    /// Reject duplicate labels on checked insertion (see `with_unique_labels`)
+    #[serde(default)]
+    unique_labels: bool,
+    /// Declarative invariants (see `with_invariant_rule`)
+    #[serde(default)]
+    invariants: Vec<Invariant>,
+    /// How IDs are generated for added elements (see `with_id_strategy`)
+    #[serde(default)]
+    id_strategy: IdStrategy,
+    /// Named entry and exit nodes used by `then`, `parallel` and `choice`
+    #[serde(default, skip_serializing_if = "Ports::is_empty")]
+    ports: Ports,
     #[serde(skip)]
-    invariants: Vec<Box<dyn Fn(&GraphComposition<N, R>) -> bool>>,
+    adjacency: AdjacencyIndex,
+    #[serde(skip)]
+    labels: LabelIndex,
+    #[serde(skip)]
+    constraints: Vec<Constraint<N, R>>,
+}
+
+/// An invariant given as a closure; cannot be cloned or serialized
+type Constraint<N, R> = Box<dyn Fn(&GraphComposition<N, R>) -> bool>;
+
+/// Serialized form of a GraphComposition, used to rebuild indexes on load
+#[derive(Deserialize)]
+struct GraphCompositionData<N, R> {
+    id: GraphId,
+    composition_root: NodeId,
+    composition_type: CompositionType,
+    nodes: HashMap<NodeId, CompositionNode<N>>,
+    edges: HashMap<EdgeId, CompositionEdge<R>>,
+    metadata: Metadata,
+    #[serde(default)]
+    unique_labels: bool,
+    #[serde(default)]
+    invariants: Vec<Invariant>,
+    #[serde(default)]
+    id_strategy: IdStrategy,
+    #[serde(default)]
+    ports: Ports,
+}
+
+impl<N, R> From<GraphCompositionData<N, R>> for GraphComposition<N, R> {
+    fn from(data: GraphCompositionData<N, R>) -> Self {
+        let adjacency = AdjacencyIndex::from_edges(&data.edges);
+        let labels = LabelIndex::from_nodes(&data.nodes);
+        Self {
+            id: data.id,
+            composition_root: data.composition_root,
+            composition_type: data.composition_type,
+            nodes: data.nodes,
+            edges: data.edges,
+            metadata: data.metadata,
+            unique_labels: data.unique_labels,
+            invariants: data.invariants,
+            id_strategy: data.id_strategy,
+            ports: data.ports,
+            adjacency,
+            labels,
+            constraints: Vec::new(),
+        }
+    }
 }
 
 impl<N, R> Clone for GraphComposition<N, R>
@@ -175,7 +693,13 @@ where
             nodes: self.nodes.clone(),
             edges: self.edges.clone(),
             metadata: self.metadata.clone(),
-            invariants: Vec::new(), // Invariants cannot be cloned
+            unique_labels: self.unique_labels,
+            invariants: self.invariants.clone(),
+            id_strategy: self.id_strategy.clone(),
+            ports: self.ports.clone(),
+            adjacency: self.adjacency.clone(),
+            labels: self.labels.clone(),
+            constraints: Vec::new(), // Closures cannot be cloned
         }
     }
 }
@@ -193,7 +717,11 @@ where
             .field("nodes", &self.nodes)
             .field("edges", &self.edges)
             .field("metadata", &self.metadata)
-            .field("invariants", &format!("<{} invariants>", self.invariants.len()))
+            .field("invariants", &self.invariants)
+            .field(
+                "constraints",
+                &format!("<{} constraints>", self.constraints.len()),
+            )
             .finish()
     }
 }
@@ -210,7 +738,7 @@ where
             && self.nodes == other.nodes
             && self.edges == other.edges
             && self.metadata == other.metadata
-        // Note: invariants are not compared
+        // Note: invariants and constraints are not compared
     }
 }
 
@@ -230,6 +758,7 @@ where
 
         let mut nodes = HashMap::new();
         nodes.insert(root_id, root_node);
+        let labels = LabelIndex::from_nodes(&nodes);
 
         Self {
             id: GraphId::new(),
@@ -238,14 +767,303 @@ where
             nodes,
             edges: HashMap::new(),
             metadata: Metadata::default(),
+            unique_labels: false,
             invariants: Vec::new(),
+            id_strategy: IdStrategy::default(),
+            ports: Ports::default(),
+            adjacency: AdjacencyIndex::default(),
+            labels,
+            constraints: Vec::new(),
+        }
+    }
+
+    /// Insert a node, replacing any node with the same ID
+    fn insert_node(&mut self, node: CompositionNode<N>) {
+        if let Some(previous) = self.nodes.remove(&node.id) {
+            self.labels.remove(&previous);
+        }
+        self.labels.insert(&node);
+        self.nodes.insert(node.id, node);
+    }
+
+    /// Replace the root node's label and data
+    fn set_root(&mut self, label: &str, data: JsonValue) {
+        if let Some(mut root) = self.nodes.remove(&self.composition_root) {
+            self.labels.remove(&root);
+            root.label = label.to_string();
+            root.data = data;
+            self.insert_node(root);
+        }
+    }
+
+    /// Name the graph and tag its root so qualified label paths can find it
+    fn set_graph_name(&mut self, name: &str) {
+        self.metadata.name = name.to_string();
+        if let Some(mut root) = self.nodes.remove(&self.composition_root) {
+            self.labels.remove(&root);
+            root.metadata.insert(
+                GRAPH_NAME_KEY.to_string(),
+                JsonValue::String(name.to_string()),
+            );
+            self.insert_node(root);
+        }
+    }
+
+    /// Insert an edge and keep the adjacency index in sync
+    fn insert_edge(&mut self, edge: CompositionEdge<R>) {
+        if let Some(previous) = self.edges.remove(&edge.id) {
+            self.adjacency
+                .remove(previous.id, previous.source, previous.target);
+        }
+        self.adjacency.insert(edge.id, edge.source, edge.target);
+        self.edges.insert(edge.id, edge);
+    }
+
+    /// Recompute the adjacency and label indexes from `nodes` and `edges`
+    ///
+    /// Only needed after mutating the public maps directly, or after changing
+    /// a label through `node_by_label_mut`.
+    pub fn rebuild_indexes(&mut self) {
+        self.adjacency = AdjacencyIndex::from_edges(&self.edges);
+        self.labels = LabelIndex::from_nodes(&self.nodes);
+    }
+
+    /// Require labels to be unique
+    ///
+    /// Fails if the graph already has duplicate labels. Afterwards the checked
+    /// `try_add_node` methods reject a label that is already taken; the
+    /// unchecked `add_node` still inserts it, but label lookups then report
+    /// the ambiguity instead of picking a node.
+    pub fn with_unique_labels(mut self) -> Result<Self, CompositionError> {
+        if let Some((label, ids)) = self.labels.labels.iter().find(|(_, ids)| ids.len() > 1) {
+            return Err(CompositionError::AmbiguousLabel(label.clone(), ids.len()));
+        }
+        self.unique_labels = true;
+        Ok(self)
+    }
+
+    /// Whether this graph is in unique-label mode
+    pub fn has_unique_labels(&self) -> bool {
+        self.unique_labels
+    }
+
+    /// Require the graph to stay acyclic
+    ///
+    /// Fails if the graph already has a cycle. Otherwise adds
+    /// [`Invariant::acyclic`], after which `try_add_edge` and `then` refuse to
+    /// close a cycle and `check_invariants` reports one; the unchecked
+    /// `add_edge` still inserts the edge.
+    pub fn with_acyclicity(self) -> Result<Self, CompositionError> {
+        if self.has_cycle() {
+            return Err(CompositionError::CycleDetected);
+        }
+        if self.is_acyclic_enforced() {
+            return Ok(self);
+        }
+        Ok(self.with_invariant_rule(Invariant::acyclic()))
+    }
+
+    /// Whether this graph must stay acyclic
+    pub fn is_acyclic_enforced(&self) -> bool {
+        self.invariants
+            .iter()
+            .any(|invariant| invariant.rule == InvariantRule::Acyclic)
+    }
+
+    /// Generate IDs with the given strategy
+    ///
+    /// Existing elements are re-keyed in a canonical order (root first, then
+    /// by label path, label and data), so two graphs built the same way end
+    /// up with the same graph, node and edge IDs. Nodes and edges added later
+    /// draw their IDs from the strategy too; a node's name is its qualified
+    /// label path (see `id_paths`), an edge's its endpoints and relationship.
+    pub fn with_id_strategy(mut self, strategy: IdStrategy) -> Self {
+        self.id_strategy = strategy;
+        if self.id_strategy.is_random() {
+            return self;
+        }
+
+        self.id = GraphId::from_uuid(self.id_strategy.generate("graph"));
+
+        let root = self.composition_root;
+        let paths = self.id_paths();
+        let mut old_nodes: Vec<CompositionNode<N>> = self.nodes.drain().map(|(_, n)| n).collect();
+        old_nodes.sort_by_cached_key(|node| {
+            (
+                node.id != root,
+                paths[&node.id].clone(),
+                node.label.clone(),
+                node.data.to_string(),
+            )
+        });
+
+        let mut ids = HashMap::new();
+        for mut node in old_nodes {
+            let new_id = self.next_node_id(&paths[&node.id]);
+            ids.insert(node.id, new_id);
+            node.id = new_id;
+            self.nodes.insert(new_id, node);
+        }
+        let rekey = |id: NodeId| ids.get(&id).copied().unwrap_or(id);
+        self.composition_root = rekey(self.composition_root);
+        self.ports.rekey(&ids);
+
+        let mut old_edges: Vec<(String, CompositionEdge<R>)> = self
+            .edges
+            .drain()
+            .map(|(_, mut edge)| {
+                edge.source = rekey(edge.source);
+                edge.target = rekey(edge.target);
+                (
+                    Self::edge_name(
+                        &edge.source,
+                        &edge.target,
+                        &edge.relationship.relationship_type,
+                    ),
+                    edge,
+                )
+            })
+            .collect();
+        old_edges.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, mut edge) in old_edges {
+            edge.id = self.next_edge_id(&name);
+            self.edges.insert(edge.id, edge);
+        }
+
+        self.rebuild_indexes();
+        self
+    }
+
+    /// The strategy used to generate IDs for added elements
+    pub fn id_strategy(&self) -> &IdStrategy {
+        &self.id_strategy
+    }
+
+    /// Declare `node_id` as (part of) the named input port
+    pub fn with_input_port(
+        mut self,
+        name: &str,
+        node_id: NodeId,
+    ) -> Result<Self, CompositionError> {
+        if !self.nodes.contains_key(&node_id) {
+            return Err(CompositionError::NodeNotFound(node_id));
+        }
+        Ports::add(&mut self.ports.inputs, name, node_id);
+        Ok(self)
+    }
+
+    /// Declare `node_id` as (part of) the named output port
+    pub fn with_output_port(
+        mut self,
+        name: &str,
+        node_id: NodeId,
+    ) -> Result<Self, CompositionError> {
+        if !self.nodes.contains_key(&node_id) {
+            return Err(CompositionError::NodeNotFound(node_id));
+        }
+        Ports::add(&mut self.ports.outputs, name, node_id);
+        Ok(self)
+    }
+
+    /// Declare the node with the given label as (part of) the named input port
+    pub fn with_input_port_by_label(
+        self,
+        name: &str,
+        label: &str,
+    ) -> Result<Self, CompositionError> {
+        let node_id = self.resolve_label(label)?;
+        self.with_input_port(name, node_id)
+    }
+
+    /// Declare the node with the given label as (part of) the named output port
+    pub fn with_output_port_by_label(
+        self,
+        name: &str,
+        label: &str,
+    ) -> Result<Self, CompositionError> {
+        let node_id = self.resolve_label(label)?;
+        self.with_output_port(name, node_id)
+    }
+
+    /// The declared input and output ports
+    pub fn ports(&self) -> &Ports {
+        &self.ports
+    }
+
+    /// The input ports, or the root as `DEFAULT_INPUT_PORT` if none are declared
+    pub fn entry_ports(&self) -> BTreeMap<String, Vec<NodeId>> {
+        if self.ports.inputs.is_empty() {
+            BTreeMap::from([(DEFAULT_INPUT_PORT.to_string(), vec![self.composition_root])])
+        } else {
+            self.ports.inputs.clone()
+        }
+    }
+
+    /// The output ports, or the leaves as `DEFAULT_OUTPUT_PORT` if none are declared
+    pub fn exit_ports(&self) -> BTreeMap<String, Vec<NodeId>> {
+        if self.ports.outputs.is_empty() {
+            let mut leaves = self.find_leaves();
+            leaves.sort();
+            BTreeMap::from([(DEFAULT_OUTPUT_PORT.to_string(), leaves)])
+        } else {
+            self.ports.outputs.clone()
+        }
+    }
+
+    /// Draw an unused node ID for a node with the given qualified label path
+    ///
+    /// Nodes sharing a path are told apart by a `#n` suffix, so a node's ID
+    /// does not change when its data does.
+    fn next_node_id(&mut self, path: &str) -> NodeId {
+        let name = format!("node:{path}");
+        let mut id = NodeId::from_uuid(self.id_strategy.generate(&name));
+        let mut attempt = 1;
+        while self.nodes.contains_key(&id) {
+            id = NodeId::from_uuid(self.id_strategy.generate(&format!("{name}#{attempt}")));
+            attempt += 1;
+        }
+        id
+    }
+
+    /// Draw an unused edge ID for an edge with the given name
+    fn next_edge_id(&mut self, name: &str) -> EdgeId {
+        let mut id = EdgeId::from_uuid(self.id_strategy.generate(name));
+        let mut attempt = 1;
+        while self.edges.contains_key(&id) {
+            id = EdgeId::from_uuid(self.id_strategy.generate(&format!("{name}#{attempt}")));
+            attempt += 1;
+        }
+        id
+    }
+
+    /// Name an edge by its endpoints and relationship, for ID derivation
+    fn edge_name(source: &NodeId, target: &NodeId, relationship: &R) -> String {
+        let relationship = serde_json::to_string(relationship).unwrap_or_default();
+        format!("edge:{source}:{target}:{relationship}")
+    }
+
+    /// Give the root a fresh ID derived from `name`
+    ///
+    /// Only used on a graph whose root has no edges yet.
+    fn derive_root_id(&mut self, name: &str) {
+        if self.id_strategy.is_random() {
+            return;
+        }
+        if let Some(mut root) = self.nodes.remove(&self.composition_root) {
+            self.labels.remove(&root);
+            root.id = self.next_node_id(name);
+            self.composition_root = root.id;
+            self.insert_node(root);
         }
     }
 
     /// Add a node to the graph
     pub fn add_node(mut self, node_type: N, label: &str, data: impl Into<JsonValue>) -> Self {
-        let node = CompositionNode::new(node_type, label.to_string(), data.into());
-        self.nodes.insert(node.id, node);
+        let mut node = CompositionNode::new(node_type, label.to_string(), data.into());
+        if !self.id_strategy.is_random() {
+            node.id = self.next_node_id(&self.child_path(label));
+        }
+        self.insert_node(node);
         self
     }
 
@@ -259,540 +1077,4106 @@ where
     ) -> Self {
         let mut node = CompositionNode::new(node_type, label.to_string(), data.into());
         node.id = id;
-        self.nodes.insert(id, node);
+        self.insert_node(node);
         self
     }
 
     /// Add an edge between nodes
-    pub fn add_edge(mut self, source: NodeId, target: NodeId, relationship: R) -> Self {
-        let edge = CompositionEdge::new(source, target, relationship);
-        self.edges.insert(edge.id, edge);
+    pub fn add_edge(self, source: NodeId, target: NodeId, relationship: R) -> Self {
+        self.add_edge_with_relationship(source, target, Relationship::new(relationship))
+    }
+
+    /// Add an edge carrying a full relationship, with metadata such as a weight
+    pub fn add_edge_with_relationship(
+        mut self,
+        source: NodeId,
+        target: NodeId,
+        relationship: Relationship<R>,
+    ) -> Self {
+        self.connect(source, target, relationship);
         self
     }
 
+    /// Insert a new edge with an ID drawn from the ID strategy
+    fn connect(&mut self, source: NodeId, target: NodeId, relationship: Relationship<R>) -> EdgeId {
+        let id = self.fresh_edge_id(source, target, &relationship.relationship_type);
+        self.insert_edge(CompositionEdge {
+            id,
+            source,
+            target,
+            relationship,
+        });
+        id
+    }
+
     /// Add an edge by node labels
+    ///
+    /// Labels may be qualified paths (see [`GraphComposition::node_by_label`]).
+    /// If either label is missing or ambiguous the graph is returned unchanged;
+    /// use `try_add_edge_by_label` to find out why.
     pub fn add_edge_by_label(
         self,
         source_label: &str,
         target_label: &str,
         relationship: R,
     ) -> Self {
-        let source_id = if source_label == "root" {
-            Some(self.composition_root)
-        } else {
-            self.nodes
-                .values()
-                .find(|n| n.label == source_label)
-                .map(|n| n.id)
-        };
-
-        let target_id = if target_label == "root" {
-            Some(self.composition_root)
-        } else {
-            self.nodes
-                .values()
-                .find(|n| n.label == target_label)
-                .map(|n| n.id)
-        };
+        let source_id = self.resolve_label(source_label);
+        let target_id = self.resolve_label(target_label);
 
-        if let (Some(source), Some(target)) = (source_id, target_id) {
+        if let (Ok(source), Ok(target)) = (source_id, target_id) {
             self.add_edge(source, target, relationship)
         } else {
             self
         }
     }
 
-    /// Add an invariant constraint
-    pub fn with_invariant<F>(mut self, invariant: F) -> Self
-    where
-        F: Fn(&GraphComposition<N, R>) -> bool + 'static,
-    {
-        self.invariants.push(Box::new(invariant));
-        self
+    /// Add a node, failing if the graph rejects it
+    pub fn try_add_node(
+        mut self,
+        node_type: N,
+        label: &str,
+        data: impl Into<JsonValue>,
+    ) -> Result<Self, CompositionError> {
+        let id = self.fresh_node_id(&self.child_path(label));
+        self.try_add_node_with_id(id, node_type, label, data)
     }
 
-    /// Check if all invariants hold
-    pub fn check_invariants(&self) -> Result<(), CompositionError> {
-        for (i, invariant) in self.invariants.iter().enumerate() {
-            if !invariant(self) {
-                return Err(CompositionError::InvariantViolation(format!(
-                    "Invariant {i} failed"
-                )));
+    /// Add a node with a specific ID, failing if the ID is already taken
+    pub fn try_add_node_with_id(
+        self,
+        id: NodeId,
+        node_type: N,
+        label: &str,
+        data: impl Into<JsonValue>,
+    ) -> Result<Self, CompositionError> {
+        if self.nodes.contains_key(&id) {
+            return Err(CompositionError::DuplicateNodeId(id));
+        }
+        if self.unique_labels && !self.labels.by_label(label).is_empty() {
+            return Err(CompositionError::DuplicateLabel(label.to_string()));
+        }
+        Ok(self.add_node_with_id(id, node_type, label, data))
+    }
+
+    /// Add an edge, failing if either endpoint is not in the graph
+    pub fn try_add_edge(
+        self,
+        source: NodeId,
+        target: NodeId,
+        relationship: R,
+    ) -> Result<Self, CompositionError> {
+        for node_id in [source, target] {
+            if !self.nodes.contains_key(&node_id) {
+                return Err(CompositionError::NodeNotFound(node_id));
             }
         }
-        Ok(())
+        if self.is_acyclic_enforced() && self.reaches_where(target, source, |_| true) {
+            return Err(CompositionError::CycleDetected);
+        }
+        Ok(self.add_edge(source, target, relationship))
     }
 
-    /// Find leaf nodes (nodes with no outgoing edges)
-    pub fn find_leaves(&self) -> Vec<NodeId> {
-        let mut leaves = Vec::new();
+    /// Add an edge by node labels, failing if a label is missing or ambiguous
+    pub fn try_add_edge_by_label(
+        self,
+        source_label: &str,
+        target_label: &str,
+        relationship: R,
+    ) -> Result<Self, CompositionError> {
+        let source = self.resolve_label(source_label)?;
+        let target = self.resolve_label(target_label)?;
+        self.try_add_edge(source, target, relationship)
+    }
 
-        for node_id in self.nodes.keys() {
-            let has_outgoing = self.edges.values().any(|e| e.source == *node_id);
-            if !has_outgoing {
-                leaves.push(*node_id);
-            }
+    /// Look up the single node with the given label
+    ///
+    /// `"root"` always means the composition root. A label containing `/` that
+    /// does not match a node directly is read as a qualified path: the first
+    /// segment names a node by label or by the graph it was created for (see
+    /// [`GRAPH_NAME_KEY`]), and each further segment names a direct successor
+    /// of the previous one, so `"Order/total"` is the `total` node hanging off
+    /// the `Order` root. Fails rather than guessing when several nodes match.
+    pub fn node_by_label(&self, label: &str) -> Result<&CompositionNode<N>, CompositionError> {
+        let node_id = self.resolve_label(label)?;
+        self.nodes
+            .get(&node_id)
+            .ok_or(CompositionError::NodeNotFound(node_id))
+    }
+
+    /// Mutable variant of [`GraphComposition::node_by_label`]
+    ///
+    /// Changing the node's label through this reference leaves the label index
+    /// stale until [`GraphComposition::rebuild_indexes`] is called.
+    pub fn node_by_label_mut(
+        &mut self,
+        label: &str,
+    ) -> Result<&mut CompositionNode<N>, CompositionError> {
+        let node_id = self.resolve_label(label)?;
+        self.nodes
+            .get_mut(&node_id)
+            .ok_or(CompositionError::NodeNotFound(node_id))
+    }
+
+    /// Resolve a label or qualified path to exactly one node ID
+    fn resolve_label(&self, label: &str) -> Result<NodeId, CompositionError> {
+        if label == "root" {
+            return Ok(self.composition_root);
         }
 
-        leaves
+        match self.labels.by_label(label) {
+            [node_id] => Ok(*node_id),
+            [] if label.contains(LABEL_PATH_SEPARATOR) => self.resolve_path(label),
+            [] => Err(CompositionError::LabelNotFound(label.to_string())),
+            ids => Err(CompositionError::AmbiguousLabel(
+                label.to_string(),
+                ids.len(),
+            )),
+        }
     }
 
-    /// Find root nodes (nodes with no incoming edges)
-    pub fn find_roots(&self) -> Vec<NodeId> {
-        let mut roots = Vec::new();
+    /// Resolve a qualified label path such as `"Order/total"`
+    fn resolve_path(&self, path: &str) -> Result<NodeId, CompositionError> {
+        let mut segments = path.split(LABEL_PATH_SEPARATOR);
+        let first = segments.next().unwrap_or_default();
 
-        for node_id in self.nodes.keys() {
-            let has_incoming = self.edges.values().any(|e| e.target == *node_id);
-            if !has_incoming {
-                roots.push(*node_id);
-            }
+        let mut candidates: HashSet<NodeId> = if first == "root" {
+            HashSet::from([self.composition_root])
+        } else {
+            self.labels
+                .by_label(first)
+                .iter()
+                .chain(self.labels.by_graph_name(first))
+                .copied()
+                .collect()
+        };
+
+        for segment in segments {
+            candidates = candidates
+                .iter()
+                .flat_map(|node_id| self.out_edges(*node_id))
+                .map(|edge| edge.target)
+                .filter(|target| self.nodes.get(target).is_some_and(|n| n.label == segment))
+                .collect();
         }
 
-        roots
+        let candidates: Vec<NodeId> = candidates.into_iter().collect();
+        match candidates.as_slice() {
+            [node_id] => Ok(*node_id),
+            [] => Err(CompositionError::LabelNotFound(path.to_string())),
+            ids => Err(CompositionError::AmbiguousLabel(
+                path.to_string(),
+                ids.len(),
+            )),
+        }
     }
 
-    /// Get all nodes connected to a given node
-    pub fn get_connected_nodes(&self, node_id: NodeId) -> Vec<NodeId> {
-        let mut connected = Vec::new();
+    /// Check that the root exists and every edge connects existing nodes
+    ///
+    /// Unlike the `try_` methods this reports every problem, not just the first.
+    pub fn validate_structure(&self) -> Result<(), Vec<CompositionError>> {
+        let mut errors = Vec::new();
+
+        if !self.nodes.contains_key(&self.composition_root) {
+            errors.push(CompositionError::NodeNotFound(self.composition_root));
+        }
 
-        // Outgoing connections
         for edge in self.edges.values() {
-            if edge.source == node_id {
-                connected.push(edge.target);
-            }
-            if edge.relationship.bidirectional && edge.target == node_id {
-                connected.push(edge.source);
+            for node_id in [edge.source, edge.target] {
+                if !self.nodes.contains_key(&node_id) {
+                    errors.push(CompositionError::DanglingEdge(edge.id, node_id));
+                }
             }
         }
 
-        connected
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 
-    /// Map a function over all nodes
-    pub fn map_nodes<F, N2>(self, f: F) -> GraphComposition<N2, R>
-    where
-        F: Fn(&CompositionNode<N>) -> CompositionNode<N2>,
-        N2: Clone + Serialize + for<'de> Deserialize<'de>,
-    {
-        let mut new_nodes = HashMap::new();
-        for (id, node) in self.nodes {
-            let new_node = f(&node);
-            new_nodes.insert(id, new_node);
-        }
+    /// The graph as an interchange document, with nodes and edges sorted by ID
+    ///
+    /// Closure invariants added with `with_invariant` are not included.
+    pub fn to_interchange(&self) -> InterchangeDocument<N, R> {
+        let mut nodes: Vec<CompositionNode<N>> = self.nodes.values().cloned().collect();
+        nodes.sort_by_key(|node| node.id);
+        let mut edges: Vec<CompositionEdge<R>> = self.edges.values().cloned().collect();
+        edges.sort_by_key(|edge| edge.id);
 
-        GraphComposition {
+        InterchangeDocument {
+            schema_version: SCHEMA_VERSION,
             id: self.id,
             composition_root: self.composition_root,
-            composition_type: self.composition_type,
-            nodes: new_nodes,
-            edges: self.edges,
-            metadata: self.metadata,
-            invariants: Vec::new(), // Invariants don't transfer across type changes
+            composition_type: self.composition_type.clone(),
+            metadata: self.metadata.clone(),
+            nodes,
+            edges,
+            invariants: self.invariants.clone(),
+            ports: self.ports.clone(),
+            unique_labels: self.unique_labels,
+            id_strategy: self.id_strategy.clone(),
         }
     }
 
-    /// Fold the graph to a value
-    pub fn fold<T, F>(&self, init: T, f: F) -> T
-    where
-        F: Fn(T, &CompositionNode<N>) -> T,
-    {
-        self.nodes.values().fold(init, f)
-    }
-}
+    /// Build a graph from an interchange document of the current schema version
+    ///
+    /// Fails on duplicate IDs, a missing root or dangling edges. Documents of
+    /// older versions are read with `from_interchange_json`, which migrates
+    /// them first.
+    pub fn from_interchange(document: InterchangeDocument<N, R>) -> Result<Self, CompositionError> {
+        if document.schema_version != SCHEMA_VERSION {
+            return Err(CompositionError::UnsupportedSchemaVersion(
+                document.schema_version.into(),
+            ));
+        }
 
-// Specialized constructors for BaseNodeType
-impl GraphComposition<BaseNodeType, BaseRelationshipType> {
-    /// Create an atomic graph (single node, no edges)
-    pub fn atomic(value_type: &str, data: JsonValue) -> Self {
-        let mut graph = Self::new(
-            BaseNodeType::Value,
-            CompositionType::Atomic {
-                value_type: value_type.to_string(),
-            },
-        );
-        graph.metadata.name = value_type.to_string();
+        let mut nodes = HashMap::new();
+        for node in document.nodes {
+            if let Some(duplicate) = nodes.insert(node.id, node) {
+                return Err(CompositionError::DuplicateNodeId(duplicate.id));
+            }
+        }
+        let mut edges = HashMap::new();
+        for edge in document.edges {
+            if let Some(duplicate) = edges.insert(edge.id, edge) {
+                return Err(CompositionError::DuplicateEdgeId(duplicate.id));
+            }
+        }
 
-        // Update root node with data
-        if let Some(root) = graph.nodes.get_mut(&graph.composition_root) {
-            root.data = data;
-            root.label = value_type.to_string();
+        let graph: Self = GraphCompositionData {
+            id: document.id,
+            composition_root: document.composition_root,
+            composition_type: document.composition_type,
+            nodes,
+            edges,
+            metadata: document.metadata,
+            unique_labels: document.unique_labels,
+            invariants: document.invariants,
+            id_strategy: document.id_strategy,
+            ports: document.ports,
+        }
+        .into();
+        if let Err(mut errors) = graph.validate_structure() {
+            return Err(errors.swap_remove(0));
         }
+        Ok(graph)
+    }
 
-        graph
+    /// Make another existing node the composition root
+    pub fn set_composition_root(&mut self, node_id: NodeId) -> Result<(), CompositionError> {
+        if !self.nodes.contains_key(&node_id) {
+            return Err(CompositionError::NodeNotFound(node_id));
+        }
+        self.composition_root = node_id;
+        Ok(())
     }
 
-    /// Create a composite graph
-    pub fn composite(structure_type: &str) -> Self {
-        let mut graph = Self::new(
-            BaseNodeType::Aggregate,
-            CompositionType::Composite {
-                structure_type: structure_type.to_string(),
-            },
-        );
-        graph.metadata.name = structure_type.to_string();
-        graph
+    /// Remove an edge
+    pub fn remove_edge(&mut self, edge_id: EdgeId) -> Option<CompositionEdge<R>> {
+        let edge = self.edges.remove(&edge_id)?;
+        self.adjacency.remove(edge.id, edge.source, edge.target);
+        Some(edge)
     }
 
-    /// Create an entity graph
-    pub fn entity(entity_type: &str, entity_id: impl Into<String>) -> Self {
-        let mut graph = Self::new(
-            BaseNodeType::EntityReference,
-            CompositionType::Domain(DomainCompositionType::Entity {
-                entity_type: entity_type.to_string(),
-            }),
-        );
-        graph.metadata.name = entity_type.to_string();
+    /// Remove a node, handling its incident edges according to `policy`
+    ///
+    /// The composition root cannot be removed; move it first with
+    /// `set_composition_root`.
+    pub fn remove_node(
+        &mut self,
+        node_id: NodeId,
+        policy: EdgeRemovalPolicy,
+    ) -> Result<RemovedElements<N, R>, CompositionError> {
+        if node_id == self.composition_root {
+            return Err(CompositionError::RootRemoval(node_id));
+        }
+        if !self.nodes.contains_key(&node_id) {
+            return Err(CompositionError::NodeNotFound(node_id));
+        }
 
-        // Update root node with entity ID
-        if let Some(root) = graph.nodes.get_mut(&graph.composition_root) {
-            root.data = serde_json::json!({ "id": entity_id.into() });
-            root.label = entity_type.to_string();
+        let incident = self.incident_edge_ids(node_id);
+        let mut removed = RemovedElements::default();
+        match policy {
+            EdgeRemovalPolicy::Refuse if !incident.is_empty() => {
+                return Err(CompositionError::NodeHasEdges(node_id, incident.len()));
+            }
+            EdgeRemovalPolicy::Cascade => {
+                removed.edges = incident
+                    .into_iter()
+                    .filter_map(|edge_id| self.remove_edge(edge_id))
+                    .collect();
+            }
+            EdgeRemovalPolicy::Orphan | EdgeRemovalPolicy::Refuse => {}
         }
 
-        graph
+        if let Some(node) = self.nodes.remove(&node_id) {
+            self.labels.remove(&node);
+            self.ports.forget(node_id);
+            removed.nodes.push(node);
+        }
+        Ok(removed)
     }
 
-    /// Create an aggregate graph
-    pub fn aggregate(aggregate_type: &str, aggregate_id: impl Into<String>) -> Self {
-        let mut graph = Self::new(
-            BaseNodeType::Aggregate,
-            CompositionType::Domain(DomainCompositionType::Aggregate {
-                aggregate_type: aggregate_type.to_string(),
-            }),
-        );
-        graph.metadata.name = aggregate_type.to_string();
+    /// Remove a node and everything reachable from it over edges accepted by
+    /// `follow`, cascading to all incident edges
+    ///
+    /// Nodes in the subgraph are removed even if something outside it also
+    /// points at them. Fails without changing the graph if the subgraph would
+    /// include the composition root.
+    pub fn remove_subgraph_where<F>(
+        &mut self,
+        root: NodeId,
+        follow: F,
+    ) -> Result<RemovedElements<N, R>, CompositionError>
+    where
+        F: Fn(&R) -> bool,
+    {
+        if !self.nodes.contains_key(&root) {
+            return Err(CompositionError::NodeNotFound(root));
+        }
 
-        // Update root node with aggregate ID
-        if let Some(root) = graph.nodes.get_mut(&graph.composition_root) {
-            root.data = serde_json::json!({ "id": aggregate_id.into() });
-            root.label = aggregate_type.to_string();
+        let mut members = vec![root];
+        let mut seen = HashSet::from([root]);
+        let mut next = 0;
+        while next < members.len() {
+            let current = members[next];
+            next += 1;
+            for edge in self.out_edges(current) {
+                if follow(&edge.relationship.relationship_type) && seen.insert(edge.target) {
+                    members.push(edge.target);
+                }
+            }
         }
 
-        graph
+        if seen.contains(&self.composition_root) {
+            return Err(CompositionError::RootRemoval(self.composition_root));
+        }
+
+        let mut removed = RemovedElements::default();
+        for node_id in members {
+            if self.nodes.contains_key(&node_id) {
+                removed.extend(self.remove_node(node_id, EdgeRemovalPolicy::Cascade)?);
+            }
+        }
+        Ok(removed)
     }
 
-    /// Sequential composition: self then other
-    pub fn then(
-        &self,
-        other: &GraphComposition<BaseNodeType, BaseRelationshipType>,
-    ) -> Result<GraphComposition<BaseNodeType, BaseRelationshipType>, CompositionError> {
-        let mut result = self.clone();
-        result.id = GraphId::new();
+    /// Keep only the nodes matching `predicate`, cascading to incident edges
+    ///
+    /// Fails without changing the graph if the predicate rejects the
+    /// composition root.
+    pub fn retain_nodes<F>(
+        &mut self,
+        predicate: F,
+    ) -> Result<RemovedElements<N, R>, CompositionError>
+    where
+        F: Fn(&CompositionNode<N>) -> bool,
+    {
+        let rejected: Vec<NodeId> = self
+            .nodes
+            .values()
+            .filter(|node| !predicate(node))
+            .map(|node| node.id)
+            .collect();
 
-        // Add all nodes from other
-        for node in other.nodes.values() {
-            result.nodes.insert(node.id, node.clone());
+        if rejected.contains(&self.composition_root) {
+            return Err(CompositionError::RootRemoval(self.composition_root));
         }
 
-        // Add all edges from other
-        for edge in other.edges.values() {
-            result.edges.insert(edge.id, edge.clone());
+        let mut removed = RemovedElements::default();
+        for node_id in rejected {
+            removed.extend(self.remove_node(node_id, EdgeRemovalPolicy::Cascade)?);
         }
+        Ok(removed)
+    }
 
-        // Connect self's leaves to other's root
-        let leaves = self.find_leaves();
-        for leaf_id in leaves {
-            result = result.add_edge(leaf_id, other.composition_root, BaseRelationshipType::Sequence);
+    /// IDs of all edges entering or leaving a node, without duplicates
+    fn incident_edge_ids(&self, node_id: NodeId) -> Vec<EdgeId> {
+        let mut ids: Vec<EdgeId> = self.adjacency.outgoing(node_id).to_vec();
+        for edge_id in self.adjacency.incoming(node_id) {
+            if !ids.contains(edge_id) {
+                ids.push(*edge_id);
+            }
         }
+        ids
+    }
 
-        result.composition_type = CompositionType::Composite {
-            structure_type: "Sequential".to_string(),
-        };
-
-        Ok(result)
+    /// A standalone copy of `nodes` and the edges between them
+    ///
+    /// The first node becomes the composition root.
+    pub fn subgraph<I>(&self, nodes: I) -> Result<Self, CompositionError>
+    where
+        I: IntoIterator<Item = NodeId>,
+    {
+        let mut root = None;
+        let mut members = HashSet::new();
+        for node_id in nodes {
+            if !self.nodes.contains_key(&node_id) {
+                return Err(CompositionError::NodeNotFound(node_id));
+            }
+            root.get_or_insert(node_id);
+            members.insert(node_id);
+        }
+        let root = root.ok_or_else(|| {
+            CompositionError::InvalidComposition("subgraph has no nodes".to_string())
+        })?;
+        Ok(self.extract(root, &members, |_| true))
     }
 
-    /// Parallel composition: self and other
-    pub fn parallel(
-        &self,
-        other: &GraphComposition<BaseNodeType, BaseRelationshipType>,
-    ) -> Result<GraphComposition<BaseNodeType, BaseRelationshipType>, CompositionError> {
-        let mut result = GraphComposition::composite("Parallel");
+    /// The subgraph induced by the nodes matching `predicate`
+    ///
+    /// Keeps the composition root if it matches. Otherwise the new root is a
+    /// matching node that no other matching node points at.
+    pub fn induced_by<F>(&self, predicate: F) -> Result<Self, CompositionError>
+    where
+        F: Fn(&CompositionNode<N>) -> bool,
+    {
+        let members: HashSet<NodeId> = self
+            .nodes
+            .values()
+            .filter(|node| predicate(node))
+            .map(|node| node.id)
+            .collect();
+        let root = if members.contains(&self.composition_root) {
+            self.composition_root
+        } else {
+            members
+                .iter()
+                .copied()
+                .min_by_key(|node_id| {
+                    let entered = self
+                        .in_edges(*node_id)
+                        .iter()
+                        .any(|edge| edge.source != *node_id && members.contains(&edge.source));
+                    (entered, *node_id)
+                })
+                .ok_or_else(|| {
+                    CompositionError::InvalidComposition(
+                        "no node matches the predicate".to_string(),
+                    )
+                })?
+        };
+        Ok(self.extract(root, &members, |_| true))
+    }
 
-        // Add all nodes from both graphs
-        for node in self.nodes.values() {
-            result.nodes.insert(node.id, node.clone());
+    /// Everything within `radius` edges of `node_id`, following edges either way
+    ///
+    /// `node_id` becomes the composition root of the result.
+    pub fn neighborhood(&self, node_id: NodeId, radius: usize) -> Result<Self, CompositionError> {
+        if !self.nodes.contains_key(&node_id) {
+            return Err(CompositionError::NodeNotFound(node_id));
         }
-        for node in other.nodes.values() {
-            result.nodes.insert(node.id, node.clone());
+
+        let mut members = HashSet::from([node_id]);
+        let mut frontier = vec![node_id];
+        for _ in 0..radius {
+            let mut next = Vec::new();
+            for current in frontier {
+                let neighbors = self
+                    .out_edges(current)
+                    .into_iter()
+                    .map(|edge| edge.target)
+                    .chain(self.in_edges(current).into_iter().map(|edge| edge.source));
+                for neighbor in neighbors {
+                    if members.insert(neighbor) {
+                        next.push(neighbor);
+                    }
+                }
+            }
+            frontier = next;
         }
+        Ok(self.extract(node_id, &members, |_| true))
+    }
 
-        // Add all edges from both graphs
-        for edge in self.edges.values() {
-            result.edges.insert(edge.id, edge.clone());
+    /// `root` and everything below it along edges accepted by `follow`
+    ///
+    /// Only the accepted edges are kept, so following `Contains` yields the
+    /// containment tree under `root` without cross references.
+    pub fn extract_component<F>(&self, root: NodeId, follow: F) -> Result<Self, CompositionError>
+    where
+        F: Fn(&R) -> bool,
+    {
+        if !self.nodes.contains_key(&root) {
+            return Err(CompositionError::NodeNotFound(root));
         }
-        for edge in other.edges.values() {
-            result.edges.insert(edge.id, edge.clone());
+
+        let mut members = HashSet::from([root]);
+        let mut stack = vec![root];
+        while let Some(node_id) = stack.pop() {
+            for next in self.successors_where(node_id, &follow) {
+                if members.insert(next) {
+                    stack.push(next);
+                }
+            }
         }
+        Ok(self.extract(root, &members, |edge| {
+            follow(&edge.relationship.relationship_type)
+        }))
+    }
 
-        // Connect new root to both subgraph roots
-        let root = result.composition_root;
-        result = result
-            .add_edge(root, self.composition_root, BaseRelationshipType::Parallel)
-            .add_edge(root, other.composition_root, BaseRelationshipType::Parallel);
+    /// The graph this one was extracted from, if it is a subgraph
+    pub fn parent_graph(&self) -> Option<GraphId> {
+        self.metadata
+            .properties
+            .get(PARENT_GRAPH_KEY)
+            .and_then(|id| serde_json::from_value(id.clone()).ok())
+    }
 
-        Ok(result)
+    /// Copy `members`, and the edges between them accepted by `keep_edge`,
+    /// into a new graph rooted at `root`
+    ///
+    /// Metadata, invariants, ports and the ID strategy are carried over, and
+    /// the parent graph is recorded under `PARENT_GRAPH_KEY`.
+    fn extract<F>(&self, root: NodeId, members: &HashSet<NodeId>, keep_edge: F) -> Self
+    where
+        F: Fn(&CompositionEdge<R>) -> bool,
+    {
+        let nodes = self
+            .nodes
+            .iter()
+            .filter(|(node_id, _)| members.contains(node_id))
+            .map(|(node_id, node)| (*node_id, node.clone()))
+            .collect();
+        let edges = self
+            .edges
+            .iter()
+            .filter(|(_, edge)| {
+                members.contains(&edge.source) && members.contains(&edge.target) && keep_edge(edge)
+            })
+            .map(|(edge_id, edge)| (*edge_id, edge.clone()))
+            .collect();
+        let mut ports = self.ports.clone();
+        ports.retain(|node_id| members.contains(&node_id));
+        let mut metadata = self.metadata.clone();
+        metadata.properties.insert(
+            PARENT_GRAPH_KEY.to_string(),
+            serde_json::to_value(self.id).unwrap_or(JsonValue::Null),
+        );
+
+        let mut subgraph = Self {
+            id: self.id,
+            composition_root: root,
+            composition_type: self.composition_type.clone(),
+            nodes,
+            edges,
+            metadata,
+            unique_labels: self.unique_labels,
+            invariants: self.invariants.clone(),
+            id_strategy: self.id_strategy.clone(),
+            ports,
+            adjacency: AdjacencyIndex::default(),
+            labels: LabelIndex::default(),
+            constraints: Vec::new(),
+        };
+        subgraph.id = GraphId::from_uuid(
+            subgraph
+                .id_strategy
+                .generate(&format!("subgraph:{}:{}", self.id, root)),
+        );
+        subgraph.rebuild_indexes();
+        subgraph
     }
 
-    /// Choice composition: self or other
-    pub fn choice(
-        &self,
-        other: &GraphComposition<BaseNodeType, BaseRelationshipType>,
-    ) -> Result<GraphComposition<BaseNodeType, BaseRelationshipType>, CompositionError> {
-        let mut result = GraphComposition::composite("Choice");
+    /// Add an invariant constraint
+    ///
+    /// Closures are dropped by `clone`, serialization and type-changing maps;
+    /// prefer `with_invariant_rule` where a built-in rule fits.
+    pub fn with_invariant<F>(mut self, invariant: F) -> Self
+    where
+        F: Fn(&GraphComposition<N, R>) -> bool + 'static,
+    {
+        self.constraints.push(Box::new(invariant));
+        self
+    }
 
-        // Add all nodes from both graphs
-        for node in self.nodes.values() {
-            result.nodes.insert(node.id, node.clone());
+    /// Add a declarative invariant, replacing any invariant of the same name
+    pub fn with_invariant_rule(mut self, invariant: Invariant) -> Self {
+        self.invariants
+            .retain(|existing| existing.name != invariant.name);
+        self.invariants.push(invariant);
+        self
+    }
+
+    /// The declarative invariants of this graph
+    pub fn invariants(&self) -> &[Invariant] {
+        &self.invariants
+    }
+
+    /// Remove a declarative invariant by name
+    pub fn remove_invariant(&mut self, name: &str) -> Option<Invariant> {
+        let index = self.invariants.iter().position(|i| i.name == name)?;
+        Some(self.invariants.remove(index))
+    }
+
+    /// Check if all invariants hold
+    ///
+    /// Stops at the first failure; a cycle under [`Invariant::acyclic`] is
+    /// reported as `CycleDetected`. Use `check_all_invariants` for a full report.
+    pub fn check_invariants(&self) -> Result<(), CompositionError> {
+        for invariant in &self.invariants {
+            if let Some(violation) = invariant.check(self).into_iter().next() {
+                return Err(match invariant.rule {
+                    InvariantRule::Acyclic => CompositionError::CycleDetected,
+                    _ => violation.into(),
+                });
+            }
         }
-        for node in other.nodes.values() {
-            result.nodes.insert(node.id, node.clone());
+        for (i, invariant) in self.constraints.iter().enumerate() {
+            if !invariant(self) {
+                return Err(CompositionError::InvariantViolation(format!(
+                    "Invariant {i} failed"
+                )));
+            }
         }
+        Ok(())
+    }
 
-        // Add all edges from both graphs
-        for edge in self.edges.values() {
-            result.edges.insert(edge.id, edge.clone());
+    /// Check every invariant and report all violations
+    ///
+    /// Closure constraints are reported as `constraint {i}`, without IDs.
+    pub fn check_all_invariants(&self) -> Result<(), Vec<Violation>> {
+        let mut violations: Vec<Violation> = self
+            .invariants
+            .iter()
+            .flat_map(|invariant| invariant.check(self))
+            .collect();
+
+        for (i, invariant) in self.constraints.iter().enumerate() {
+            if !invariant(self) {
+                violations.push(Violation {
+                    invariant: format!("constraint {i}"),
+                    message: format!("Invariant {i} failed"),
+                    nodes: Vec::new(),
+                    edges: Vec::new(),
+                });
+            }
         }
-        for edge in other.edges.values() {
-            result.edges.insert(edge.id, edge.clone());
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
         }
+    }
 
-        // Connect new root to both subgraph roots with choice edges
-        let root = result.composition_root;
-        result = result
-            .add_edge(root, self.composition_root, BaseRelationshipType::Choice)
-            .add_edge(root, other.composition_root, BaseRelationshipType::Choice);
+    /// Find leaf nodes (nodes with no outgoing edges)
+    pub fn find_leaves(&self) -> Vec<NodeId> {
+        self.nodes
+            .keys()
+            .filter(|node_id| self.adjacency.outgoing(**node_id).is_empty())
+            .copied()
+            .collect()
+    }
 
-        Ok(result)
+    /// Find root nodes (nodes with no incoming edges)
+    pub fn find_roots(&self) -> Vec<NodeId> {
+        self.nodes
+            .keys()
+            .filter(|node_id| self.adjacency.incoming(**node_id).is_empty())
+            .copied()
+            .collect()
     }
-}
 
-impl<N, R> Composable for GraphComposition<N, R>
-where
-    N: Clone + Serialize + for<'de> Deserialize<'de>,
-    R: Clone + Serialize + for<'de> Deserialize<'de>,
-{
-    type Output = GraphComposition<N, R>;
+    /// Get all nodes connected to a given node
+    pub fn get_connected_nodes(&self, node_id: NodeId) -> Vec<NodeId> {
+        let mut connected: Vec<NodeId> = self
+            .out_edges(node_id)
+            .into_iter()
+            .map(|e| e.target)
+            .collect();
 
-    fn compose(&self, other: &Self) -> Result<Self::Output, CompositionError> {
-        // Default composition merges graphs
-        let mut result = self.clone();
+        // Bidirectional edges are also traversable from their target
+        connected.extend(
+            self.in_edges(node_id)
+                .into_iter()
+                .filter(|e| e.relationship.bidirectional)
+                .map(|e| e.source),
+        );
 
-        // Add all nodes from other
-        for node in other.nodes.values() {
-            result.nodes.insert(node.id, node.clone());
-        }
+        connected
+    }
 
-        // Add all edges from other
-        for edge in other.edges.values() {
-            result.edges.insert(edge.id, edge.clone());
-        }
+    /// Edges leaving the given node
+    pub fn out_edges(&self, node_id: NodeId) -> Vec<&CompositionEdge<R>> {
+        self.adjacency
+            .outgoing(node_id)
+            .iter()
+            .filter_map(|edge_id| self.edges.get(edge_id))
+            .collect()
+    }
 
-        Ok(result)
+    /// Edges entering the given node
+    pub fn in_edges(&self, node_id: NodeId) -> Vec<&CompositionEdge<R>> {
+        self.adjacency
+            .incoming(node_id)
+            .iter()
+            .filter_map(|edge_id| self.edges.get(edge_id))
+            .collect()
     }
 
-    fn can_compose_with(&self, _other: &Self) -> bool {
-        // For now, any graphs can be composed
-        true
+    /// Number of edges leaving the given node
+    pub fn out_degree(&self, node_id: NodeId) -> usize {
+        self.adjacency.outgoing(node_id).len()
     }
-}
 
-/// Morphism between graphs
-pub trait GraphMorphism<N1, R1, N2, R2>: Send + Sync
-where
-    N1: Clone + Serialize + for<'de> Deserialize<'de>,
-    R1: Clone + Serialize + for<'de> Deserialize<'de>,
-    N2: Clone + Serialize + for<'de> Deserialize<'de>,
-    R2: Clone + Serialize + for<'de> Deserialize<'de>,
-{
-    fn apply(
+    /// Number of edges entering the given node
+    pub fn in_degree(&self, node_id: NodeId) -> usize {
+        self.adjacency.incoming(node_id).len()
+    }
+
+    /// Whether the graph contains a cycle
+    ///
+    /// Back-edges of intentional loops (see `Relationship::back_edge`) are
+    /// ignored here and by the other cycle and ordering queries.
+    pub fn has_cycle(&self) -> bool {
+        self.has_cycle_where(|_| true)
+    }
+
+    /// Whether the edges whose relationship passes `filter` form a cycle
+    pub fn has_cycle_where<F>(&self, filter: F) -> bool
+    where
+        F: Fn(&R) -> bool,
+    {
+        self.topological_order_where(filter).is_err()
+    }
+
+    /// Find the cycles in the graph as node paths
+    ///
+    /// See [`GraphComposition::find_cycles_where`].
+    pub fn find_cycles(&self) -> Vec<Vec<NodeId>> {
+        self.find_cycles_where(|_| true)
+    }
+
+    /// Find cycles among the edges whose relationship passes `filter`
+    ///
+    /// Reports one shortest cycle per strongly connected component rather
+    /// than every elementary cycle, which can be exponentially many. Each path
+    /// lists its nodes in edge order; the last node has an edge back to the
+    /// first. A self-loop is a path of one node.
+    pub fn find_cycles_where<F>(&self, filter: F) -> Vec<Vec<NodeId>>
+    where
+        F: Fn(&R) -> bool,
+    {
+        let mut cycles: Vec<Vec<NodeId>> = self
+            .strongly_connected_components(&filter)
+            .into_iter()
+            .filter_map(|component| self.shortest_cycle_in(&component, &filter))
+            .collect();
+        cycles.sort();
+        cycles
+    }
+
+    /// Order the nodes so every edge points forward
+    ///
+    /// Fails with `CycleDetected` if the graph has a cycle. Ties are broken by
+    /// node ID so the order is stable.
+    pub fn topological_order(&self) -> Result<Vec<NodeId>, CompositionError> {
+        self.topological_order_where(|_| true)
+    }
+
+    /// Order the nodes so every edge whose relationship passes `filter`
+    /// points forward; other edges are ignored
+    ///
+    /// For instance, passing `|r| matches!(r, Sequence | DependsOn)` orders
+    /// the steps of a workflow while ignoring its `Contains` structure.
+    pub fn topological_order_where<F>(&self, filter: F) -> Result<Vec<NodeId>, CompositionError>
+    where
+        F: Fn(&R) -> bool,
+    {
+        let mut in_degree: HashMap<NodeId, usize> = self.nodes.keys().map(|id| (*id, 0)).collect();
+        for edge in self.edges.values() {
+            if filter(&edge.relationship.relationship_type) && !edge.relationship.is_back_edge() {
+                if let Some(degree) = in_degree.get_mut(&edge.target) {
+                    *degree += 1;
+                }
+            }
+        }
+
+        let mut ready: BTreeSet<NodeId> = in_degree
+            .iter()
+            .filter(|(_, degree)| **degree == 0)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+
+        while let Some(node_id) = ready.pop_first() {
+            order.push(node_id);
+            for target in self.successors_where(node_id, &filter) {
+                if let Some(degree) = in_degree.get_mut(&target) {
+                    *degree -= 1;
+                    if *degree == 0 {
+                        ready.insert(target);
+                    }
+                }
+            }
+        }
+
+        if order.len() == self.nodes.len() {
+            Ok(order)
+        } else {
+            Err(CompositionError::CycleDetected)
+        }
+    }
+
+    /// Targets of the edges leaving `node_id` whose relationship passes
+    /// `filter`, skipping loop back-edges
+    fn successors_where<'a, F>(
+        &'a self,
+        node_id: NodeId,
+        filter: &'a F,
+    ) -> impl Iterator<Item = NodeId> + 'a
+    where
+        F: Fn(&R) -> bool,
+    {
+        self.out_edges(node_id)
+            .into_iter()
+            .filter(move |edge| {
+                filter(&edge.relationship.relationship_type) && !edge.relationship.is_back_edge()
+            })
+            .map(|edge| edge.target)
+    }
+
+    /// Whether `to` can be reached from `from` along edges passing `filter`
+    fn reaches_where<F>(&self, from: NodeId, to: NodeId, filter: F) -> bool
+    where
+        F: Fn(&R) -> bool,
+    {
+        let mut visited = HashSet::from([from]);
+        let mut stack = vec![from];
+        while let Some(node_id) = stack.pop() {
+            if node_id == to {
+                return true;
+            }
+            for next in self.successors_where(node_id, &filter) {
+                if visited.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        false
+    }
+
+    /// Strongly connected components that contain a cycle (Kosaraju)
+    fn strongly_connected_components<F>(&self, filter: &F) -> Vec<HashSet<NodeId>>
+    where
+        F: Fn(&R) -> bool,
+    {
+        // First pass: record nodes by DFS finishing time
+        let mut finished = Vec::with_capacity(self.nodes.len());
+        let mut visited = HashSet::new();
+        for start in self.nodes.keys() {
+            if !visited.insert(*start) {
+                continue;
+            }
+            let mut stack = vec![(
+                *start,
+                self.successors_where(*start, filter).collect::<Vec<_>>(),
+            )];
+            while let Some((node_id, pending)) = stack.last_mut() {
+                if let Some(next) = pending.pop() {
+                    if visited.insert(next) {
+                        let successors = self.successors_where(next, filter).collect();
+                        stack.push((next, successors));
+                    }
+                } else {
+                    finished.push(*node_id);
+                    stack.pop();
+                }
+            }
+        }
+
+        // Second pass: collect components on the reversed graph
+        let mut assigned = HashSet::new();
+        let mut components = Vec::new();
+        for start in finished.into_iter().rev() {
+            if !assigned.insert(start) {
+                continue;
+            }
+            let mut component = HashSet::from([start]);
+            let mut stack = vec![start];
+            while let Some(node_id) = stack.pop() {
+                for edge in self.in_edges(node_id) {
+                    let followed = filter(&edge.relationship.relationship_type)
+                        && !edge.relationship.is_back_edge();
+                    if followed && assigned.insert(edge.source) {
+                        component.insert(edge.source);
+                        stack.push(edge.source);
+                    }
+                }
+            }
+
+            let self_loop = || {
+                self.successors_where(start, filter)
+                    .any(|next| next == start)
+            };
+            if component.len() > 1 || self_loop() {
+                components.push(component);
+            }
+        }
+        components
+    }
+
+    /// Shortest cycle through the smallest node of a strongly connected component
+    fn shortest_cycle_in<F>(&self, component: &HashSet<NodeId>, filter: &F) -> Option<Vec<NodeId>>
+    where
+        F: Fn(&R) -> bool,
+    {
+        let start = *component.iter().min()?;
+        let mut parent: HashMap<NodeId, NodeId> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(node_id) = queue.pop_front() {
+            let mut successors: Vec<NodeId> = self
+                .successors_where(node_id, filter)
+                .filter(|next| component.contains(next))
+                .collect();
+            successors.sort();
+            for next in successors {
+                if next == start {
+                    let mut path = vec![node_id];
+                    while let Some(previous) = parent.get(path.last()?) {
+                        path.push(*previous);
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                if let Entry::Vacant(entry) = parent.entry(next) {
+                    entry.insert(node_id);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Map a function over all nodes
+    pub fn map_nodes<F, N2>(self, f: F) -> GraphComposition<N2, R>
+    where
+        F: Fn(&CompositionNode<N>) -> CompositionNode<N2>,
+        N2: Clone + Serialize + for<'de> Deserialize<'de>,
+    {
+        let mut new_nodes = HashMap::new();
+        for (id, node) in self.nodes {
+            let new_node = f(&node);
+            new_nodes.insert(id, new_node);
+        }
+
+        let labels = LabelIndex::from_nodes(&new_nodes);
+
+        GraphComposition {
+            id: self.id,
+            composition_root: self.composition_root,
+            composition_type: self.composition_type,
+            nodes: new_nodes,
+            edges: self.edges,
+            metadata: self.metadata,
+            unique_labels: self.unique_labels,
+            invariants: self.invariants,
+            id_strategy: self.id_strategy,
+            ports: self.ports,
+            adjacency: self.adjacency,
+            labels,
+            constraints: Vec::new(), // Closures don't transfer across type changes
+        }
+    }
+
+    /// Map a function over all edges, changing the relationship type
+    pub fn map_edges<F, R2>(mut self, f: F) -> GraphComposition<N, R2>
+    where
+        F: Fn(&CompositionEdge<R>) -> CompositionEdge<R2>,
+        R2: Clone + Serialize + for<'de> Deserialize<'de>,
+    {
+        let nodes = std::mem::take(&mut self.nodes);
+        let edges = self.edges.iter().map(|(id, edge)| (*id, f(edge))).collect();
+        self.with_elements(nodes, edges)
+    }
+
+    /// Map over nodes and edges at once
+    pub fn bimap<F, G, N2, R2>(self, f: F, g: G) -> GraphComposition<N2, R2>
+    where
+        F: Fn(&CompositionNode<N>) -> CompositionNode<N2>,
+        G: Fn(&CompositionEdge<R>) -> CompositionEdge<R2>,
+        N2: Clone + Serialize + for<'de> Deserialize<'de>,
+        R2: Clone + Serialize + for<'de> Deserialize<'de>,
+    {
+        let nodes = self.nodes.iter().map(|(id, node)| (*id, f(node))).collect();
+        let edges = self.edges.iter().map(|(id, edge)| (*id, g(edge))).collect();
+        self.with_elements(nodes, edges)
+    }
+
+    /// Map a fallible function over all nodes, stopping at the first failure
+    pub fn try_map_nodes<F, N2, E>(
+        mut self,
+        f: F,
+    ) -> Result<GraphComposition<N2, R>, CompositionError>
+    where
+        F: Fn(&CompositionNode<N>) -> Result<CompositionNode<N2>, E>,
+        N2: Clone + Serialize + for<'de> Deserialize<'de>,
+        E: std::fmt::Display,
+    {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|(id, node)| {
+                f(node)
+                    .map(|mapped| (*id, mapped))
+                    .map_err(|e| CompositionError::FunctorError(format!("node {id}: {e}")))
+            })
+            .collect::<Result<_, _>>()?;
+        let edges = std::mem::take(&mut self.edges);
+        Ok(self.with_elements(nodes, edges))
+    }
+
+    /// Map a fallible function over all edges, stopping at the first failure
+    pub fn try_map_edges<F, R2, E>(
+        mut self,
+        f: F,
+    ) -> Result<GraphComposition<N, R2>, CompositionError>
+    where
+        F: Fn(&CompositionEdge<R>) -> Result<CompositionEdge<R2>, E>,
+        R2: Clone + Serialize + for<'de> Deserialize<'de>,
+        E: std::fmt::Display,
+    {
+        let edges = self
+            .edges
+            .iter()
+            .map(|(id, edge)| {
+                f(edge)
+                    .map(|mapped| (*id, mapped))
+                    .map_err(|e| CompositionError::FunctorError(format!("edge {id}: {e}")))
+            })
+            .collect::<Result<_, _>>()?;
+        let nodes = std::mem::take(&mut self.nodes);
+        Ok(self.with_elements(nodes, edges))
+    }
+
+    /// Transform every node without changing the node type
+    ///
+    /// Unlike `map_nodes` the graph keeps its closure constraints, since they
+    /// still apply to the same type.
+    pub fn update_nodes<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&mut CompositionNode<N>),
+    {
+        for node in self.nodes.values_mut() {
+            f(node);
+        }
+        self.rebuild_indexes();
+        self
+    }
+
+    /// Transform every edge without changing the relationship type
+    ///
+    /// Unlike `map_edges` the graph keeps its closure constraints.
+    pub fn update_edges<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&mut CompositionEdge<R>),
+    {
+        for edge in self.edges.values_mut() {
+            f(edge);
+        }
+        self.rebuild_indexes();
+        self
+    }
+
+    /// Rebuild this graph around new node and edge maps
+    ///
+    /// Indexes are recomputed, since mapped elements may carry new labels or
+    /// endpoints. Declarative invariants are kept; closure constraints are
+    /// dropped because they are typed on `N` and `R`.
+    fn with_elements<N2, R2>(
+        self,
+        nodes: HashMap<NodeId, CompositionNode<N2>>,
+        edges: HashMap<EdgeId, CompositionEdge<R2>>,
+    ) -> GraphComposition<N2, R2> {
+        GraphCompositionData {
+            id: self.id,
+            composition_root: self.composition_root,
+            composition_type: self.composition_type,
+            nodes,
+            edges,
+            metadata: self.metadata,
+            unique_labels: self.unique_labels,
+            invariants: self.invariants,
+            id_strategy: self.id_strategy,
+            ports: self.ports,
+        }
+        .into()
+    }
+
+    /// Fold the graph to a value
+    pub fn fold<T, F>(&self, init: T, f: F) -> T
+    where
+        F: Fn(T, &CompositionNode<N>) -> T,
+    {
+        self.nodes.values().fold(init, f)
+    }
+
+    /// Check that `other` can be composed into this graph
+    ///
+    /// Fails with `IncompatibleTypes` unless the composition types are
+    /// compatible (see [`CompositionType::is_compatible_with`]).
+    pub fn check_composable(&self, other: &Self) -> Result<(), CompositionError> {
+        if self
+            .composition_type
+            .is_compatible_with(&other.composition_type)
+        {
+            Ok(())
+        } else {
+            Err(CompositionError::IncompatibleTypes(
+                format!("{:?}", self.composition_type),
+                format!("{:?}", other.composition_type),
+            ))
+        }
+    }
+
+    /// Merge `other` into a copy of this graph
+    ///
+    /// The result keeps this graph's root, type and metadata. Nodes and edges
+    /// present in both graphs with identical content are shared; IDs reused
+    /// for different content are resolved by `strategy`.
+    pub fn compose_with(
         &self,
-        graph: &GraphComposition<N1, R1>,
-    ) -> Result<GraphComposition<N2, R2>, CompositionError>;
-}
+        other: &Self,
+        strategy: ComposeStrategy,
+    ) -> Result<Self, CompositionError> {
+        self.check_composable(other)?;
+        let mut result = self.clone();
+        let other_paths = other.id_paths();
+
+        // Where each of other's nodes ends up in the result
+        let mut node_ids: HashMap<NodeId, NodeId> = HashMap::new();
+        let mut other_nodes: Vec<&CompositionNode<N>> = other.nodes.values().collect();
+        other_nodes.sort_by_key(|node| node.id);
+
+        for node in other_nodes {
+            let Some(existing) = result.nodes.get(&node.id) else {
+                result.insert_node(node.clone());
+                node_ids.insert(node.id, node.id);
+                continue;
+            };
+            if same_content(existing, node) {
+                node_ids.insert(node.id, node.id);
+                continue;
+            }
+
+            match strategy {
+                ComposeStrategy::FailOnConflict => {
+                    return Err(CompositionError::DuplicateNodeId(node.id));
+                }
+                ComposeStrategy::Rekey => {
+                    let mut rekeyed = node.clone();
+                    rekeyed.id = result.fresh_node_id(&result.child_path(&other_paths[&node.id]));
+                    node_ids.insert(node.id, rekeyed.id);
+                    result.insert_node(rekeyed);
+                }
+                ComposeStrategy::MergeById(merge) => {
+                    if !same_content(&existing.node_type, &node.node_type) {
+                        return Err(CompositionError::IncompatibleTypes(
+                            serde_json::to_string(&existing.node_type).unwrap_or_default(),
+                            serde_json::to_string(&node.node_type).unwrap_or_default(),
+                        ));
+                    }
+                    if existing.label != node.label {
+                        return Err(CompositionError::DataConflict(node.id));
+                    }
+                    let mut merged = existing.clone();
+                    merged.data = merge
+                        .merge(&existing.data, &node.data)
+                        .ok_or(CompositionError::DataConflict(node.id))?;
+                    for (key, value) in &node.metadata {
+                        merged
+                            .metadata
+                            .entry(key.clone())
+                            .or_insert_with(|| value.clone());
+                    }
+                    node_ids.insert(node.id, node.id);
+                    result.insert_node(merged);
+                }
+            }
+        }
+
+        let mut other_edges: Vec<&CompositionEdge<R>> = other.edges.values().collect();
+        other_edges.sort_by_key(|edge| edge.id);
+
+        for edge in other_edges {
+            let mut edge = edge.clone();
+            edge.source = node_ids.get(&edge.source).copied().unwrap_or(edge.source);
+            edge.target = node_ids.get(&edge.target).copied().unwrap_or(edge.target);
+
+            if let Some(existing) = result.edges.get(&edge.id) {
+                if same_content(existing, &edge) {
+                    continue;
+                }
+                if let ComposeStrategy::FailOnConflict = strategy {
+                    return Err(CompositionError::DuplicateEdgeId(edge.id));
+                }
+                edge.id = result.fresh_edge_id(
+                    edge.source,
+                    edge.target,
+                    &edge.relationship.relationship_type,
+                );
+            }
+            result.insert_edge(edge);
+        }
+
+        Ok(result)
+    }
+
+    /// Glue `other` onto this graph along shared interface nodes
+    ///
+    /// This is the pushout of the two graphs over their interface: each pair
+    /// of identified nodes becomes one node, keeping this graph's ID, type and
+    /// label, with data reconciled by `reconcile` (failing with `DataConflict`
+    /// if it cannot) and metadata united. All other nodes and edges of `other`
+    /// are added, re-keyed where their IDs are taken; an edge that joins the
+    /// same glued nodes with the same relationship in both graphs is kept once.
+    /// The result is rooted at this graph's root.
+    pub fn glue(
+        &self,
+        other: &Self,
+        interface: Interface,
+        reconcile: DataMerge,
+    ) -> Result<Self, CompositionError> {
+        let pairs = self.interface_pairs(other, &interface)?;
+
+        let mut result = self.merge_along(other, &pairs, &reconcile)?;
+        result.id = GraphId::from_uuid(
+            result
+                .id_strategy
+                .generate(&format!("glue:{}:{}", self.id, other.id)),
+        );
+        result.composition_type = CompositionType::Composite {
+            structure_type: "Pushout".to_string(),
+        };
+        Ok(result)
+    }
+
+    /// A copy of this graph with `other` glued on along `pairs` (see `glue`)
+    fn merge_along(
+        &self,
+        other: &Self,
+        pairs: &[(NodeId, NodeId)],
+        reconcile: &DataMerge,
+    ) -> Result<Self, CompositionError> {
+        let mut result = self.clone();
+
+        // Where each of other's nodes ends up in the result
+        let mut node_ids: HashMap<NodeId, NodeId> = HashMap::new();
+        for (ours, theirs) in pairs {
+            if let Some(previous) = node_ids.insert(*theirs, *ours) {
+                if previous != *ours {
+                    return Err(CompositionError::InvalidComposition(format!(
+                        "node {theirs} is glued to both {previous} and {ours}"
+                    )));
+                }
+            }
+            let theirs = &other.nodes[theirs];
+            let mut merged = result.nodes[ours].clone();
+            merged.data = reconcile
+                .merge(&merged.data, &theirs.data)
+                .ok_or(CompositionError::DataConflict(*ours))?;
+            for (key, value) in &theirs.metadata {
+                merged
+                    .metadata
+                    .entry(key.clone())
+                    .or_insert_with(|| value.clone());
+            }
+            result.insert_node(merged);
+        }
+
+        let mut other_nodes: Vec<&CompositionNode<N>> = other
+            .nodes
+            .values()
+            .filter(|node| !node_ids.contains_key(&node.id))
+            .collect();
+        other_nodes.sort_by_key(|node| node.id);
+        let other_paths = other.id_paths();
+        for node in other_nodes {
+            let mut node = node.clone();
+            let original = node.id;
+            if result.nodes.contains_key(&node.id) {
+                node.id = result.fresh_node_id(&result.child_path(&other_paths[&original]));
+            }
+            node_ids.insert(original, node.id);
+            result.insert_node(node);
+        }
+
+        let mut other_edges: Vec<&CompositionEdge<R>> = other.edges.values().collect();
+        other_edges.sort_by_key(|edge| edge.id);
+        for edge in other_edges {
+            let mut edge = edge.clone();
+            edge.source = node_ids.get(&edge.source).copied().unwrap_or(edge.source);
+            edge.target = node_ids.get(&edge.target).copied().unwrap_or(edge.target);
+
+            let duplicate = result.out_edges(edge.source).into_iter().any(|existing| {
+                existing.target == edge.target
+                    && same_content(&existing.relationship, &edge.relationship)
+            });
+            if duplicate {
+                continue;
+            }
+            if result.edges.contains_key(&edge.id) {
+                edge.id = result.fresh_edge_id(
+                    edge.source,
+                    edge.target,
+                    &edge.relationship.relationship_type,
+                );
+            }
+            result.insert_edge(edge);
+        }
+
+        Ok(result)
+    }
+
+    /// Resolve an interface to pairs of (node in `self`, node in `other`)
+    fn interface_pairs(
+        &self,
+        other: &Self,
+        interface: &Interface,
+    ) -> Result<Vec<(NodeId, NodeId)>, CompositionError> {
+        match interface {
+            Interface::Ids(pairs) => {
+                for (ours, theirs) in pairs {
+                    if !self.nodes.contains_key(ours) {
+                        return Err(CompositionError::NodeNotFound(*ours));
+                    }
+                    if !other.nodes.contains_key(theirs) {
+                        return Err(CompositionError::NodeNotFound(*theirs));
+                    }
+                }
+                Ok(pairs.clone())
+            }
+            Interface::Labels(pairs) => pairs
+                .iter()
+                .map(|(ours, theirs)| Ok((self.resolve_label(ours)?, other.resolve_label(theirs)?)))
+                .collect(),
+            Interface::EntityIds => self.matching_nodes(other, NodeIdentity::EntityId),
+        }
+    }
+
+    /// Pairs of (node in `self`, node in `other`) with the same identity
+    fn matching_nodes(
+        &self,
+        other: &Self,
+        identity: NodeIdentity,
+    ) -> Result<Vec<(NodeId, NodeId)>, CompositionError> {
+        let ours = self.node_keys(identity)?;
+        let theirs = other.node_keys(identity)?;
+        let mut pairs: Vec<(NodeId, NodeId)> = ours
+            .iter()
+            .filter_map(|(key, node)| Some((*node, *theirs.get(key)?)))
+            .collect();
+        pairs.sort();
+        Ok(pairs)
+    }
+
+    /// Nodes by their identity key; nodes without one are left out
+    fn node_keys(
+        &self,
+        identity: NodeIdentity,
+    ) -> Result<HashMap<String, NodeId>, CompositionError> {
+        match identity {
+            NodeIdentity::Id => Ok(self
+                .nodes
+                .keys()
+                .map(|node_id| (node_id.to_string(), *node_id))
+                .collect()),
+            NodeIdentity::LabelPath => {
+                let mut keys: HashMap<String, Vec<NodeId>> = HashMap::new();
+                for (node_id, path) in self.label_paths() {
+                    keys.entry(path).or_default().push(node_id);
+                }
+                keys.into_iter()
+                    .map(|(path, ids)| match ids.as_slice() {
+                        [node_id] => Ok((path, *node_id)),
+                        _ => Err(CompositionError::AmbiguousLabel(path, ids.len())),
+                    })
+                    .collect()
+            }
+            NodeIdentity::EntityId => {
+                let mut ids = HashMap::new();
+                for node in self.nodes.values() {
+                    let Some(entity_id) = node.data.get(ENTITY_ID_KEY) else {
+                        continue;
+                    };
+                    let entity_id = entity_id
+                        .as_str()
+                        .map_or_else(|| entity_id.to_string(), str::to_string);
+                    if ids.insert(entity_id.clone(), node.id).is_some() {
+                        return Err(CompositionError::InvalidComposition(format!(
+                            "entity {entity_id} is referenced by several nodes"
+                        )));
+                    }
+                }
+                Ok(ids)
+            }
+        }
+    }
+
+    /// The qualified label path of a node, such as `"root/sales/team"`
+    ///
+    /// The path follows a shortest chain of edges from the composition root,
+    /// choosing the alphabetically first among equally short ones, and
+    /// resolves back to the node unless labels repeat along the way. Nodes
+    /// the root cannot reach are identified by their plain label.
+    pub fn label_path(&self, node_id: NodeId) -> Option<String> {
+        self.label_paths().remove(&node_id)
+    }
+
+    /// The label path of every node (see `label_path`)
+    fn label_paths(&self) -> HashMap<NodeId, String> {
+        let mut paths = self.paths_from("root".to_string(), |node| node.label.clone());
+        for node in self.nodes.values() {
+            paths.entry(node.id).or_insert_with(|| node.label.clone());
+        }
+        paths
+    }
+
+    /// The qualified label path node IDs are derived from
+    ///
+    /// Like `label_path`, but rooted at the graph name and naming each
+    /// composed root by its `GRAPH_NAME_KEY`, so a node's path is the chain
+    /// of graphs it sits in followed by its label, such as
+    /// `"Parallel/Lint/run"`. Nodes the root cannot reach sit directly under
+    /// the graph name, as they do when first added.
+    fn id_paths(&self) -> HashMap<NodeId, String> {
+        let mut paths = self.paths_from(self.graph_name().to_string(), |node| {
+            LabelIndex::graph_name(node)
+                .unwrap_or(&node.label)
+                .to_string()
+        });
+        for node in self.nodes.values() {
+            paths
+                .entry(node.id)
+                .or_insert_with(|| self.child_path(&node.label));
+        }
+        paths
+    }
+
+    /// Paths of the nodes reachable from the root along shortest chains of
+    /// edges, choosing the alphabetically first among equally short ones
+    fn paths_from<F>(&self, root: String, segment: F) -> HashMap<NodeId, String>
+    where
+        F: Fn(&CompositionNode<N>) -> String,
+    {
+        let mut paths = HashMap::from([(self.composition_root, root)]);
+        let mut frontier = vec![self.composition_root];
+        while !frontier.is_empty() {
+            frontier.sort_by(|a, b| paths[a].cmp(&paths[b]));
+            let mut next = Vec::new();
+            for node_id in frontier {
+                let parent = paths[&node_id].clone();
+                let mut targets: Vec<(String, NodeId)> = self
+                    .out_edges(node_id)
+                    .iter()
+                    .filter_map(|edge| self.nodes.get(&edge.target))
+                    .map(|target| (segment(target), target.id))
+                    .collect();
+                targets.sort();
+                for (name, target) in targets {
+                    if let Entry::Vacant(entry) = paths.entry(target) {
+                        entry.insert(format!("{parent}{LABEL_PATH_SEPARATOR}{name}"));
+                        next.push(target);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        paths
+    }
+
+    /// The name of the graph: its root's graph name, or its metadata name
+    fn graph_name(&self) -> &str {
+        self.nodes
+            .get(&self.composition_root)
+            .and_then(LabelIndex::graph_name)
+            .unwrap_or(&self.metadata.name)
+    }
+
+    /// The qualified path of a node named `name` directly under this graph
+    fn child_path(&self, name: &str) -> String {
+        format!("{}{LABEL_PATH_SEPARATOR}{name}", self.graph_name())
+    }
+
+    /// Every node and edge of either graph, with matching nodes merged
+    ///
+    /// Nodes of `other` are matched to nodes of this graph by `identity`.
+    /// Matched nodes keep this graph's ID, type and label, with data
+    /// reconciled by `reconcile` (failing with `DataConflict` if it cannot);
+    /// unmatched nodes of `other` are added, re-keyed where their IDs are
+    /// taken, and an edge present in both is kept once. The result is rooted
+    /// at this graph's root and keeps its composition type.
+    pub fn union(
+        &self,
+        other: &Self,
+        identity: NodeIdentity,
+        reconcile: DataMerge,
+    ) -> Result<Self, CompositionError> {
+        let pairs = self.matching_nodes(other, identity)?;
+        let mut result = self.merge_along(other, &pairs, &reconcile)?;
+        result.id = GraphId::from_uuid(
+            result
+                .id_strategy
+                .generate(&format!("union:{}:{}", self.id, other.id)),
+        );
+        Ok(result)
+    }
+
+    /// The nodes of this graph that `other` also has, and the edges between
+    /// them that `other` also has
+    ///
+    /// Edges match when they join matching nodes with the same relationship.
+    /// Node data is reconciled as in `union`. The result is rooted at this
+    /// graph's root if it is shared, otherwise at the node `other`'s root
+    /// matches, otherwise at a shared node no other shared node points at.
+    /// Dangling edges are left out. Fails if the graphs share no node.
+    pub fn intersection(
+        &self,
+        other: &Self,
+        identity: NodeIdentity,
+        reconcile: DataMerge,
+    ) -> Result<Self, CompositionError> {
+        let pairs = self.matching_nodes(other, identity)?;
+        let counterparts: HashMap<NodeId, NodeId> = pairs.iter().copied().collect();
+        let other_root = pairs
+            .iter()
+            .find(|(_, theirs)| *theirs == other.composition_root)
+            .map(|(ours, _)| *ours);
+        let root = if counterparts.contains_key(&self.composition_root) {
+            self.composition_root
+        } else if let Some(ours) = other_root {
+            ours
+        } else {
+            counterparts
+                .keys()
+                .copied()
+                .min_by_key(|node_id| {
+                    let entered = self.in_edges(*node_id).iter().any(|edge| {
+                        edge.source != *node_id && counterparts.contains_key(&edge.source)
+                    });
+                    (entered, *node_id)
+                })
+                .ok_or_else(|| {
+                    CompositionError::InvalidComposition("the graphs share no node".to_string())
+                })?
+        };
+
+        let mut result = self.clone();
+        result.id = GraphId::from_uuid(
+            result
+                .id_strategy
+                .generate(&format!("intersection:{}:{}", self.id, other.id)),
+        );
+        result.set_composition_root(root)?;
+        result.retain_nodes(|node| counterparts.contains_key(&node.id))?;
+
+        let unshared: Vec<EdgeId> = result
+            .edges
+            .values()
+            .filter(|edge| {
+                // Dangling edges have no counterpart either
+                let (Some(source), Some(target)) = (
+                    counterparts.get(&edge.source),
+                    counterparts.get(&edge.target),
+                ) else {
+                    return true;
+                };
+                !other.out_edges(*source).into_iter().any(|theirs| {
+                    theirs.target == *target
+                        && same_content(&theirs.relationship, &edge.relationship)
+                })
+            })
+            .map(|edge| edge.id)
+            .collect();
+        for edge_id in unshared {
+            result.remove_edge(edge_id);
+        }
+
+        for (ours, theirs) in &pairs {
+            let mut node = result.nodes[ours].clone();
+            node.data = reconcile
+                .merge(&node.data, &other.nodes[theirs].data)
+                .ok_or(CompositionError::DataConflict(*ours))?;
+            result.insert_node(node);
+        }
+        Ok(result)
+    }
+
+    /// What this graph has and `other` lacks
+    ///
+    /// Keeps the nodes with no match in `other`, the edges `other` does not
+    /// also have (see `intersection`), and the endpoints of those edges. The
+    /// composition root is always kept so the result stays rooted.
+    pub fn difference(
+        &self,
+        other: &Self,
+        identity: NodeIdentity,
+    ) -> Result<Self, CompositionError> {
+        let counterparts: HashMap<NodeId, NodeId> =
+            self.matching_nodes(other, identity)?.into_iter().collect();
+
+        let mut result = self.clone();
+        result.id = GraphId::from_uuid(
+            result
+                .id_strategy
+                .generate(&format!("difference:{}:{}", self.id, other.id)),
+        );
+
+        let shared: Vec<EdgeId> = self
+            .edges
+            .values()
+            .filter(|edge| {
+                let (Some(source), Some(target)) = (
+                    counterparts.get(&edge.source),
+                    counterparts.get(&edge.target),
+                ) else {
+                    return false;
+                };
+                other.out_edges(*source).into_iter().any(|theirs| {
+                    theirs.target == *target
+                        && same_content(&theirs.relationship, &edge.relationship)
+                })
+            })
+            .map(|edge| edge.id)
+            .collect();
+        for edge_id in shared {
+            result.remove_edge(edge_id);
+        }
+
+        let endpoints: HashSet<NodeId> = result
+            .edges
+            .values()
+            .flat_map(|edge| [edge.source, edge.target])
+            .collect();
+        result.retain_nodes(|node| {
+            node.id == self.composition_root
+                || !counterparts.contains_key(&node.id)
+                || endpoints.contains(&node.id)
+        })?;
+        Ok(result)
+    }
+
+    /// An unused node ID for a node with the given qualified label path,
+    /// drawn from the ID strategy
+    fn fresh_node_id(&mut self, path: &str) -> NodeId {
+        if self.id_strategy.is_random() {
+            NodeId::new()
+        } else {
+            self.next_node_id(path)
+        }
+    }
+
+    /// Copy every node and edge of `other` into this graph
+    ///
+    /// Nodes and edges whose IDs are already taken are re-keyed through the
+    /// ID strategy, so graphs built with the same seed or namespace do not
+    /// overwrite each other. Returns where each of `other`'s nodes ended up.
+    fn absorb(&mut self, other: &Self) -> HashMap<NodeId, NodeId> {
+        let paths = other.id_paths();
+        let mut nodes: Vec<&CompositionNode<N>> = other.nodes.values().collect();
+        nodes.sort_by_key(|node| node.id);
+
+        let mut ids = HashMap::with_capacity(nodes.len());
+        for node in nodes {
+            let mut node = node.clone();
+            let original = node.id;
+            if self.nodes.contains_key(&node.id) {
+                node.id = self.fresh_node_id(&self.child_path(&paths[&original]));
+            }
+            ids.insert(original, node.id);
+            self.insert_node(node);
+        }
+
+        let mut edges: Vec<&CompositionEdge<R>> = other.edges.values().collect();
+        edges.sort_by_key(|edge| edge.id);
+        for edge in edges {
+            let mut edge = edge.clone();
+            edge.source = ids.get(&edge.source).copied().unwrap_or(edge.source);
+            edge.target = ids.get(&edge.target).copied().unwrap_or(edge.target);
+            if self.edges.contains_key(&edge.id) {
+                edge.id = self.fresh_edge_id(
+                    edge.source,
+                    edge.target,
+                    &edge.relationship.relationship_type,
+                );
+            }
+            self.insert_edge(edge);
+        }
+        ids
+    }
+
+    /// An unused ID for an edge from `source` to `target`, drawn from the ID
+    /// strategy
+    fn fresh_edge_id(&mut self, source: NodeId, target: NodeId, relationship: &R) -> EdgeId {
+        if self.id_strategy.is_random() {
+            EdgeId::new()
+        } else {
+            let name = Self::edge_name(&source, &target, relationship);
+            self.next_edge_id(&name)
+        }
+    }
+}
+
+impl<N, R> GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de> + StructuralRelationship,
+{
+    /// Remove a node together with everything it transitively `Contains`
+    pub fn remove_subgraph(
+        &mut self,
+        root: NodeId,
+    ) -> Result<RemovedElements<N, R>, CompositionError> {
+        self.remove_subgraph_where(root, R::is_contains)
+    }
+}
+
+/// Named constructors for graphs of any conforming node type
+///
+/// `GraphComposition<BaseNodeType, BaseRelationshipType>` has inherent
+/// versions of these, so `GraphComposition::composite("Order")` needs no type
+/// annotations. Other node and relationship types go through this trait, as in
+/// `GraphComposition::<MyNode, MyRelation>::composite("Order")`.
+pub trait GraphConstructors: Sized {
+    /// Create an atomic graph (single node, no edges)
+    fn atomic(value_type: &str, data: JsonValue) -> Self;
+
+    /// Create a composite graph
+    fn composite(structure_type: &str) -> Self;
+
+    /// Create an entity graph
+    fn entity(entity_type: &str, entity_id: impl Into<String>) -> Self;
+
+    /// Create an aggregate graph
+    fn aggregate(aggregate_type: &str, aggregate_id: impl Into<String>) -> Self;
+}
+
+impl<N, R> GraphConstructors for GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de> + CompositeRootType,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    fn atomic(value_type: &str, data: JsonValue) -> Self {
+        let mut graph = Self::new(
+            N::value_root(),
+            CompositionType::Atomic {
+                value_type: value_type.to_string(),
+            },
+        );
+        graph.set_graph_name(value_type);
+
+        // Update root node with data
+        graph.set_root(value_type, data);
+
+        graph
+    }
+
+    fn composite(structure_type: &str) -> Self {
+        let mut graph = Self::new(
+            N::composite_root(),
+            CompositionType::Composite {
+                structure_type: structure_type.to_string(),
+            },
+        );
+        graph.set_graph_name(structure_type);
+        graph
+    }
+
+    fn entity(entity_type: &str, entity_id: impl Into<String>) -> Self {
+        let mut graph = Self::new(
+            N::entity_root(),
+            CompositionType::Domain(DomainCompositionType::Entity {
+                entity_type: entity_type.to_string(),
+            }),
+        );
+        graph.set_graph_name(entity_type);
+
+        // Update root node with entity ID
+        graph.set_root(entity_type, serde_json::json!({ "id": entity_id.into() }));
+
+        graph
+    }
+
+    fn aggregate(aggregate_type: &str, aggregate_id: impl Into<String>) -> Self {
+        let mut graph = Self::new(
+            N::aggregate_root(),
+            CompositionType::Domain(DomainCompositionType::Aggregate {
+                aggregate_type: aggregate_type.to_string(),
+            }),
+        );
+        graph.set_graph_name(aggregate_type);
+
+        // Update root node with aggregate ID
+        graph.set_root(
+            aggregate_type,
+            serde_json::json!({ "id": aggregate_id.into() }),
+        );
+
+        graph
+    }
+}
+
+// Specialized constructors for BaseNodeType
+impl GraphComposition<BaseNodeType, BaseRelationshipType> {
+    /// Create an atomic graph (single node, no edges)
+    pub fn atomic(value_type: &str, data: JsonValue) -> Self {
+        <Self as GraphConstructors>::atomic(value_type, data)
+    }
+
+    /// Create a composite graph
+    pub fn composite(structure_type: &str) -> Self {
+        <Self as GraphConstructors>::composite(structure_type)
+    }
+
+    /// Create an entity graph
+    pub fn entity(entity_type: &str, entity_id: impl Into<String>) -> Self {
+        <Self as GraphConstructors>::entity(entity_type, entity_id)
+    }
+
+    /// Create an aggregate graph
+    pub fn aggregate(aggregate_type: &str, aggregate_id: impl Into<String>) -> Self {
+        <Self as GraphConstructors>::aggregate(aggregate_type, aggregate_id)
+    }
+}
+
+// Composition operators for any structural relationship type
+impl<N, R> GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de> + StructuralRelationship,
+{
+    /// Connect the root to each branch's entries and expose their exits
+    ///
+    /// Branches without ports are entered at their root. Output ports are only
+    /// declared when a branch declared ports; exits with the same name join.
+    /// `ids` maps each branch's node IDs to where they ended up in self.
+    fn wire_branches(
+        &mut self,
+        branches: &[(&GraphComposition<N, R>, Relationship<R>)],
+        ids: &[HashMap<NodeId, NodeId>],
+    ) {
+        let root = self.composition_root;
+        for ((branch, relationship), ids) in branches.iter().zip(ids) {
+            let mut entries = branch.entry_ports();
+            Ports::rekey_nodes(&mut entries, ids);
+            for entry in entries.values().flatten() {
+                self.connect(root, *entry, relationship.clone());
+            }
+        }
+
+        if branches.iter().any(|(branch, _)| !branch.ports.is_empty()) {
+            for ((branch, _), ids) in branches.iter().zip(ids) {
+                let mut exits = branch.exit_ports();
+                Ports::rekey_nodes(&mut exits, ids);
+                Ports::merge(&mut self.ports.outputs, exits);
+            }
+        }
+    }
+
+    /// Sequential composition: self then other
+    ///
+    /// Wires self's output ports to other's input ports (see `exit_ports` and
+    /// `entry_ports`); without declared ports, every leaf of self leads to
+    /// other's root.
+    pub fn then(
+        &self,
+        other: &GraphComposition<N, R>,
+    ) -> Result<GraphComposition<N, R>, CompositionError> {
+        let mut result = self.clone();
+        result.id = GraphId::from_uuid(
+            result
+                .id_strategy
+                .generate(&format!("then:{}:{}", self.id, other.id)),
+        );
+
+        // Add all nodes and edges from other, re-keying IDs self already uses
+        let ids = result.absorb(other);
+
+        // Connect self's exits to other's entries: same-named ports pairwise,
+        // or every exit to every entry when no names match (by default, the
+        // leaves to the root)
+        let exits = self.exit_ports();
+        let mut entries = other.entry_ports();
+        Ports::rekey_nodes(&mut entries, &ids);
+        let matched: Vec<&String> = exits
+            .keys()
+            .filter(|name| entries.contains_key(*name))
+            .collect();
+        if matched.is_empty() {
+            for exit in exits.values().flatten() {
+                for entry in entries.values().flatten() {
+                    result.connect(*exit, *entry, Relationship::new(R::sequence()));
+                }
+            }
+        }
+        for port in matched {
+            for exit in &exits[port] {
+                for entry in &entries[port] {
+                    let relationship = Relationship::new(R::sequence())
+                        .with_metadata("port".to_string(), JsonValue::String(port.clone()));
+                    result.connect(*exit, *entry, relationship);
+                }
+            }
+        }
+
+        // The result is entered like self and left like other
+        if !(self.ports.is_empty() && other.ports.is_empty()) {
+            let mut outputs = other.exit_ports();
+            Ports::rekey_nodes(&mut outputs, &ids);
+            result.ports = Ports {
+                inputs: self.entry_ports(),
+                outputs,
+            };
+        }
+
+        result.composition_type = CompositionType::Composite {
+            structure_type: "Sequential".to_string(),
+        };
+
+        let acyclic = self.is_acyclic_enforced() || other.is_acyclic_enforced();
+        if acyclic && result.has_cycle() {
+            return Err(CompositionError::CycleDetected);
+        }
+        if acyclic && !result.is_acyclic_enforced() {
+            result = result.with_invariant_rule(Invariant::acyclic());
+        }
+
+        Ok(result)
+    }
+
+    /// Parallel composition: self and other
+    pub fn parallel(
+        &self,
+        other: &GraphComposition<N, R>,
+    ) -> Result<GraphComposition<N, R>, CompositionError>
+    where
+        N: CompositeRootType,
+    {
+        Ok(Self::parallel_all([self, other]))
+    }
+
+    /// Choice composition: self or other
+    pub fn choice(
+        &self,
+        other: &GraphComposition<N, R>,
+    ) -> Result<GraphComposition<N, R>, CompositionError>
+    where
+        N: CompositeRootType,
+    {
+        Ok(Self::choice_all([self, other]))
+    }
+
+    /// Parallel composition of any number of branches under one fork node
+    ///
+    /// Folding with `parallel` adds a wrapper root at every step; here every
+    /// branch hangs off the same root. The ID strategy is taken from the first
+    /// branch.
+    pub fn parallel_all<I, B>(branches: I) -> GraphComposition<N, R>
+    where
+        I: IntoIterator<Item = B>,
+        B: Borrow<GraphComposition<N, R>>,
+        N: CompositeRootType,
+    {
+        let branches: Vec<B> = branches.into_iter().collect();
+        let branches: Vec<(&Self, Relationship<R>)> = branches
+            .iter()
+            .map(|branch| (branch.borrow(), Relationship::new(R::parallel())))
+            .collect();
+        Self::fork("Parallel", &branches)
+    }
+
+    /// Choice between any number of branches under one fork node
+    ///
+    /// See `parallel_all`. Every branch is always enabled; use `choice_with`
+    /// to guard them.
+    pub fn choice_all<I, B>(branches: I) -> GraphComposition<N, R>
+    where
+        I: IntoIterator<Item = B>,
+        B: Borrow<GraphComposition<N, R>>,
+        N: CompositeRootType,
+    {
+        let branches: Vec<B> = branches.into_iter().collect();
+        let branches: Vec<(&Self, Relationship<R>)> = branches
+            .iter()
+            .map(|branch| (branch.borrow(), Relationship::new(R::choice())))
+            .collect();
+        Self::fork("Choice", &branches)
+    }
+
+    /// Choice between guarded branches under one fork node
+    ///
+    /// Each guard is stored on the edges leading to its branch, together with
+    /// the branch's position (see `evaluate_choice`). Fails if the
+    /// probabilities are incomplete or do not sum to 1.
+    pub fn choice_with<I, B>(branches: I) -> Result<GraphComposition<N, R>, CompositionError>
+    where
+        I: IntoIterator<Item = (Guard, B)>,
+        B: Borrow<GraphComposition<N, R>>,
+        N: CompositeRootType,
+    {
+        let branches: Vec<(Guard, B)> = branches.into_iter().collect();
+        check_probabilities(branches.iter().map(|(guard, _)| guard))?;
+
+        let branches: Vec<(&Self, Relationship<R>)> = branches
+            .iter()
+            .enumerate()
+            .map(|(position, (guard, branch))| {
+                let relationship = guard.annotate(Relationship::new(R::choice()), position);
+                (branch.borrow(), relationship)
+            })
+            .collect();
+        Ok(Self::fork("Choice", &branches))
+    }
+
+    /// A new composite root named `kind` linked to every branch
+    fn fork(kind: &str, branches: &[(&Self, Relationship<R>)]) -> Self
+    where
+        N: CompositeRootType,
+    {
+        let mut result = <Self as GraphConstructors>::composite(kind);
+        if let Some(root) = result.nodes.get_mut(&result.composition_root) {
+            root.metadata
+                .insert(FORK_KEY.to_string(), JsonValue::String(kind.to_string()));
+        }
+        if let Some((first, _)) = branches.first() {
+            result.id_strategy = first.id_strategy.clone();
+        }
+        let prefix = kind.to_lowercase();
+        let ids: Vec<String> = branches.iter().map(|(b, _)| b.id.to_string()).collect();
+        result.id = GraphId::from_uuid(
+            result
+                .id_strategy
+                .generate(&format!("{prefix}:{}", ids.join(":"))),
+        );
+        let roots: Vec<String> = branches
+            .iter()
+            .map(|(b, _)| b.composition_root.to_string())
+            .collect();
+        result.derive_root_id(&format!("{prefix}:{}", roots.join(":")));
+
+        // Add all nodes and edges from every branch, re-keying IDs already
+        // used by the new root or an earlier branch
+        let ids: Vec<HashMap<NodeId, NodeId>> = branches
+            .iter()
+            .map(|(branch, _)| result.absorb(branch))
+            .collect();
+
+        // Connect the new root to every branch's entries
+        result.wire_branches(branches, &ids);
+
+        result
+    }
+
+    /// The branches of a choice node and their guards, in branch order
+    ///
+    /// Branches are the targets of the node's outgoing choice edges. Edges
+    /// without a stored position come last, ordered by edge ID.
+    pub fn choice_branches(
+        &self,
+        node_id: NodeId,
+    ) -> Result<Vec<(NodeId, Guard)>, CompositionError> {
+        if !self.nodes.contains_key(&node_id) {
+            return Err(CompositionError::NodeNotFound(node_id));
+        }
+
+        let mut edges: Vec<&CompositionEdge<R>> = self
+            .out_edges(node_id)
+            .into_iter()
+            .filter(|edge| edge.relationship.relationship_type == R::choice())
+            .collect();
+        edges.sort_by_key(|edge| {
+            let position = edge.relationship.metadata.get(BRANCH_KEY);
+            (
+                position.and_then(JsonValue::as_u64).unwrap_or(u64::MAX),
+                edge.id,
+            )
+        });
+        edges
+            .into_iter()
+            .map(|edge| Ok((edge.target, Guard::from_relationship(&edge.relationship)?)))
+            .collect()
+    }
+
+    /// The branches a choice node may take for `context`, with their probabilities
+    ///
+    /// A branch is enabled when its condition holds; `Otherwise` branches are
+    /// enabled only when no other branch is. Probabilities are renormalized
+    /// over the enabled branches, which share equally when none are given.
+    pub fn evaluate_choice(
+        &self,
+        node_id: NodeId,
+        context: &JsonValue,
+    ) -> Result<Vec<(NodeId, f64)>, CompositionError> {
+        let branches = self.choice_branches(node_id)?;
+        check_probabilities(branches.iter().map(|(_, guard)| guard))?;
+
+        let mut enabled: Vec<&(NodeId, Guard)> = branches
+            .iter()
+            .filter(|(_, guard)| guard.condition.holds(context))
+            .collect();
+        if enabled.is_empty() {
+            enabled = branches
+                .iter()
+                .filter(|(_, guard)| guard.condition == Condition::Otherwise)
+                .collect();
+        }
+
+        let total: f64 = enabled.iter().filter_map(|(_, g)| g.probability).sum();
+        let share = 1.0 / enabled.len() as f64;
+        Ok(enabled
+            .into_iter()
+            .map(|(target, guard)| {
+                let probability = match guard.probability {
+                    Some(p) if total > 0.0 => p / total,
+                    _ => share,
+                };
+                (*target, probability)
+            })
+            .collect())
+    }
+
+    /// The branch a choice node selects for `context`
+    ///
+    /// The most probable enabled branch, and among equals the first, so a
+    /// choice guarded only by conditions takes the first branch whose
+    /// condition holds. `None` if no branch is enabled.
+    pub fn select_branch(
+        &self,
+        node_id: NodeId,
+        context: &JsonValue,
+    ) -> Result<Option<NodeId>, CompositionError> {
+        let mut selected: Option<(NodeId, f64)> = None;
+        for (target, probability) in self.evaluate_choice(node_id, context)? {
+            if selected.is_none_or(|(_, best)| probability > best) {
+                selected = Some((target, probability));
+            }
+        }
+        Ok(selected.map(|(target, _)| target))
+    }
+
+    /// Merge nested `Parallel` and `Choice` wrapper roots into their parent fork
+    ///
+    /// A wrapper root, as left behind by folding with `parallel` or `choice`
+    /// and marked with `FORK_KEY`, is merged when its only incoming edge has the same kind as its outgoing
+    /// edges: its outgoing edges move to the parent and the wrapper is removed.
+    /// Wrappers named in a port, guarded choices and the composition root are
+    /// kept.
+    pub fn flatten_forks(mut self) -> Self {
+        let mut wrappers: Vec<NodeId> = self
+            .nodes
+            .values()
+            .filter(|node| fork_kind(node).is_some())
+            .map(|node| node.id)
+            .collect();
+        wrappers.sort();
+
+        for wrapper in wrappers {
+            let Some(parent) = self.fork_parent(wrapper) else {
+                continue;
+            };
+            for edge_id in self.adjacency.outgoing(wrapper).to_vec() {
+                if let Some(mut edge) = self.remove_edge(edge_id) {
+                    edge.source = parent;
+                    self.insert_edge(edge);
+                }
+            }
+            let _ = self.remove_node(wrapper, EdgeRemovalPolicy::Cascade);
+        }
+        self
+    }
+
+    /// The fork a wrapper root can be merged into, if any
+    fn fork_parent(&self, wrapper: NodeId) -> Option<NodeId> {
+        let in_ports = self
+            .ports
+            .inputs
+            .values()
+            .chain(self.ports.outputs.values())
+            .flatten()
+            .any(|node_id| *node_id == wrapper);
+        if wrapper == self.composition_root || in_ports {
+            return None;
+        }
+
+        let incoming = self.in_edges(wrapper);
+        let [edge] = incoming.as_slice() else {
+            return None;
+        };
+        let kind = &edge.relationship.relationship_type;
+        let forks = *kind == R::parallel() || *kind == R::choice();
+        let outgoing = self.out_edges(wrapper);
+        let same_kind = outgoing
+            .iter()
+            .all(|out| out.relationship.relationship_type == *kind);
+        // Guards and probabilities do not carry over to a merged fork
+        let guarded = outgoing.iter().chain([edge]).any(|e| {
+            let metadata = &e.relationship.metadata;
+            metadata.contains_key(GUARD_KEY) || metadata.contains_key(PROBABILITY_KEY)
+        });
+        (forks && same_kind && !guarded && !outgoing.is_empty() && edge.source != wrapper)
+            .then_some(edge.source)
+    }
+
+    /// Run the graph `times` times
+    ///
+    /// Builds a loop (see the `loops` module): a header enters the graph,
+    /// its exits lead back to the header, and the loop is left through the
+    /// header.
+    pub fn repeat(&self, times: usize) -> Result<GraphComposition<N, R>, CompositionError>
+    where
+        N: CompositeRootType,
+    {
+        if times == 0 {
+            return Err(CompositionError::InvalidComposition(
+                "repeat needs at least one iteration".to_string(),
+            ));
+        }
+        Ok(self.loop_over("Repeat", LoopKind::Repeat { times }))
+    }
+
+    /// Run the graph as long as the guard's condition holds
+    pub fn while_guard(&self, guard: Guard) -> Result<GraphComposition<N, R>, CompositionError>
+    where
+        N: CompositeRootType,
+    {
+        if let Some(p) = guard.probability.filter(|p| !(0.0..=1.0).contains(p)) {
+            return Err(CompositionError::InvalidProbability(format!(
+                "{p} is not between 0 and 1"
+            )));
+        }
+        Ok(self.loop_over("While", LoopKind::While { guard }))
+    }
+
+    /// Run the graph once per element of the array at `collection_path`, a
+    /// JSON pointer into the context
+    pub fn for_each(
+        &self,
+        collection_path: &str,
+    ) -> Result<GraphComposition<N, R>, CompositionError>
+    where
+        N: CompositeRootType,
+    {
+        if !(collection_path.is_empty() || collection_path.starts_with('/')) {
+            return Err(CompositionError::InvalidComposition(format!(
+                "{collection_path} is not a JSON pointer"
+            )));
+        }
+        let collection = collection_path.to_string();
+        Ok(self.loop_over("ForEach", LoopKind::ForEach { collection }))
+    }
+
+    /// The loop a header node runs, if it is one
+    pub fn loop_kind(&self, node_id: NodeId) -> Option<LoopKind> {
+        let kind = self.nodes.get(&node_id)?.metadata.get(LOOP_KEY)?;
+        serde_json::from_value(kind.clone()).ok()
+    }
+
+    /// Wrap the graph in a loop header named `kind`
+    fn loop_over(&self, kind: &str, spec: LoopKind) -> Self
+    where
+        N: CompositeRootType,
+    {
+        let mut result = <Self as GraphConstructors>::composite(kind);
+        result.id_strategy = self.id_strategy.clone();
+        let prefix = kind.to_lowercase();
+        result.id = GraphId::from_uuid(
+            result
+                .id_strategy
+                .generate(&format!("{prefix}:{}", self.id)),
+        );
+        result.derive_root_id(&format!("{prefix}:{}", self.composition_root));
+
+        let header = result.composition_root;
+        if let Some(root) = result.nodes.get_mut(&header) {
+            let spec = serde_json::to_value(&spec).unwrap_or(JsonValue::Null);
+            root.metadata.insert(LOOP_KEY.to_string(), spec);
+        }
+
+        let ids = result.absorb(self);
+        let mut entries = self.entry_ports();
+        let mut exits = self.exit_ports();
+        Ports::rekey_nodes(&mut entries, &ids);
+        Ports::rekey_nodes(&mut exits, &ids);
+
+        // Enter the body from the header, and return to it from every exit
+        for entry in entries.values().flatten() {
+            result.connect(header, *entry, Relationship::new(R::sequence()));
+        }
+        for exit in exits.values().flatten() {
+            result.connect(*exit, header, Relationship::new(R::loop_back()).back_edge());
+        }
+        result.ports.outputs = BTreeMap::from([(DEFAULT_OUTPUT_PORT.to_string(), vec![header])]);
+
+        result
+    }
+}
+
+impl<N, R> Composable for GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    type Output = GraphComposition<N, R>;
+
+    /// Merge `other` into `self` with the default `ComposeStrategy`
+    fn compose(&self, other: &Self) -> Result<Self::Output, CompositionError> {
+        self.compose_with(other, ComposeStrategy::default())
+    }
+
+    fn can_compose_with(&self, other: &Self) -> bool {
+        self.check_composable(other).is_ok()
+    }
+}
+
+/// Morphism between graphs
+pub trait GraphMorphism<N1, R1, N2, R2>: Send + Sync
+where
+    N1: Clone + Serialize + for<'de> Deserialize<'de>,
+    R1: Clone + Serialize + for<'de> Deserialize<'de>,
+    N2: Clone + Serialize + for<'de> Deserialize<'de>,
+    R2: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    fn apply(
+        &self,
+        graph: &GraphComposition<N1, R1>,
+    ) -> Result<GraphComposition<N2, R2>, CompositionError>;
+}
+
+/// Functor trait for structure-preserving maps
+pub trait GraphFunctor<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    fn fmap<F, N2>(&self, f: F) -> GraphComposition<N2, R>
+    where
+        F: Fn(&CompositionNode<N>) -> CompositionNode<N2>,
+        N2: Clone + Serialize + for<'de> Deserialize<'de>;
+}
+
+impl<N, R> GraphFunctor<N, R> for GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    fn fmap<F, N2>(&self, f: F) -> GraphComposition<N2, R>
+    where
+        F: Fn(&CompositionNode<N>) -> CompositionNode<N2>,
+        N2: Clone + Serialize + for<'de> Deserialize<'de>,
+    {
+        self.clone().map_nodes(f)
+    }
+}
+
+/// Monad trait for composition with context
+pub trait GraphMonad<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    fn pure(value: CompositionNode<N>) -> GraphComposition<N, R>;
+
+    fn bind<F>(&self, f: F) -> Result<GraphComposition<N, R>, CompositionError>
+    where
+        F: Fn(&CompositionNode<N>) -> GraphComposition<N, R>;
+}
+
+impl<N, R> GraphMonad<N, R> for GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    /// A graph holding just the given node as its root
+    fn pure(value: CompositionNode<N>) -> GraphComposition<N, R> {
+        let root = value.id;
+        let label = value.label.clone();
+        GraphCompositionData {
+            id: GraphId::new(),
+            composition_root: root,
+            composition_type: CompositionType::Monad {
+                context_type: label.clone(),
+            },
+            nodes: HashMap::from([(root, value)]),
+            edges: HashMap::new(),
+            metadata: Metadata::new(label),
+            unique_labels: false,
+            invariants: Vec::new(),
+            id_strategy: IdStrategy::default(),
+            ports: Ports::default(),
+        }
+        .into()
+    }
+
+    fn bind<F>(&self, f: F) -> Result<GraphComposition<N, R>, CompositionError>
+    where
+        F: Fn(&CompositionNode<N>) -> GraphComposition<N, R>,
+    {
+        self.flat_map(f)
+    }
+}
+
+impl<N, R> GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Substitute every node with the graph `f` returns for it
+    ///
+    /// An edge `u -> v` becomes edges from every exit of `f(u)` (its output
+    /// ports, or its leaves; see `exit_ports`) to the root of `f(v)`, keeping
+    /// its relationship. Fails with `MonadError` if `f(u)` has no exit but `u`
+    /// has outgoing edges, as when `f(u)` is a cycle. The result is rooted at
+    /// the graph substituted for the composition root. IDs are kept where they
+    /// do not collide with elements already placed, and drawn from the ID
+    /// strategy otherwise, so `f` may return the same graph for several nodes.
+    ///
+    /// This is `bind` with a node type that may change, which `flatten` needs.
+    pub fn flat_map<F, N2>(&self, f: F) -> Result<GraphComposition<N2, R>, CompositionError>
+    where
+        F: Fn(&CompositionNode<N>) -> GraphComposition<N2, R>,
+        N2: Clone + Serialize + for<'de> Deserialize<'de>,
+    {
+        let mut result: GraphComposition<N2, R> = GraphCompositionData {
+            id: GraphId::new(),
+            composition_root: self.composition_root,
+            composition_type: CompositionType::Monad {
+                context_type: self.metadata.name.clone(),
+            },
+            nodes: HashMap::new(),
+            edges: HashMap::new(),
+            metadata: self.metadata.clone(),
+            unique_labels: false,
+            invariants: Vec::new(),
+            id_strategy: self.id_strategy.clone(),
+            ports: Ports::default(),
+        }
+        .into();
+        result.id = GraphId::from_uuid(result.id_strategy.generate(&format!("bind:{}", self.id)));
+
+        // Entry node and exit nodes of the graph substituted for each node
+        let mut ports: HashMap<NodeId, (NodeId, Vec<NodeId>)> = HashMap::new();
+
+        let mut nodes: Vec<&CompositionNode<N>> = self.nodes.values().collect();
+        nodes.sort_by_key(|node| node.id);
+        for node in nodes {
+            let substitute = f(node);
+            if !substitute.nodes.contains_key(&substitute.composition_root) {
+                return Err(CompositionError::MonadError(format!(
+                    "graph bound to node {} has no root node",
+                    node.id
+                )));
+            }
+
+            let ids = result.absorb(&substitute);
+            let rekey = |id: NodeId| ids.get(&id).copied().unwrap_or(id);
+
+            let entry = rekey(substitute.composition_root);
+            let exits: BTreeSet<NodeId> = substitute
+                .exit_ports()
+                .into_values()
+                .flatten()
+                .map(rekey)
+                .collect();
+            if exits.is_empty() && !self.out_edges(node.id).is_empty() {
+                return Err(CompositionError::MonadError(format!(
+                    "graph bound to node {} has no exit for its outgoing edges",
+                    node.id
+                )));
+            }
+            ports.insert(node.id, (entry, exits.into_iter().collect()));
+        }
+
+        let mut edges: Vec<&CompositionEdge<R>> = self.edges.values().collect();
+        edges.sort_by_key(|edge| edge.id);
+        for edge in edges {
+            let (_, exits) = ports
+                .get(&edge.source)
+                .ok_or(CompositionError::DanglingEdge(edge.id, edge.source))?;
+            let (entry, _) = ports
+                .get(&edge.target)
+                .ok_or(CompositionError::DanglingEdge(edge.id, edge.target))?;
+
+            for exit in exits {
+                let mut rewired = edge.clone();
+                rewired.source = *exit;
+                rewired.target = *entry;
+                if result.edges.contains_key(&rewired.id) {
+                    rewired.id = result.fresh_edge_id(
+                        rewired.source,
+                        rewired.target,
+                        &rewired.relationship.relationship_type,
+                    );
+                }
+                result.insert_edge(rewired);
+            }
+        }
+
+        result.composition_root = ports
+            .get(&self.composition_root)
+            .map(|(entry, _)| *entry)
+            .ok_or(CompositionError::NodeNotFound(self.composition_root))?;
+
+        Ok(result)
+    }
+}
+
+impl<N, R> GraphComposition<GraphComposition<N, R>, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Collapse a graph of graphs by substituting each node with the graph it carries
+    pub fn flatten(&self) -> Result<GraphComposition<N, R>, CompositionError> {
+        self.flat_map(|node| node.node_type.clone())
+    }
+}
+
+/// Helper function to create a line item graph
+///
+/// The line item is left through its root, so its value nodes are not wired
+/// to whatever follows it in a sequence.
+pub fn line_item_graph(product: &str, quantity: i32, price: f64) -> GraphComposition {
+    let mut graph = GraphComposition::composite("LineItem")
+        .add_node(BaseNodeType::Value, "product", serde_json::json!({ "name": product }))
+        .add_node(BaseNodeType::Value, "quantity", quantity)
+        .add_node(BaseNodeType::Value, "price", price)
+        .add_node(BaseNodeType::Value, "total", quantity as f64 * price);
+    let root = graph.composition_root;
+    Ports::add(&mut graph.ports.outputs, DEFAULT_OUTPUT_PORT, root);
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_graph_creation() {
+        let money = GraphComposition::atomic(
+            "Money",
+            serde_json::json!({ "amount": 100, "currency": "USD" }),
+        );
+
+        assert_eq!(money.nodes.len(), 1);
+        assert_eq!(money.edges.len(), 0);
+        assert!(matches!(
+            money.composition_type,
+            CompositionType::Atomic { .. }
+        ));
+    }
+
+    #[test]
+    fn test_composite_graph_creation() {
+        let address = GraphComposition::composite("Address")
+            .add_node(BaseNodeType::Value, "street", "123 Main St")
+            .add_node(BaseNodeType::Value, "city", "Springfield")
+            .add_node(BaseNodeType::Value, "zip", "12345")
+            .add_edge_by_label("root", "street", BaseRelationshipType::Contains)
+            .add_edge_by_label("root", "city", BaseRelationshipType::Contains)
+            .add_edge_by_label("root", "zip", BaseRelationshipType::Contains);
+
+        assert_eq!(address.nodes.len(), 4); // root + 3 nodes
+        assert_eq!(address.edges.len(), 3);
+        assert!(matches!(
+            address.composition_type,
+            CompositionType::Composite { .. }
+        ));
+    }
+
+    #[test]
+    fn test_entity_creation() {
+        let user = GraphComposition::entity("User", "user-123");
+
+        assert_eq!(user.nodes.len(), 1);
+        assert!(matches!(
+            user.composition_type,
+            CompositionType::Domain(DomainCompositionType::Entity { .. })
+        ));
+
+        // Check that the entity has an ID
+        let root_node = &user.nodes[&user.composition_root];
+        if let JsonValue::Object(ref map) = root_node.data {
+            assert_eq!(map.get("id").unwrap(), "user-123");
+        } else {
+            panic!("Entity should have object data");
+        }
+    }
+
+    #[test]
+    fn test_sequential_composition() {
+        let validate = GraphComposition::composite("ValidateOrder");
+        let calculate = GraphComposition::composite("CalculatePricing");
+
+        let workflow = validate.then(&calculate).unwrap();
+
+        // Should have nodes from both graphs plus connections
+        assert!(workflow.nodes.len() >= 2);
+        assert!(workflow.edges.len() >= 1); // At least one sequence edge
+    }
+
+    #[test]
+    fn test_parallel_composition() {
+        let check_inventory = GraphComposition::composite("CheckInventory");
+        let verify_payment = GraphComposition::composite("VerifyPayment");
+
+        let parallel = check_inventory.parallel(&verify_payment).unwrap();
+
+        // Should have a new root connected to both subgraphs
+        assert!(parallel.nodes.len() >= 3); // new root + 2 subgraph roots
+        assert!(parallel.edges.len() >= 2); // 2 parallel edges
+    }
+
+    #[test]
+    fn test_generic_graph_composition() {
+        // Create a graph with custom node and relationship types
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        enum CustomNode {
+            Start,
+            Process,
+            End,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        enum CustomRelation {
+            Next,
+            Error,
+        }
+
+        let graph = GraphComposition::<CustomNode, CustomRelation>::new(
+            CustomNode::Start,
+            CompositionType::Composite {
+                structure_type: "Workflow".to_string(),
+            },
+        )
+        .add_node(CustomNode::Process, "process", serde_json::json!({}))
+        .add_node(CustomNode::End, "end", serde_json::json!({}));
+
+        assert_eq!(graph.nodes.len(), 3);
+    }
+
+    #[test]
+    fn test_operators_on_custom_types() {
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        enum Step {
+            Flow,
+            Task,
+        }
+
+        impl CompositeRootType for Step {
+            fn composite_root() -> Self {
+                Step::Flow
+            }
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        enum Link {
+            Next,
+            Fork,
+            Either,
+            Owns,
+        }
+
+        impl StructuralRelationship for Link {
+            fn sequence() -> Self {
+                Link::Next
+            }
+            fn parallel() -> Self {
+                Link::Fork
+            }
+            fn choice() -> Self {
+                Link::Either
+            }
+            fn contains() -> Self {
+                Link::Owns
+            }
+        }
+
+        type Flow = GraphComposition<Step, Link>;
+        let build = Flow::composite("Build")
+            .add_node(Step::Task, "compile", serde_json::json!({}))
+            .add_edge_by_label("root", "compile", Link::Owns);
+        let test = Flow::composite("Test");
+
+        let sequence = build.then(&test).unwrap();
+        let compile = build.node_by_label("compile").unwrap().id;
+        assert_eq!(
+            sequence.out_edges(compile)[0]
+                .relationship
+                .relationship_type,
+            Link::Next
+        );
+
+        let both = build.parallel(&test).unwrap();
+        assert_eq!(both.nodes[&both.composition_root].node_type, Step::Flow);
+        assert!(both
+            .out_edges(both.composition_root)
+            .iter()
+            .all(|edge| edge.relationship.relationship_type == Link::Fork));
+        let either = build.choice(&test).unwrap();
+        assert_eq!(either.out_degree(either.composition_root), 2);
+
+        let mut pruned = sequence;
+        let removed = pruned.remove_subgraph(compile).unwrap();
+        assert_eq!(removed.nodes.len(), 1);
+        assert!(Flow::entity("Job", "j-1")
+            .nodes
+            .values()
+            .all(|n| n.node_type == Step::Flow));
+    }
+
+    #[test]
+    fn test_functor_map() {
+        let graph = GraphComposition::composite("Test")
+            .add_node(BaseNodeType::Value, "a", serde_json::json!({ "value": 1 }))
+            .add_node(BaseNodeType::Value, "b", serde_json::json!({ "value": 2 }));
+
+        let doubled = graph.fmap(|node| {
+            let mut new_node = node.clone();
+            if let JsonValue::Object(ref mut map) = new_node.data {
+                if let Some(JsonValue::Number(n)) = map.get("value") {
+                    if let Some(v) = n.as_i64() {
+                        map.insert("value".to_string(), serde_json::json!(v * 2));
+                    }
+                }
+            }
+            new_node
+        });
+
+        // Verify values were doubled
+        for node in doubled.nodes.values() {
+            if let JsonValue::Object(ref map) = node.data {
+                if let Some(JsonValue::Number(n)) = map.get("value") {
+                    let v = n.as_i64().unwrap_or(0);
+                    assert!(v == 0 || v == 2 || v == 4); // 0 for root, 2 and 4 for doubled values
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_adjacency_queries() {
+        let address = GraphComposition::composite("Address")
+            .add_node(BaseNodeType::Value, "street", "123 Main St")
+            .add_node(BaseNodeType::Value, "city", "Springfield")
+            .add_edge_by_label("root", "street", BaseRelationshipType::Contains)
+            .add_edge_by_label("root", "city", BaseRelationshipType::Contains);
+
+        let root = address.composition_root;
+        let street = address
+            .nodes
+            .values()
+            .find(|n| n.label == "street")
+            .unwrap()
+            .id;
+
+        assert_eq!(address.out_degree(root), 2);
+        assert_eq!(address.in_degree(root), 0);
+        assert_eq!(address.in_degree(street), 1);
+        assert_eq!(address.out_edges(root).len(), 2);
+        assert_eq!(address.in_edges(street)[0].source, root);
+        assert_eq!(address.find_roots(), vec![root]);
+        assert_eq!(address.find_leaves().len(), 2);
+    }
+
+    #[test]
+    fn test_adjacency_survives_composition_and_serde() {
+        let validate = GraphComposition::composite("ValidateOrder")
+            .add_node(BaseNodeType::Value, "rules", "strict")
+            .add_edge_by_label("root", "rules", BaseRelationshipType::Contains);
+        let calculate = GraphComposition::composite("CalculatePricing");
+
+        let workflow = validate
+            .then(&calculate)
+            .unwrap()
+            .parallel(&GraphComposition::composite("Audit"))
+            .unwrap();
+
+        // Index agrees with a full edge scan for every node
+        for node_id in workflow.nodes.keys() {
+            let scanned = workflow
+                .edges
+                .values()
+                .filter(|e| e.source == *node_id)
+                .count();
+            assert_eq!(workflow.out_degree(*node_id), scanned);
+        }
+
+        let json = serde_json::to_string(&workflow).unwrap();
+        let restored: GraphComposition = serde_json::from_str(&json).unwrap();
+        for node_id in workflow.nodes.keys() {
+            assert_eq!(restored.out_degree(*node_id), workflow.out_degree(*node_id));
+            assert_eq!(restored.in_degree(*node_id), workflow.in_degree(*node_id));
+        }
+
+        let mapped = restored.map_nodes(|n| n.clone());
+        assert_eq!(mapped.out_degree(mapped.composition_root), 2);
+    }
+
+    #[test]
+    fn test_compose_with_self_does_not_duplicate_adjacency() {
+        let graph = GraphComposition::composite("Order")
+            .add_node(BaseNodeType::Value, "total", 10)
+            .add_edge_by_label("root", "total", BaseRelationshipType::Contains);
+
+        let composed = graph.compose(&graph).unwrap();
+
+        assert_eq!(composed.edges.len(), 1);
+        assert_eq!(composed.out_degree(composed.composition_root), 1);
+    }
+
+    #[test]
+    fn test_checked_edge_rejects_missing_nodes() {
+        let graph = GraphComposition::composite("Order");
+        let root = graph.composition_root;
+        let missing = NodeId::new();
+
+        let result = graph.try_add_edge(root, missing, BaseRelationshipType::Contains);
+        assert_eq!(result.unwrap_err(), CompositionError::NodeNotFound(missing));
+    }
+
+    #[test]
+    fn test_checked_edge_by_label() {
+        let graph = GraphComposition::composite("Order")
+            .add_node(BaseNodeType::Value, "total", 10)
+            .try_add_edge_by_label("root", "total", BaseRelationshipType::Contains)
+            .unwrap();
+        assert_eq!(graph.edges.len(), 1);
+
+        let missing =
+            graph
+                .clone()
+                .try_add_edge_by_label("root", "status", BaseRelationshipType::Contains);
+        assert_eq!(
+            missing.unwrap_err(),
+            CompositionError::LabelNotFound("status".to_string())
+        );
+
+        let ambiguous = graph
+            .add_node(BaseNodeType::Value, "total", 20)
+            .try_add_edge_by_label("root", "total", BaseRelationshipType::Contains);
+        assert_eq!(
+            ambiguous.unwrap_err(),
+            CompositionError::AmbiguousLabel("total".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn test_checked_node_rejects_duplicate_id() {
+        let graph = GraphComposition::composite("Order");
+        let root = graph.composition_root;
+
+        let result = graph.try_add_node_with_id(root, BaseNodeType::Value, "dup", 1);
+        assert_eq!(result.unwrap_err(), CompositionError::DuplicateNodeId(root));
+    }
+
+    #[test]
+    fn test_validate_structure_reports_every_dangling_edge() {
+        let ghost_a = NodeId::new();
+        let ghost_b = NodeId::new();
+        let graph = GraphComposition::composite("Broken");
+        let root = graph.composition_root;
+        let graph = graph
+            .add_edge(root, ghost_a, BaseRelationshipType::Contains)
+            .add_edge(ghost_b, root, BaseRelationshipType::Contains);
+
+        let errors = graph.validate_structure().unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors
+            .iter()
+            .all(|e| matches!(e, CompositionError::DanglingEdge(_, id) if *id == ghost_a || *id == ghost_b)));
+
+        assert!(GraphComposition::composite("Fine")
+            .validate_structure()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_node_by_label() {
+        let mut order = GraphComposition::composite("Order")
+            .add_node(BaseNodeType::Value, "total", 100)
+            .add_edge_by_label("root", "total", BaseRelationshipType::Contains);
+
+        assert_eq!(
+            order.node_by_label("total").unwrap().data,
+            serde_json::json!(100)
+        );
+        assert_eq!(
+            order.node_by_label("root").unwrap().id,
+            order.composition_root
+        );
+
+        order.node_by_label_mut("total").unwrap().data = serde_json::json!(150);
+        assert_eq!(
+            order.node_by_label("total").unwrap().data,
+            serde_json::json!(150)
+        );
+
+        assert_eq!(
+            order.node_by_label("missing").unwrap_err(),
+            CompositionError::LabelNotFound("missing".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unique_labels_mode() {
+        let order = GraphComposition::composite("Order")
+            .add_node(BaseNodeType::Value, "total", 100)
+            .with_unique_labels()
+            .unwrap();
+        assert!(order.has_unique_labels());
+
+        let result = order.try_add_node(BaseNodeType::Value, "total", 200);
+        assert_eq!(
+            result.unwrap_err(),
+            CompositionError::DuplicateLabel("total".to_string())
+        );
+
+        let duplicated = GraphComposition::composite("Order")
+            .add_node(BaseNodeType::Value, "total", 100)
+            .add_node(BaseNodeType::Value, "total", 200);
+        assert_eq!(
+            duplicated.with_unique_labels().unwrap_err(),
+            CompositionError::AmbiguousLabel("total".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn test_qualified_label_paths_across_composition() {
+        let order = GraphComposition::composite("Order")
+            .add_node(BaseNodeType::Value, "total", 100)
+            .add_edge_by_label("root", "total", BaseRelationshipType::Contains);
+        let invoice = GraphComposition::composite("Invoice")
+            .add_node(BaseNodeType::Value, "total", 120)
+            .add_edge_by_label("root", "total", BaseRelationshipType::Contains);
+
+        let combined = order.parallel(&invoice).unwrap();
+
+        assert_eq!(
+            combined.node_by_label("total").unwrap_err(),
+            CompositionError::AmbiguousLabel("total".to_string(), 2)
+        );
+        assert_eq!(
+            combined.node_by_label("Order/total").unwrap().data,
+            serde_json::json!(100)
+        );
+        assert_eq!(
+            combined.node_by_label("Invoice/total").unwrap().data,
+            serde_json::json!(120)
+        );
+        assert_eq!(
+            combined.node_by_label("root/Order").unwrap_err(),
+            CompositionError::LabelNotFound("root/Order".to_string())
+        );
+
+        // Label lookups survive a serde round-trip
+        let json = serde_json::to_string(&combined).unwrap();
+        let restored: GraphComposition = serde_json::from_str(&json).unwrap();
+        assert!(restored.node_by_label("Invoice/total").is_ok());
+    }
+
+    fn order_with_items() -> GraphComposition {
+        GraphComposition::composite("Order")
+            .add_node(BaseNodeType::Value, "items", serde_json::json!([]))
+            .add_node(BaseNodeType::Value, "item_1", "widget")
+            .add_node(BaseNodeType::Value, "item_2", "gadget")
+            .add_node(BaseNodeType::Value, "customer", "c-1")
+            .add_edge_by_label("root", "items", BaseRelationshipType::Contains)
+            .add_edge_by_label("items", "item_1", BaseRelationshipType::Contains)
+            .add_edge_by_label("items", "item_2", BaseRelationshipType::Contains)
+            .add_edge_by_label("item_1", "customer", BaseRelationshipType::References)
+    }
+
+    #[test]
+    fn test_remove_node_policies() {
+        let mut graph = order_with_items();
+        let item_1 = graph.node_by_label("item_1").unwrap().id;
+
+        assert_eq!(
+            graph
+                .remove_node(item_1, EdgeRemovalPolicy::Refuse)
+                .unwrap_err(),
+            CompositionError::NodeHasEdges(item_1, 2)
+        );
+
+        let mut orphaned = graph.clone();
+        let removed = orphaned
+            .remove_node(item_1, EdgeRemovalPolicy::Orphan)
+            .unwrap();
+        assert_eq!(removed.nodes.len(), 1);
+        assert!(removed.edges.is_empty());
+        assert_eq!(orphaned.validate_structure().unwrap_err().len(), 2);
+
+        let removed = graph
+            .remove_node(item_1, EdgeRemovalPolicy::Cascade)
+            .unwrap();
+        assert_eq!(removed.edges.len(), 2);
+        assert_eq!(graph.edges.len(), 2);
+        assert!(graph.validate_structure().is_ok());
+        assert!(graph.node_by_label("item_1").is_err());
+
+        let root = graph.composition_root;
+        assert_eq!(
+            graph
+                .remove_node(root, EdgeRemovalPolicy::Cascade)
+                .unwrap_err(),
+            CompositionError::RootRemoval(root)
+        );
+    }
+
+    #[test]
+    fn test_remove_edge_updates_indexes() {
+        let mut graph = order_with_items();
+        let root = graph.composition_root;
+        let edge_id = graph.out_edges(root)[0].id;
+
+        assert!(graph.remove_edge(edge_id).is_some());
+        assert!(graph.remove_edge(edge_id).is_none());
+        assert_eq!(graph.out_degree(root), 0);
+    }
+
+    #[test]
+    fn test_remove_subgraph_follows_contains() {
+        let mut graph = order_with_items();
+        let items = graph.node_by_label("items").unwrap().id;
+
+        let removed = graph.remove_subgraph(items).unwrap();
+
+        // items, item_1 and item_2 go; customer is only referenced
+        assert_eq!(removed.nodes.len(), 3);
+        assert_eq!(removed.edges.len(), 4);
+        assert_eq!(graph.nodes.len(), 2);
+        assert!(graph.node_by_label("customer").is_ok());
+
+        let root = graph.composition_root;
+        assert_eq!(
+            graph.remove_subgraph(root).unwrap_err(),
+            CompositionError::RootRemoval(root)
+        );
+    }
+
+    #[test]
+    fn test_retain_nodes_and_reroot() {
+        let mut graph = order_with_items();
+
+        let removed = graph.retain_nodes(|n| n.label != "item_2").unwrap();
+        assert_eq!(removed.nodes.len(), 1);
+        assert_eq!(graph.nodes.len(), 4);
+
+        assert!(graph.retain_nodes(|n| n.label != "root").is_err());
+        assert_eq!(graph.nodes.len(), 4);
+
+        let items = graph.node_by_label("items").unwrap().id;
+        graph.set_composition_root(items).unwrap();
+        let removed = graph.retain_nodes(|n| n.label != "root").unwrap();
+        assert_eq!(removed.nodes.len(), 1);
+        assert_eq!(graph.composition_root, items);
+    }
+
+    #[test]
+    fn test_map_edges_changes_relationship_type() {
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        enum OrderRelation {
+            HasPart,
+            Other(String),
+        }
+
+        let graph = order_with_items();
+        let root = graph.composition_root;
+
+        let typed = graph.map_edges(|edge| CompositionEdge {
+            id: edge.id,
+            source: edge.source,
+            target: edge.target,
+            relationship: Relationship {
+                relationship_type: match &edge.relationship.relationship_type {
+                    BaseRelationshipType::Contains => OrderRelation::HasPart,
+                    other => OrderRelation::Other(other.to_string()),
+                },
+                metadata: edge.relationship.metadata.clone(),
+                bidirectional: edge.relationship.bidirectional,
+            },
+        });
+
+        assert_eq!(typed.edges.len(), 4);
+        assert_eq!(typed.out_degree(root), 1);
+        assert_eq!(
+            typed.out_edges(root)[0].relationship.relationship_type,
+            OrderRelation::HasPart
+        );
+        assert!(typed.node_by_label("items/item_1").is_ok());
+    }
+
+    #[test]
+    fn test_bimap_relabels_and_reindexes() {
+        let graph = order_with_items();
+
+        let mapped = graph.bimap(
+            |node| {
+                let mut node = node.clone();
+                node.label = node.label.to_uppercase();
+                node
+            },
+            |edge| {
+                CompositionEdge::new(
+                    edge.source,
+                    edge.target,
+                    edge.relationship.relationship_type.to_string(),
+                )
+            },
+        );
+
+        assert!(mapped.node_by_label("ITEMS").is_ok());
+        assert!(mapped.node_by_label("items").is_err());
+        assert!(mapped
+            .edges
+            .values()
+            .any(|e| e.relationship.relationship_type == "References"));
+    }
+
+    #[test]
+    fn test_try_map_reports_functor_error() {
+        let graph = order_with_items();
+
+        let result = graph.clone().try_map_nodes(|node| {
+            if node.label == "customer" {
+                Err("customer nodes are not allowed")
+            } else {
+                Ok(node.clone())
+            }
+        });
+        assert!(matches!(
+            result.unwrap_err(),
+            CompositionError::FunctorError(msg) if msg.contains("customer nodes are not allowed")
+        ));
+
+        let ok = graph
+            .try_map_edges(|edge| Ok::<_, String>(edge.clone()))
+            .unwrap();
+        assert_eq!(ok.edges.len(), 4);
+    }
+
+    #[test]
+    fn test_update_keeps_invariants() {
+        let graph = order_with_items()
+            .with_invariant(|g| g.nodes.values().all(|n| !n.label.is_empty()))
+            .update_nodes(|node| node.label.clear());
+
+        assert!(graph.check_invariants().is_err());
+    }
+
+    #[test]
+    fn test_bind_rewires_edges_through_roots_and_leaves() {
+        let workflow = GraphComposition::composite("Checkout")
+            .add_node(BaseNodeType::Command, "pay", serde_json::json!({}))
+            .add_node(BaseNodeType::Command, "ship", serde_json::json!({}))
+            .add_edge_by_label("pay", "ship", BaseRelationshipType::Sequence);
+
+        // Expand "pay" into authorize -> capture, leave other nodes alone
+        let expanded = workflow
+            .bind(|node| {
+                if node.label == "pay" {
+                    GraphComposition::composite("Payment")
+                        .add_node(BaseNodeType::Command, "authorize", serde_json::json!({}))
+                        .add_node(BaseNodeType::Command, "capture", serde_json::json!({}))
+                        .add_edge_by_label("root", "authorize", BaseRelationshipType::Sequence)
+                        .add_edge_by_label("authorize", "capture", BaseRelationshipType::Sequence)
+                } else {
+                    GraphComposition::pure(node.clone())
+                }
+            })
+            .unwrap();
+
+        assert_eq!(expanded.nodes.len(), 5);
+        assert!(matches!(
+            expanded.composition_type,
+            CompositionType::Monad { .. }
+        ));
+
+        let capture = expanded.node_by_label("capture").unwrap().id;
+        let ship = expanded.node_by_label("ship").unwrap().id;
+        assert_eq!(expanded.out_edges(capture)[0].target, ship);
+        assert_eq!(expanded.in_degree(ship), 1);
+    }
+
+    #[test]
+    fn test_bind_with_shared_graph_regenerates_ids() {
+        let shared = GraphComposition::atomic("Money", serde_json::json!(0));
+        let graph = order_with_items();
+
+        let bound = graph.bind(|_| shared.clone()).unwrap();
+
+        assert_eq!(bound.nodes.len(), graph.nodes.len());
+        assert_eq!(bound.edges.len(), graph.edges.len());
+        assert!(bound.validate_structure().is_ok());
+
+        let graph = graph.with_id_strategy(IdStrategy::sequential(7));
+        let shared = shared.with_id_strategy(IdStrategy::sequential(7));
+        let bound = graph.bind(|_| shared.clone()).unwrap();
+        assert_eq!(bound.nodes.len(), graph.nodes.len());
+        assert_eq!(bound, graph.bind(|_| shared.clone()).unwrap());
+    }
+
+    #[test]
+    fn test_bind_leaves_cyclic_substitutes_through_their_ports() {
+        let graph = GraphComposition::composite("Order")
+            .add_node(BaseNodeType::Value, "total", 10)
+            .add_edge_by_label("root", "total", BaseRelationshipType::Contains);
+        let retry = || {
+            GraphComposition::composite("Retry")
+                .add_node(BaseNodeType::Command, "attempt", serde_json::json!({}))
+                .add_edge_by_label("root", "attempt", BaseRelationshipType::Sequence)
+                .add_edge_by_label("attempt", "root", BaseRelationshipType::Sequence)
+        };
+        let expand = |node: &CompositionNode<BaseNodeType>| {
+            if node.label == "root" {
+                retry()
+            } else {
+                GraphComposition::atomic("Total", serde_json::json!(10))
+            }
+        };
+
+        // A cycle has no leaves, so without ports it has no exit
+        assert!(matches!(
+            graph.bind(expand),
+            Err(CompositionError::MonadError(_))
+        ));
+
+        let expand = |node: &CompositionNode<BaseNodeType>| {
+            if node.label == "root" {
+                retry()
+                    .with_output_port_by_label("done", "attempt")
+                    .unwrap()
+            } else {
+                GraphComposition::atomic("Total", serde_json::json!(10))
+            }
+        };
+        let bound = graph.bind(expand).unwrap();
+        assert_eq!(bound.nodes.len(), 3);
+        let attempt = bound.node_by_label("attempt").unwrap().id;
+        let total = bound.node_by_label("Total").unwrap().id;
+        assert_eq!(bound.out_edges(attempt).len(), 2);
+        assert_eq!(bound.in_degree(total), 1);
+    }
+
+    #[test]
+    fn test_flatten_graph_of_graphs() {
+        let validate = GraphComposition::composite("Validate")
+            .add_node(BaseNodeType::Command, "check", serde_json::json!({}))
+            .add_edge_by_label("root", "check", BaseRelationshipType::Sequence);
+        let process = GraphComposition::composite("Process");
 
-/// Functor trait for structure-preserving maps
-pub trait GraphFunctor<N, R>
-where
-    N: Clone + Serialize + for<'de> Deserialize<'de>,
-    R: Clone + Serialize + for<'de> Deserialize<'de>,
-{
-    fn fmap<F, N2>(&self, f: F) -> GraphComposition<N2, R>
-    where
-        F: Fn(&CompositionNode<N>) -> CompositionNode<N2>,
-        N2: Clone + Serialize + for<'de> Deserialize<'de>;
-}
+        let nested = GraphComposition::<GraphComposition, BaseRelationshipType>::new(
+            validate.clone(),
+            CompositionType::Composite {
+                structure_type: "Pipeline".to_string(),
+            },
+        )
+        .add_node(process, "process", serde_json::json!({}))
+        .add_edge_by_label("root", "process", BaseRelationshipType::Sequence);
 
-impl<N, R> GraphFunctor<N, R> for GraphComposition<N, R>
-where
-    N: Clone + Serialize + for<'de> Deserialize<'de>,
-    R: Clone + Serialize + for<'de> Deserialize<'de>,
-{
-    fn fmap<F, N2>(&self, f: F) -> GraphComposition<N2, R>
-    where
-        F: Fn(&CompositionNode<N>) -> CompositionNode<N2>,
-        N2: Clone + Serialize + for<'de> Deserialize<'de>,
-    {
-        self.clone().map_nodes(f)
+        let flat = nested.flatten().unwrap();
+
+        assert_eq!(flat.nodes.len(), 3);
+        assert_eq!(flat.composition_root, validate.composition_root);
+        let check = flat.node_by_label("check").unwrap().id;
+        assert_eq!(flat.out_degree(check), 1);
     }
-}
 
-/// Monad trait for composition with context
-pub trait GraphMonad<N, R>
-where
-    N: Clone + Serialize + for<'de> Deserialize<'de>,
-    R: Clone + Serialize + for<'de> Deserialize<'de>,
-{
-    fn pure(value: CompositionNode<N>) -> GraphComposition<N, R>;
+    #[test]
+    fn test_deterministic_ids() {
+        let build = |strategy: IdStrategy| {
+            GraphComposition::composite("Pipeline")
+                .with_id_strategy(strategy)
+                .add_node(BaseNodeType::Command, "step", serde_json::json!({ "n": 1 }))
+                .add_node(BaseNodeType::Command, "step", serde_json::json!({ "n": 2 }))
+                .add_node(BaseNodeType::Event, "done", serde_json::json!({}))
+                .add_edge_by_label("root", "done", BaseRelationshipType::Sequence)
+        };
 
-    fn bind<F>(&self, f: F) -> Result<GraphComposition<N, R>, CompositionError>
-    where
-        F: Fn(&CompositionNode<N>) -> GraphComposition<N, R>;
-}
+        let first = build(IdStrategy::for_aggregate("Pipeline", "p-1"));
+        let second = build(IdStrategy::for_aggregate("Pipeline", "p-1"));
+        assert_eq!(first, second);
+        assert_eq!(first.nodes.len(), 4);
+        assert_eq!(first.edges.len(), 1);
+        assert_ne!(first, build(IdStrategy::for_aggregate("Pipeline", "p-2")));
+        assert_eq!(
+            build(IdStrategy::sequential(1)),
+            build(IdStrategy::sequential(1))
+        );
+        assert_ne!(build(IdStrategy::Random), build(IdStrategy::Random));
 
-/// Helper function to create a line item graph
-pub fn line_item_graph(product: &str, quantity: i32, price: f64) -> GraphComposition {
-    GraphComposition::composite("LineItem")
-        .add_node(BaseNodeType::Value, "product", serde_json::json!({ "name": product }))
-        .add_node(BaseNodeType::Value, "quantity", quantity)
-        .add_node(BaseNodeType::Value, "price", price)
-        .add_node(BaseNodeType::Value, "total", quantity as f64 * price)
-}
+        let tail = GraphComposition::atomic("Done", serde_json::json!({}))
+            .with_id_strategy(IdStrategy::named("tail"));
+        assert_eq!(first.then(&tail).unwrap(), second.then(&tail).unwrap());
+        let parallel = first.parallel(&tail).unwrap();
+        assert_eq!(parallel, second.parallel(&tail).unwrap());
+        assert_eq!(parallel.nodes.len(), 6);
+        assert!(parallel.validate_structure().is_ok());
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_editing_node_data_keeps_derived_ids() {
+        let build = |title: &str| {
+            GraphComposition::aggregate("Document", "d-1")
+                .add_node(
+                    BaseNodeType::Value,
+                    "info",
+                    serde_json::json!({ "title": title }),
+                )
+                .add_edge_by_label("root", "info", BaseRelationshipType::Contains)
+                .with_id_strategy(IdStrategy::for_aggregate("Document", "d-1"))
+        };
+        let ids = |graph: &GraphComposition| {
+            let mut nodes: Vec<NodeId> = graph.nodes.keys().copied().collect();
+            let mut edges: Vec<EdgeId> = graph.edges.keys().copied().collect();
+            nodes.sort();
+            edges.sort();
+            (nodes, edges)
+        };
+
+        let draft = build("Draft");
+        let edited = build("Final");
+        assert_eq!(ids(&draft), ids(&edited));
+        assert_eq!(
+            draft.node_by_label("info").unwrap().id,
+            edited.node_by_label("info").unwrap().id
+        );
+        assert_ne!(draft, edited);
+
+        let added = |title: &str| {
+            GraphComposition::aggregate("Document", "d-1")
+                .with_id_strategy(IdStrategy::for_aggregate("Document", "d-1"))
+                .add_node(
+                    BaseNodeType::Value,
+                    "info",
+                    serde_json::json!({ "title": title }),
+                )
+                .add_edge_by_label("root", "info", BaseRelationshipType::Contains)
+        };
+        assert_eq!(ids(&added("Draft")), ids(&added("Final")));
+    }
 
     #[test]
-    fn test_atomic_graph_creation() {
-        let money = GraphComposition::atomic(
-            "Money",
-            serde_json::json!({ "amount": 100, "currency": "USD" }),
+    fn test_composing_graphs_with_the_same_id_strategy_keeps_every_node() {
+        let build = |name: &str, strategy: IdStrategy| {
+            GraphComposition::composite(name)
+                .with_id_strategy(strategy)
+                .add_node(BaseNodeType::Command, "step", serde_json::json!({}))
+                .add_edge_by_label("root", "step", BaseRelationshipType::Sequence)
+        };
+
+        for strategy in [IdStrategy::sequential(1), IdStrategy::named("shared")] {
+            let first = build("Build", strategy.clone());
+            let second = build("Build", strategy);
+            assert_eq!(first.composition_root, second.composition_root);
+
+            let sequential = first.then(&second).unwrap();
+            assert_eq!(sequential.nodes.len(), 4);
+            assert_eq!(sequential.edges.len(), 3);
+            assert!(sequential.validate_structure().is_ok());
+            assert!(!sequential.has_cycle());
+
+            let parallel = first.parallel(&second).unwrap();
+            assert_eq!(parallel.nodes.len(), 5);
+            assert_eq!(parallel.out_degree(parallel.composition_root), 2);
+            assert!(parallel.validate_structure().is_ok());
+            assert_eq!(parallel, first.parallel(&second).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_cycles_and_topological_order() {
+        let graph = GraphComposition::composite("Build")
+            .add_node(BaseNodeType::Command, "compile", serde_json::json!({}))
+            .add_node(BaseNodeType::Command, "test", serde_json::json!({}))
+            .add_node(BaseNodeType::Command, "deploy", serde_json::json!({}))
+            .add_edge_by_label("root", "compile", BaseRelationshipType::Sequence)
+            .add_edge_by_label("compile", "test", BaseRelationshipType::Sequence)
+            .add_edge_by_label("test", "deploy", BaseRelationshipType::DependsOn);
+        let id = |label: &str| graph.node_by_label(label).unwrap().id;
+
+        assert!(!graph.has_cycle());
+        assert!(graph.find_cycles().is_empty());
+        let order = graph.topological_order().unwrap();
+        let position = |label: &str| order.iter().position(|n| *n == id(label)).unwrap();
+        assert!(position("root") < position("compile"));
+        assert!(position("test") < position("deploy"));
+
+        // A retry edge closes a loop, but only among Sequence edges
+        let cyclic =
+            graph
+                .clone()
+                .add_edge_by_label("deploy", "compile", BaseRelationshipType::Sequence);
+        assert!(cyclic.has_cycle());
+        assert_eq!(
+            cyclic.topological_order().unwrap_err(),
+            CompositionError::CycleDetected
         );
+        assert!(!cyclic.has_cycle_where(|r| *r == BaseRelationshipType::Sequence));
+        assert!(cyclic
+            .topological_order_where(|r| *r == BaseRelationshipType::Sequence)
+            .is_ok());
 
-        assert_eq!(money.nodes.len(), 1);
-        assert_eq!(money.edges.len(), 0);
-        assert!(matches!(
-            money.composition_type,
-            CompositionType::Atomic { .. }
-        ));
+        let cycles = cyclic.find_cycles();
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].len(), 3);
+        for node in ["compile", "test", "deploy"] {
+            assert!(cycles[0].contains(&id(node)));
+        }
+
+        let looped = graph
+            .clone()
+            .add_edge(id("test"), id("test"), BaseRelationshipType::Sequence);
+        assert_eq!(looped.find_cycles(), vec![vec![id("test")]]);
     }
 
     #[test]
-    fn test_composite_graph_creation() {
-        let address = GraphComposition::composite("Address")
-            .add_node(BaseNodeType::Value, "street", "123 Main St")
-            .add_node(BaseNodeType::Value, "city", "Springfield")
-            .add_node(BaseNodeType::Value, "zip", "12345")
-            .add_edge_by_label("root", "street", BaseRelationshipType::Contains)
-            .add_edge_by_label("root", "city", BaseRelationshipType::Contains)
-            .add_edge_by_label("root", "zip", BaseRelationshipType::Contains);
+    fn test_enforced_acyclicity() {
+        let chain = GraphComposition::composite("Chain")
+            .add_node(BaseNodeType::Command, "step", serde_json::json!({}))
+            .add_edge_by_label("root", "step", BaseRelationshipType::Sequence)
+            .with_acyclicity()
+            .unwrap();
+        assert!(chain.is_acyclic_enforced());
 
-        assert_eq!(address.nodes.len(), 4); // root + 3 nodes
-        assert_eq!(address.edges.len(), 3);
+        let root = chain.composition_root;
+        let step = chain.node_by_label("step").unwrap().id;
+        assert_eq!(
+            chain
+                .clone()
+                .try_add_edge(step, root, BaseRelationshipType::Sequence)
+                .unwrap_err(),
+            CompositionError::CycleDetected
+        );
+        assert!(chain.then(&chain).is_ok());
+        let retry = GraphComposition::composite("Retry")
+            .add_node(BaseNodeType::Command, "attempt", serde_json::json!({}))
+            .add_edge_by_label("root", "attempt", BaseRelationshipType::Sequence)
+            .add_edge_by_label("attempt", "root", BaseRelationshipType::Sequence);
+        assert_eq!(
+            chain.then(&retry).unwrap_err(),
+            CompositionError::CycleDetected
+        );
+
+        let unchecked = chain.add_edge(step, root, BaseRelationshipType::Sequence);
+        assert_eq!(
+            unchecked.check_invariants().unwrap_err(),
+            CompositionError::CycleDetected
+        );
+        assert_eq!(
+            unchecked.with_acyclicity().unwrap_err(),
+            CompositionError::CycleDetected
+        );
+    }
+
+    /// Two graphs sharing a customer node whose data has drifted apart
+    fn orders_sharing_customer() -> (GraphComposition, GraphComposition, NodeId) {
+        let customer = NodeId::new();
+        let first = GraphComposition::composite("Orders")
+            .add_node_with_id(
+                customer,
+                BaseNodeType::EntityReference,
+                "customer",
+                serde_json::json!({ "name": "Ada", "tier": "gold" }),
+            )
+            .add_edge_by_label("root", "customer", BaseRelationshipType::References);
+        let second = GraphComposition::composite("Invoices")
+            .add_node_with_id(
+                customer,
+                BaseNodeType::EntityReference,
+                "customer",
+                serde_json::json!({ "name": "Ada Lovelace", "email": "ada@example.com" }),
+            )
+            .add_edge_by_label("root", "customer", BaseRelationshipType::References);
+        (first, second, customer)
+    }
+
+    #[test]
+    fn test_compose_strategies() {
+        let (first, second, customer) = orders_sharing_customer();
+
+        let merged = first
+            .compose_with(&second, ComposeStrategy::MergeById(DataMerge::Deep))
+            .unwrap();
+        assert_eq!(merged.nodes.len(), 3);
+        assert_eq!(merged.in_degree(customer), 2);
+        assert_eq!(
+            merged.nodes[&customer].data,
+            serde_json::json!({ "name": "Ada Lovelace", "tier": "gold", "email": "ada@example.com" })
+        );
+
+        let kept = first
+            .compose_with(&second, ComposeStrategy::MergeById(DataMerge::KeepSelf))
+            .unwrap();
+        assert_eq!(kept.nodes[&customer].data, first.nodes[&customer].data);
+
+        let rekeyed = first.compose(&second).unwrap();
+        assert_eq!(rekeyed.nodes.len(), 4);
+        assert_eq!(rekeyed.in_degree(customer), 1);
+        assert!(rekeyed.validate_structure().is_ok());
+
+        // Merging never silently drops the other graph's type or label
+        let event = GraphComposition::composite("Events").add_node_with_id(
+            customer,
+            BaseNodeType::Event,
+            "customer",
+            serde_json::json!({}),
+        );
         assert!(matches!(
-            address.composition_type,
-            CompositionType::Composite { .. }
+            first.compose_with(&event, ComposeStrategy::MergeById(DataMerge::Deep)),
+            Err(CompositionError::IncompatibleTypes(_, _))
         ));
+        let renamed = GraphComposition::composite("Renamed").add_node_with_id(
+            customer,
+            BaseNodeType::EntityReference,
+            "client",
+            serde_json::json!({}),
+        );
+        assert_eq!(
+            first
+                .compose_with(&renamed, ComposeStrategy::MergeById(DataMerge::Deep))
+                .unwrap_err(),
+            CompositionError::DataConflict(customer)
+        );
+
+        assert_eq!(
+            first
+                .compose_with(&second, ComposeStrategy::FailOnConflict)
+                .unwrap_err(),
+            CompositionError::DuplicateNodeId(customer)
+        );
+        assert!(first
+            .compose_with(&first, ComposeStrategy::FailOnConflict)
+            .is_ok());
     }
 
     #[test]
-    fn test_entity_creation() {
-        let user = GraphComposition::entity("User", "user-123");
+    fn test_can_compose_with_checks_types() {
+        let order = GraphComposition::aggregate("Order", "order-1");
+        let customer = GraphComposition::entity("Customer", "c-1");
+        let money = GraphComposition::atomic("Money", serde_json::json!(10));
+        let text = GraphComposition::atomic("Text", serde_json::json!("ten"));
 
-        assert_eq!(user.nodes.len(), 1);
+        assert!(order.can_compose_with(&customer));
+        assert!(!customer.can_compose_with(&order));
+        assert!(money.can_compose_with(&GraphComposition::composite("Prices")));
+        assert!(!money.can_compose_with(&text));
         assert!(matches!(
-            user.composition_type,
-            CompositionType::Domain(DomainCompositionType::Entity { .. })
+            money.compose(&text),
+            Err(CompositionError::IncompatibleTypes(_, _))
         ));
+    }
 
-        // Check that the entity has an ID
-        let root_node = &user.nodes[&user.composition_root];
-        if let JsonValue::Object(ref map) = root_node.data {
-            assert_eq!(map.get("id").unwrap(), "user-123");
-        } else {
-            panic!("Entity should have object data");
-        }
+    #[test]
+    fn test_glue_along_entity_reference() {
+        let customer_ref = |name: &str| serde_json::json!({ "entity_id": "customer-42", "entity_type": "Customer", "name": name });
+        let order = GraphComposition::aggregate("Order", "order-1")
+            .add_node(BaseNodeType::EntityReference, "buyer", customer_ref("Ada"))
+            .add_edge_by_label("root", "buyer", BaseRelationshipType::References);
+        let invoice = GraphComposition::aggregate("Invoice", "invoice-7")
+            .add_node(
+                BaseNodeType::EntityReference,
+                "payer",
+                customer_ref("Ada Lovelace"),
+            )
+            .add_node(
+                BaseNodeType::Value,
+                "address",
+                serde_json::json!({ "city": "London" }),
+            )
+            .add_edge_by_label("root", "payer", BaseRelationshipType::References)
+            .add_edge_by_label("payer", "address", BaseRelationshipType::Contains);
+
+        let glued = order
+            .glue(&invoice, Interface::EntityIds, DataMerge::Deep)
+            .unwrap();
+        let buyer = glued.node_by_label("buyer").unwrap();
+        assert_eq!(glued.nodes.len(), 4);
+        assert!(glued.node_by_label("payer").is_err());
+        assert_eq!(buyer.data["name"], "Ada Lovelace");
+        assert_eq!(glued.in_degree(buyer.id), 2);
+        assert_eq!(glued.out_degree(buyer.id), 1);
+        assert_eq!(glued.composition_root, order.composition_root);
+        assert!(glued.validate_structure().is_ok());
+
+        let by_label = order
+            .glue(
+                &invoice,
+                Interface::Labels(vec![("buyer".into(), "payer".into())]),
+                DataMerge::KeepSelf,
+            )
+            .unwrap();
+        assert_eq!(by_label.node_by_label("buyer").unwrap().data["name"], "Ada");
+
+        assert_eq!(
+            order
+                .glue(&invoice, Interface::EntityIds, DataMerge::RequireEqual)
+                .unwrap_err(),
+            CompositionError::DataConflict(order.node_by_label("buyer").unwrap().id)
+        );
     }
 
     #[test]
-    fn test_sequential_composition() {
-        let validate = GraphComposition::composite("ValidateOrder");
-        let calculate = GraphComposition::composite("CalculatePricing");
+    fn test_glue_keeps_unrelated_nodes_apart() {
+        let left = GraphComposition::composite("Left").add_node(
+            BaseNodeType::Value,
+            "shared",
+            serde_json::json!({}),
+        );
+        let shared = left.node_by_label("shared").unwrap().id;
 
-        let workflow = validate.then(&calculate).unwrap();
+        // Same IDs, but only the shared node is in the interface
+        let mut right = left.clone();
+        right.nodes.get_mut(&right.composition_root).unwrap().label = "other".to_string();
+        right.rebuild_indexes();
+        let right = right.add_edge_by_label("root", "shared", BaseRelationshipType::Contains);
 
-        // Should have nodes from both graphs plus connections
-        assert!(workflow.nodes.len() >= 2);
-        assert!(workflow.edges.len() >= 1); // At least one sequence edge
+        let glued = left
+            .glue(
+                &right,
+                Interface::Ids(vec![(shared, shared)]),
+                DataMerge::Deep,
+            )
+            .unwrap();
+        assert_eq!(glued.nodes.len(), 3);
+        assert_eq!(glued.in_degree(shared), 1);
+        assert!(glued.validate_structure().is_ok());
+
+        // Identical edges between glued nodes are kept once
+        let twice = glued
+            .glue(
+                &glued,
+                Interface::Ids(glued.nodes.keys().map(|id| (*id, *id)).collect()),
+                DataMerge::Deep,
+            )
+            .unwrap();
+        assert_eq!(twice.edges.len(), glued.edges.len());
     }
 
     #[test]
-    fn test_parallel_composition() {
-        let check_inventory = GraphComposition::composite("CheckInventory");
-        let verify_payment = GraphComposition::composite("VerifyPayment");
+    fn test_then_wires_through_ports() {
+        let line_item = line_item_graph("Widget", 2, 10.0);
+        let checkout = GraphComposition::composite("Checkout")
+            .add_node(BaseNodeType::Command, "pay", serde_json::json!({}))
+            .add_edge_by_label("root", "pay", BaseRelationshipType::Sequence);
 
-        let parallel = check_inventory.parallel(&verify_payment).unwrap();
+        let sequence = line_item.then(&checkout).unwrap();
+        let price = line_item.node_by_label("price").unwrap().id;
+        assert_eq!(sequence.out_degree(price), 0);
+        assert_eq!(sequence.out_degree(line_item.composition_root), 1);
+        assert_eq!(
+            sequence.exit_ports()[DEFAULT_OUTPUT_PORT],
+            vec![checkout.node_by_label("pay").unwrap().id]
+        );
+    }
 
-        // Should have a new root connected to both subgraphs
-        assert!(parallel.nodes.len() >= 3); // new root + 2 subgraph roots
-        assert!(parallel.edges.len() >= 2); // 2 parallel edges
+    #[test]
+    fn test_named_ports_pair_up() {
+        let validate = GraphComposition::composite("Validate")
+            .add_node(BaseNodeType::Event, "valid", serde_json::json!({}))
+            .add_node(BaseNodeType::Event, "invalid", serde_json::json!({}))
+            .add_edge_by_label("root", "valid", BaseRelationshipType::Choice)
+            .add_edge_by_label("root", "invalid", BaseRelationshipType::Choice)
+            .with_output_port_by_label("accepted", "valid")
+            .unwrap()
+            .with_output_port_by_label("rejected", "invalid")
+            .unwrap();
+        let handle = GraphComposition::composite("Handle")
+            .add_node(BaseNodeType::Command, "ship", serde_json::json!({}))
+            .add_node(BaseNodeType::Command, "refund", serde_json::json!({}))
+            .with_input_port_by_label("accepted", "ship")
+            .unwrap()
+            .with_input_port_by_label("rejected", "refund")
+            .unwrap()
+            .with_output_port_by_label("done", "ship")
+            .unwrap()
+            .with_output_port_by_label("done", "refund")
+            .unwrap();
+
+        let flow = validate.then(&handle).unwrap();
+        let id = |label: &str| flow.node_by_label(label).unwrap().id;
+        let targets = |label: &str| -> Vec<NodeId> {
+            flow.out_edges(id(label)).iter().map(|e| e.target).collect()
+        };
+        assert_eq!(targets("valid"), vec![id("ship")]);
+        assert_eq!(targets("invalid"), vec![id("refund")]);
+        assert_eq!(
+            flow.out_edges(id("valid"))[0].relationship.metadata["port"],
+            "accepted"
+        );
+        assert_eq!(flow.out_degree(handle.composition_root), 0);
+        assert_eq!(flow.ports().outputs["done"].len(), 2);
+
+        // Branches are entered through their input ports and joined on exits
+        let both = handle.parallel(&line_item_graph("Widget", 1, 5.0)).unwrap();
+        let root = both.composition_root;
+        assert_eq!(both.out_degree(root), 3);
+        assert_eq!(both.ports().outputs["done"].len(), 2);
+        assert_eq!(both.ports().outputs[DEFAULT_OUTPUT_PORT].len(), 1);
+
+        let json = serde_json::to_string(&flow).unwrap();
+        let restored: GraphComposition = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.ports(), flow.ports());
     }
 
     #[test]
-    fn test_generic_graph_composition() {
-        // Create a graph with custom node and relationship types
-        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-        enum CustomNode {
-            Start,
-            Process,
-            End,
-        }
+    fn test_extract_component_and_subgraphs() {
+        let order = GraphComposition::aggregate("Order", "order-1")
+            .add_node(BaseNodeType::Entity, "line", serde_json::json!({}))
+            .add_node(BaseNodeType::Value, "sku", serde_json::json!("W-1"))
+            .add_node(BaseNodeType::Entity, "customer", serde_json::json!({}))
+            .add_node(
+                BaseNodeType::Value,
+                "email",
+                serde_json::json!("a@example.com"),
+            )
+            .add_edge_by_label("root", "line", BaseRelationshipType::Contains)
+            .add_edge_by_label("line", "sku", BaseRelationshipType::Contains)
+            .add_edge_by_label("line", "customer", BaseRelationshipType::References)
+            .add_edge_by_label("customer", "email", BaseRelationshipType::Contains)
+            .with_invariant_rule(Invariant::acyclic())
+            .with_output_port_by_label("shipped", "sku")
+            .unwrap();
+        let id = |label: &str| order.node_by_label(label).unwrap().id;
 
-        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-        enum CustomRelation {
-            Next,
-            Error,
+        let tree = order
+            .extract_component(id("line"), |rel| *rel == BaseRelationshipType::Contains)
+            .unwrap();
+        assert_eq!(tree.composition_root, id("line"));
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.edges.len(), 1);
+        assert_eq!(tree.parent_graph(), Some(order.id));
+        assert_ne!(tree.id, order.id);
+        assert_eq!(tree.metadata.name, "Order");
+        assert_eq!(tree.invariants(), order.invariants());
+        assert_eq!(tree.ports().outputs["shipped"], vec![id("sku")]);
+        assert_eq!(tree.find_leaves(), vec![id("sku")]);
+
+        // One hop either way from the customer, keeping edges between members
+        let around = order.neighborhood(id("customer"), 1).unwrap();
+        let mut labels: Vec<&str> = around.nodes.values().map(|n| n.label.as_str()).collect();
+        labels.sort();
+        assert_eq!(labels, vec!["customer", "email", "line"]);
+        assert_eq!(around.composition_root, id("customer"));
+        assert_eq!(around.edges.len(), 2);
+        assert!(around.ports().is_empty());
+        assert_eq!(
+            order.neighborhood(id("customer"), 2).unwrap().nodes.len(),
+            5
+        );
+
+        // The root of an induced subgraph is a node nothing else in it enters
+        let entities = order
+            .induced_by(|node| matches!(node.node_type, BaseNodeType::Entity))
+            .unwrap();
+        assert_eq!(entities.composition_root, id("line"));
+        assert_eq!(entities.edges.len(), 1);
+        assert!(order.induced_by(|_| false).is_err());
+
+        let picked = order.subgraph([id("email"), id("customer")]).unwrap();
+        assert_eq!(picked.composition_root, id("email"));
+        assert_eq!(picked.in_degree(id("email")), 1);
+        assert!(matches!(
+            order.subgraph([NodeId::new()]),
+            Err(CompositionError::NodeNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_parallel_all_uses_one_fork() {
+        let branches: Vec<GraphComposition> = ["A", "B", "C", "D"]
+            .iter()
+            .map(|name| GraphComposition::composite(name))
+            .collect();
+
+        let fork = GraphComposition::parallel_all(&branches);
+        assert_eq!(fork.nodes.len(), 5);
+        assert_eq!(fork.out_degree(fork.composition_root), 4);
+        assert_eq!(fork.metadata.name, "Parallel");
+
+        let options = GraphComposition::choice_all(branches.iter().take(2));
+        assert!(options
+            .out_edges(options.composition_root)
+            .iter()
+            .all(|edge| edge.relationship.relationship_type == BaseRelationshipType::Choice));
+
+        // parallel is parallel_all over two branches, down to derived IDs
+        let named = |name: &str| {
+            GraphComposition::composite(name).with_id_strategy(IdStrategy::named(name))
+        };
+        let (a, b) = (named("A"), named("B"));
+        assert_eq!(
+            a.parallel(&b).unwrap(),
+            GraphComposition::parallel_all([&a, &b])
+        );
+    }
+
+    #[test]
+    fn test_flatten_forks() {
+        let branches: Vec<GraphComposition> = ["A", "B", "C", "D"]
+            .iter()
+            .map(|name| GraphComposition::composite(name))
+            .collect();
+        let roots: HashSet<NodeId> = branches.iter().map(|b| b.composition_root).collect();
+
+        let mut nested = branches[0].clone();
+        for branch in &branches[1..] {
+            nested = nested.parallel(branch).unwrap();
         }
+        // Three wrapper roots, chained
+        assert_eq!(nested.nodes.len(), 7);
 
-        let graph = GraphComposition::<CustomNode, CustomRelation>::new(
-            CustomNode::Start,
-            CompositionType::Composite {
-                structure_type: "Workflow".to_string(),
-            },
-        )
-        .add_node(CustomNode::Process, "process", serde_json::json!({}))
-        .add_node(CustomNode::End, "end", serde_json::json!({}));
+        let flat = nested.flatten_forks();
+        assert_eq!(flat.nodes.len(), 5);
+        let targets: HashSet<NodeId> = flat
+            .out_edges(flat.composition_root)
+            .iter()
+            .map(|edge| edge.target)
+            .collect();
+        assert_eq!(targets, roots);
 
-        assert_eq!(graph.nodes.len(), 3);
+        // A choice nested in a parallel fork is a different fork and stays
+        let mixed = branches[0]
+            .choice(&branches[1])
+            .unwrap()
+            .parallel(&branches[2])
+            .unwrap()
+            .flatten_forks();
+        assert_eq!(mixed.nodes.len(), 5);
+        assert_eq!(mixed.out_degree(mixed.composition_root), 2);
+
+        // A user graph that is merely named like a fork is not a wrapper
+        let lookalike = GraphComposition::composite("Parallel")
+            .add_node(BaseNodeType::Command, "lint", serde_json::json!({}))
+            .add_edge_by_label("root", "lint", BaseRelationshipType::Parallel);
+        let kept = GraphComposition::parallel_all([&lookalike, &branches[0]]).flatten_forks();
+        assert_eq!(kept.nodes.len(), 4);
+        assert!(kept.nodes.contains_key(&lookalike.composition_root));
     }
 
     #[test]
-    fn test_functor_map() {
-        let graph = GraphComposition::composite("Test")
-            .add_node(BaseNodeType::Value, "a", serde_json::json!({ "value": 1 }))
-            .add_node(BaseNodeType::Value, "b", serde_json::json!({ "value": 2 }));
+    fn test_guarded_choice() {
+        let express = GraphComposition::composite("Express");
+        let standard = GraphComposition::composite("Standard");
+        let pickup = GraphComposition::composite("Pickup");
 
-        let doubled = graph.fmap(|node| {
-            let mut new_node = node.clone();
-            if let JsonValue::Object(ref mut map) = new_node.data {
-                if let Some(JsonValue::Number(n)) = map.get("value") {
-                    if let Some(v) = n.as_i64() {
-                        map.insert("value".to_string(), serde_json::json!(v * 2));
-                    }
-                }
-            }
-            new_node
-        });
+        let shipping = GraphComposition::choice_with(vec![
+            (
+                Guard::when(Condition::greater_than("/order/total", 100.0)),
+                &express,
+            ),
+            (
+                Guard::when(Condition::equals("/order/pickup", true)),
+                &pickup,
+            ),
+            (Guard::otherwise(), &standard),
+        ])
+        .unwrap();
+        let fork = shipping.composition_root;
 
-        // Verify values were doubled
-        for node in doubled.nodes.values() {
-            if let JsonValue::Object(ref map) = node.data {
-                if let Some(JsonValue::Number(n)) = map.get("value") {
-                    let v = n.as_i64().unwrap_or(0);
-                    assert!(v == 0 || v == 2 || v == 4); // 0 for root, 2 and 4 for doubled values
-                }
+        let big = serde_json::json!({ "order": { "total": 250.0, "pickup": true } });
+        let small = serde_json::json!({ "order": { "total": 20.0 } });
+        assert_eq!(
+            shipping.select_branch(fork, &big).unwrap(),
+            Some(express.composition_root)
+        );
+        assert_eq!(shipping.evaluate_choice(fork, &big).unwrap().len(), 2);
+        assert_eq!(
+            shipping.select_branch(fork, &small).unwrap(),
+            Some(standard.composition_root)
+        );
+
+        // Guards survive serialization
+        let json = serde_json::to_string(&shipping).unwrap();
+        let restored: GraphComposition = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            restored.select_branch(fork, &small).unwrap(),
+            Some(standard.composition_root)
+        );
+
+        // Probabilities are validated and renormalized over enabled branches
+        assert!(matches!(
+            GraphComposition::choice_with([
+                (Guard::probability(0.7), &express),
+                (Guard::probability(0.7), &standard),
+            ]),
+            Err(CompositionError::InvalidProbability(_))
+        ));
+        let weighted = GraphComposition::choice_with([
+            (Guard::probability(0.2), &express),
+            (
+                Guard::when(Condition::exists("/member")).with_probability(0.5),
+                &pickup,
+            ),
+            (Guard::probability(0.3), &standard),
+        ])
+        .unwrap();
+        let root = weighted.composition_root;
+        let member = weighted
+            .evaluate_choice(root, &serde_json::json!({ "member": 1 }))
+            .unwrap();
+        assert_eq!(member[1], (pickup.composition_root, 0.5));
+        let guest = weighted.evaluate_choice(root, &small).unwrap();
+        assert_eq!(guest.len(), 2);
+        assert!((guest[1].1 - 0.6).abs() < 1e-9);
+        assert_eq!(
+            weighted.select_branch(root, &small).unwrap(),
+            Some(standard.composition_root)
+        );
+
+        // Guarded choices are not merged into an enclosing choice
+        let courier = GraphComposition::composite("Courier");
+        let nested = shipping.choice(&courier).unwrap().flatten_forks();
+        assert_eq!(nested.nodes.len(), shipping.nodes.len() + 2);
+        assert_eq!(nested.out_degree(fork), 3);
+    }
+
+    #[test]
+    fn test_loops_are_not_cycles() {
+        let payment = GraphComposition::composite("Payment")
+            .add_node(BaseNodeType::Command, "charge", serde_json::json!({}))
+            .add_edge_by_label("root", "charge", BaseRelationshipType::Sequence)
+            .with_acyclicity()
+            .unwrap();
+        let charge = payment.node_by_label("charge").unwrap().id;
+
+        let retry = payment.repeat(3).unwrap();
+        let header = retry.composition_root;
+        assert_eq!(retry.loop_kind(header), Some(LoopKind::Repeat { times: 3 }));
+        let back = retry.out_edges(charge);
+        assert_eq!(back.len(), 1);
+        assert_eq!(back[0].target, header);
+        assert_eq!(
+            back[0].relationship.relationship_type,
+            BaseRelationshipType::Loop
+        );
+        assert!(!retry.has_cycle());
+        assert_eq!(retry.topological_order().unwrap()[0], header);
+
+        // The loop is left through its header, and stays acyclic when sequenced
+        let receipt = GraphComposition::composite("Receipt");
+        let flow = retry.then(&receipt).unwrap().with_acyclicity().unwrap();
+        assert!(flow.is_reachable(header, receipt.composition_root));
+        assert_eq!(flow.out_degree(charge), 1);
+        assert!(flow.check_invariants().is_ok());
+
+        // A plain edge back to the header is still a cycle
+        let broken = retry.add_edge(charge, header, BaseRelationshipType::Sequence);
+        let cycles = broken.find_cycles();
+        assert_eq!(cycles.len(), 1);
+        assert!(cycles[0].contains(&header) && cycles[0].contains(&charge));
+
+        let lines = line_item_graph("Widget", 1, 5.0)
+            .for_each("/order/lines")
+            .unwrap();
+        let context = serde_json::json!({ "order": { "lines": [{}, {}] } });
+        let kind = lines.loop_kind(lines.composition_root).unwrap();
+        assert_eq!(kind.iterations(&context), Some(2));
+        let restored: GraphComposition =
+            serde_json::from_str(&serde_json::to_string(&lines).unwrap()).unwrap();
+        assert!(!restored.has_cycle());
+
+        let poll = payment
+            .while_guard(Guard::when(Condition::equals("/status", "pending")))
+            .unwrap();
+        assert!(matches!(
+            poll.loop_kind(poll.composition_root),
+            Some(LoopKind::While { .. })
+        ));
+        assert!(payment.repeat(0).is_err());
+        assert!(payment.for_each("order.lines").is_err());
+    }
+
+    #[test]
+    fn test_set_operations_on_org_chart_versions() {
+        let chart = |teams: &[(&str, i64)]| {
+            let mut graph = GraphComposition::composite("Org")
+                .add_node(BaseNodeType::Entity, "sales", serde_json::json!({}))
+                .add_edge_by_label("root", "sales", BaseRelationshipType::Hierarchy);
+            for (team, size) in teams {
+                graph = graph
+                    .add_node(
+                        BaseNodeType::Entity,
+                        team,
+                        serde_json::json!({ "size": size }),
+                    )
+                    .add_edge_by_label("sales", team, BaseRelationshipType::Hierarchy);
             }
-        }
+            graph
+        };
+        let before = chart(&[("emea", 5), ("apac", 3)]);
+        let after = chart(&[("emea", 7), ("americas", 4)]);
+        let emea = before.node_by_label("emea").unwrap().id;
+        assert_eq!(before.label_path(emea).unwrap(), "root/sales/emea");
+
+        let union = before
+            .union(&after, NodeIdentity::LabelPath, DataMerge::KeepOther)
+            .unwrap();
+        assert_eq!(union.nodes.len(), 5);
+        assert_eq!(union.edges.len(), 4);
+        assert_eq!(union.composition_root, before.composition_root);
+        assert_eq!(union.nodes[&emea].data["size"], 7);
+        assert!(union.node_by_label("root/sales/americas").is_ok());
+        assert_eq!(
+            before
+                .union(&after, NodeIdentity::LabelPath, DataMerge::RequireEqual)
+                .unwrap_err(),
+            CompositionError::DataConflict(emea)
+        );
+
+        let common = before
+            .intersection(&after, NodeIdentity::LabelPath, DataMerge::KeepSelf)
+            .unwrap();
+        let mut labels: Vec<&str> = common.nodes.values().map(|n| n.label.as_str()).collect();
+        labels.sort();
+        assert_eq!(labels, vec!["emea", "root", "sales"]);
+        assert_eq!(common.edges.len(), 2);
+        assert_eq!(common.nodes[&emea].data["size"], 5);
+
+        // Removed teams, anchored at the root; unchanged structure drops out
+        let removed = before.difference(&after, NodeIdentity::LabelPath).unwrap();
+        let mut labels: Vec<&str> = removed.nodes.values().map(|n| n.label.as_str()).collect();
+        labels.sort();
+        assert_eq!(labels, vec!["apac", "root", "sales"]);
+        assert_eq!(removed.edges.len(), 1);
+        assert_eq!(removed.composition_root, before.composition_root);
+
+        // Different versions share no IDs, so by ID nothing matches
+        let disjoint = before.difference(&after, NodeIdentity::Id).unwrap();
+        assert_eq!(disjoint.nodes.len(), before.nodes.len());
+        assert!(before
+            .intersection(&after, NodeIdentity::Id, DataMerge::Deep)
+            .is_err());
+        assert_eq!(
+            before
+                .intersection(&before, NodeIdentity::Id, DataMerge::RequireEqual)
+                .unwrap()
+                .edges
+                .len(),
+            before.edges.len()
+        );
+    }
+
+    #[test]
+    fn test_intersection_skips_dangling_edges() {
+        let graph = GraphComposition::composite("Order")
+            .add_node(BaseNodeType::Value, "total", 100)
+            .add_edge_by_label("root", "total", BaseRelationshipType::Contains);
+        let ghost = NodeId::new();
+        let broken = graph.clone().add_edge(
+            graph.composition_root,
+            ghost,
+            BaseRelationshipType::Contains,
+        );
+        assert!(broken.validate_structure().is_err());
+
+        let common = broken
+            .intersection(&graph, NodeIdentity::Id, DataMerge::KeepSelf)
+            .unwrap();
+        assert_eq!(common.nodes.len(), 2);
+        assert_eq!(common.edges.len(), 1);
+        assert!(common.validate_structure().is_ok());
     }
 }
@@ -21,6 +21,7 @@ pub trait Decomposable: Sized {
 #[cfg(feature = "document")]
 pub mod document {
     use super::*;
+    use crate::IdStrategy;
     use cim_domain::AggregateRoot;
     use cim_domain_document::aggregate::{
         Document, DocumentInfoComponent, ContentAddressComponent,
@@ -29,7 +30,9 @@ pub mod document {
 
     impl Composable for Document {
         fn to_graph(&self) -> GraphComposition {
-            let mut graph = GraphComposition::aggregate("Document", self.id().to_string());
+            let aggregate_id = self.id().to_string();
+            let mut graph = GraphComposition::aggregate("Document", aggregate_id.clone())
+                .with_id_strategy(IdStrategy::for_aggregate("Document", &aggregate_id));
 
             // Add document info if available
             if let Some(info) = self.get_component::<DocumentInfoComponent>() {
@@ -103,6 +106,7 @@ pub mod document {
     /// Create a document processing pipeline graph
     pub fn create_processing_pipeline() -> GraphComposition {
         GraphComposition::composite("DocumentPipeline")
+            .with_id_strategy(IdStrategy::named("DocumentPipeline"))
             .add_node(BaseNodeType::Custom("Stage".to_string()), "ingest", json!({
                 "type": "Document Ingestion",
                 "accepts": ["pdf", "docx", "md", "txt"],
@@ -131,23 +135,24 @@ pub mod document {
 #[cfg(feature = "graph")]
 pub mod graph {
     use super::*;
+    use crate::IdStrategy;
     use cim_domain::AggregateRoot;
     use cim_domain_graph::aggregate::ConceptGraph;
 
     impl Composable for ConceptGraph {
         fn to_graph(&self) -> GraphComposition {
-            let mut graph = GraphComposition::aggregate("ConceptGraph", self.id().to_string());
+            let aggregate_id = self.id().to_string();
+            let mut graph = GraphComposition::aggregate("ConceptGraph", aggregate_id.clone())
+                .with_id_strategy(IdStrategy::for_aggregate("ConceptGraph", &aggregate_id));
 
             // Store node ID mappings for edges
             let mut node_id_map = std::collections::HashMap::new();
 
             // Add nodes from the concept graph
             for (domain_node_id, node) in self.nodes() {
-                let local_node_id = NodeId::new();
                 let node_label = format!("node_{domain_node_id}");
 
-                graph = graph.add_node_with_id(
-                    local_node_id,
+                graph = graph.add_node(
                     BaseNodeType::Custom("Concept".to_string()),
                     &node_label,
                     json!({
@@ -158,8 +163,10 @@ pub mod graph {
                     })
                 );
 
-                // Store mapping from domain NodeId to local NodeId
-                node_id_map.insert(domain_node_id, local_node_id);
+                // Store mapping from domain NodeId to the ID the graph derived
+                if let Ok(local_node) = graph.node_by_label(&node_label) {
+                    node_id_map.insert(domain_node_id, local_node.id);
+                }
             }
 
             // Add relationships using the mapped node IDs
@@ -186,12 +193,15 @@ pub mod graph {
 #[cfg(feature = "person")]
 pub mod person {
     use super::*;
+    use crate::IdStrategy;
     use cim_domain::AggregateRoot;
     use cim_domain_person::aggregate::{Person, IdentityComponent, ContactComponent};
 
     impl Composable for Person {
         fn to_graph(&self) -> GraphComposition {
-            let mut graph = GraphComposition::aggregate("Person", self.id().to_string());
+            let aggregate_id = self.id().to_string();
+            let mut graph = GraphComposition::aggregate("Person", aggregate_id.clone())
+                .with_id_strategy(IdStrategy::for_aggregate("Person", &aggregate_id));
 
             // Add identity component if available
             if let Some(identity) = self.get_component::<IdentityComponent>() {
@@ -231,6 +241,7 @@ pub mod person {
 #[cfg(feature = "workflow")]
 pub mod workflow {
     use super::*;
+    use crate::IdStrategy;
     use cim_domain::AggregateRoot;
     use cim_domain_workflow::aggregate::WorkflowAggregate;
     use cim_domain_workflow::{WorkflowState, TransitionInput, TransitionOutput};
@@ -242,7 +253,9 @@ pub mod workflow {
         O: TransitionOutput,
     {
         fn to_graph(&self) -> GraphComposition {
-            let mut graph = GraphComposition::aggregate("Workflow", self.id().to_string());
+            let aggregate_id = self.id().to_string();
+            let mut graph = GraphComposition::aggregate("Workflow", aggregate_id.clone())
+                .with_id_strategy(IdStrategy::for_aggregate("Workflow", &aggregate_id));
 
             // Add current state
             graph = graph.add_node(
@@ -260,7 +273,11 @@ pub mod workflow {
                 "metadata",
                 json!({
                     "status": format!("{:?}", self.status()),
-                    "started_at": self.started_at.elapsed().unwrap().as_secs(),
+                    "started_at": self
+                        .started_at
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|since| since.as_secs())
+                        .unwrap_or_default(),
                     "transition_count": self.transition_count(),
                 })
             );
@@ -277,12 +294,15 @@ pub mod workflow {
 #[cfg(feature = "location")]
 pub mod location {
     use super::*;
+    use crate::IdStrategy;
     use cim_domain::AggregateRoot;
     use cim_domain_location::aggregate::Location;
 
     impl Composable for Location {
         fn to_graph(&self) -> GraphComposition {
-            let mut graph = GraphComposition::aggregate("Location", self.id().to_string());
+            let aggregate_id = self.id().to_string();
+            let mut graph = GraphComposition::aggregate("Location", aggregate_id.clone())
+                .with_id_strategy(IdStrategy::for_aggregate("Location", &aggregate_id));
 
             // Add basic location info
             graph = graph.add_node(
@@ -328,12 +348,39 @@ pub mod location {
             graph
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use cim_domain::EntityId;
+        use cim_domain_location::value_objects::Address;
+
+        #[test]
+        fn test_location_graph_is_reproducible() {
+            let address = Address::new(
+                "1 Main St".to_string(),
+                "Springfield".to_string(),
+                "IL".to_string(),
+                "US".to_string(),
+                "62701".to_string(),
+            );
+            let location =
+                Location::new_physical(EntityId::new(), "Head office".to_string(), address)
+                    .unwrap();
+
+            let graph = location.to_graph();
+            assert_eq!(graph, location.to_graph());
+            assert_eq!(graph.node_by_label("address").unwrap().data["city"], "Springfield");
+            assert!(graph.validate_structure().is_ok());
+        }
+    }
 }
 
 // Agent domain compositions (when feature enabled)
 #[cfg(feature = "agent")]
 pub mod agent {
     use super::*;
+    use crate::IdStrategy;
     use cim_domain::AggregateRoot;
     use cim_domain_agent::aggregate::{
         Agent, AgentMetadata, CapabilitiesComponent,
@@ -342,7 +389,9 @@ pub mod agent {
 
     impl Composable for Agent {
         fn to_graph(&self) -> GraphComposition {
-            let mut graph = GraphComposition::aggregate("Agent", self.id().to_string());
+            let aggregate_id = self.id().to_string();
+            let mut graph = GraphComposition::aggregate("Agent", aggregate_id.clone())
+                .with_id_strategy(IdStrategy::for_aggregate("Agent", &aggregate_id));
 
             // Add basic agent info
             graph = graph.add_node(
@@ -419,6 +468,7 @@ pub mod agent {
     /// Create an agent capability graph
     pub fn create_agent_network() -> GraphComposition {
         GraphComposition::composite("AgentNetwork")
+            .with_id_strategy(IdStrategy::named("AgentNetwork"))
             .add_node(BaseNodeType::Custom("AgentType".to_string()), "human_agents", json!({
                 "type": "Human Agents",
                 "description": "Human-controlled agents in the system",
@@ -451,6 +501,7 @@ pub mod agent {
 #[cfg(feature = "organization")]
 pub mod organization {
     use super::*;
+    use crate::IdStrategy;
     use cim_domain::AggregateRoot;
     use cim_domain_organization::organization::{
         Organization, OrganizationMetadata, BudgetComponent,
@@ -458,7 +509,9 @@ pub mod organization {
 
     impl Composable for Organization {
         fn to_graph(&self) -> GraphComposition {
-            let mut graph = GraphComposition::aggregate("Organization", self.id().to_string());
+            let aggregate_id = self.id().to_string();
+            let mut graph = GraphComposition::aggregate("Organization", aggregate_id.clone())
+                .with_id_strategy(IdStrategy::for_aggregate("Organization", &aggregate_id));
 
             // Add basic organization info
             graph = graph.add_node(
@@ -550,6 +603,7 @@ pub mod organization {
     /// Create an organizational hierarchy graph
     pub fn create_org_hierarchy() -> GraphComposition {
         GraphComposition::composite("OrganizationalHierarchy")
+            .with_id_strategy(IdStrategy::named("OrganizationalHierarchy"))
             .add_node(BaseNodeType::Custom("Level".to_string()), "company", json!({
                 "level": "Company",
                 "description": "Top-level organization",
@@ -577,12 +631,15 @@ pub mod organization {
 #[cfg(feature = "conceptualspaces")]
 pub mod conceptualspaces {
     use super::*;
+    use crate::IdStrategy;
     use cim_domain::AggregateRoot;
     use cim_domain_conceptualspaces::{ConceptualSpaceAggregate, ConceptualPoint};
 
     impl Composable for ConceptualSpaceAggregate {
         fn to_graph(&self) -> GraphComposition {
-            let mut graph = GraphComposition::aggregate("ConceptualSpace", self.id().to_string());
+            let aggregate_id = self.id().to_string();
+            let mut graph = GraphComposition::aggregate("ConceptualSpace", aggregate_id.clone())
+                .with_id_strategy(IdStrategy::for_aggregate("ConceptualSpace", &aggregate_id));
 
             // Add space metadata
             graph = graph.add_node(
@@ -633,6 +690,7 @@ pub mod conceptualspaces {
     /// Create a conceptual space visualization
     pub fn create_conceptual_space_viz() -> GraphComposition {
         GraphComposition::composite("ConceptualSpaceVisualization")
+            .with_id_strategy(IdStrategy::named("ConceptualSpaceVisualization"))
             .add_node(BaseNodeType::Custom("Space".to_string()), "space", json!({
                 "type": "Conceptual Space",
                 "description": "High-dimensional semantic space",
@@ -652,17 +710,12 @@ pub mod conceptualspaces {
 }
 
 /// Compose multiple domain objects into a knowledge graph
+///
+/// The objects' graphs are joined in parallel under a single root.
 pub fn compose_knowledge_graph<T: Composable>(objects: &[T]) -> GraphComposition {
-    if objects.is_empty() {
-        return GraphComposition::composite("KnowledgeGraph");
+    match objects {
+        [] => GraphComposition::composite("KnowledgeGraph"),
+        [object] => object.to_graph(),
+        _ => GraphComposition::parallel_all(objects.iter().map(Composable::to_graph)),
     }
-
-    let mut graph = objects[0].to_graph();
-
-    for obj in &objects[1..] {
-        graph = graph.parallel(&obj.to_graph())
-            .unwrap_or_else(|_| graph.clone());
-    }
-
-    graph
 }
@@ -0,0 +1,493 @@
+//! GraphViz DOT rendering of compositions
+//!
+//! `to_dot` draws each node type with its own shape and each relationship
+//! type with its own edge style, and groups the branches of parallel and
+//! choice forks into clusters. Styles are chosen by the serialized variant
+//! name of a type, as `InvariantRule::AllowedNodeTypes` compares them, so
+//! other node and relationship types that reuse the base variant names are
+//! drawn the same way. `DotOptions` adds callbacks for styling any type.
+
+use crate::base_types::*;
+use crate::composition::{
+    fork_kind, CompositionEdge, CompositionNode, GraphComposition, GRAPH_NAME_KEY,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::fmt::Write;
+
+/// DOT attributes of a node or edge, such as `shape` or `style`
+pub type DotAttributes = BTreeMap<String, String>;
+
+/// Callback adjusting the attributes of a node
+type NodeStyle<'a, N> = Box<dyn Fn(&CompositionNode<N>, &mut DotAttributes) + 'a>;
+
+/// Callback adjusting the attributes of an edge
+type EdgeStyle<'a, R> = Box<dyn Fn(&CompositionEdge<R>, &mut DotAttributes) + 'a>;
+
+/// How `GraphComposition::to_dot_with` renders a composition
+pub struct DotOptions<'a, N = BaseNodeType, R = BaseRelationshipType> {
+    clusters: bool,
+    node_style: Option<NodeStyle<'a, N>>,
+    edge_style: Option<EdgeStyle<'a, R>>,
+}
+
+impl<'a, N, R> DotOptions<'a, N, R> {
+    pub fn new() -> Self {
+        Self {
+            clusters: true,
+            node_style: None,
+            edge_style: None,
+        }
+    }
+
+    /// Draw the branches of forks without grouping them into clusters
+    pub fn without_clusters(mut self) -> Self {
+        self.clusters = false;
+        self
+    }
+
+    /// Adjust the attributes of each node after the defaults are applied
+    pub fn node_style(
+        mut self,
+        style: impl Fn(&CompositionNode<N>, &mut DotAttributes) + 'a,
+    ) -> Self {
+        self.node_style = Some(Box::new(style));
+        self
+    }
+
+    /// Adjust the attributes of each edge after the defaults are applied
+    pub fn edge_style(
+        mut self,
+        style: impl Fn(&CompositionEdge<R>, &mut DotAttributes) + 'a,
+    ) -> Self {
+        self.edge_style = Some(Box::new(style));
+        self
+    }
+}
+
+impl<N, R> Default for DotOptions<'_, N, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The branch of a fork drawn as a cluster
+struct Cluster {
+    label: String,
+    style: &'static str,
+    members: BTreeSet<NodeId>,
+}
+
+impl<N, R> GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Render the composition in GraphViz DOT with the default styles
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with(&DotOptions::new())
+    }
+
+    /// Render the composition in GraphViz DOT
+    ///
+    /// Nodes and edges are written in a stable order, so rendering the same
+    /// graph twice gives the same text.
+    pub fn to_dot_with(&self, options: &DotOptions<N, R>) -> String {
+        let clusters = if options.clusters {
+            self.dot_clusters()
+        } else {
+            Vec::new()
+        };
+
+        // Each node is drawn in the innermost cluster containing it; clusters
+        // are sorted outermost first, so that is the last one
+        let mut nodes: Vec<&CompositionNode<N>> = self.nodes.values().collect();
+        nodes.sort_by_key(|node| (display_label(node), node.id));
+        let mut top_level = Vec::new();
+        let mut by_cluster: Vec<Vec<&CompositionNode<N>>> = vec![Vec::new(); clusters.len()];
+        for node in nodes {
+            match clusters
+                .iter()
+                .rposition(|(c, _)| c.members.contains(&node.id))
+            {
+                Some(index) => by_cluster[index].push(node),
+                None => top_level.push(node),
+            }
+        }
+
+        let mut dot = format!("digraph {} {{\n", quote(&self.metadata.name));
+        for node in top_level {
+            self.write_node(&mut dot, node, options, 1);
+        }
+        for index in (0..clusters.len()).filter(|i| clusters[*i].1.is_none()) {
+            self.write_cluster(&mut dot, &clusters, &by_cluster, index, options, 1);
+        }
+
+        let mut edges: Vec<&CompositionEdge<R>> = self.edges.values().collect();
+        edges.sort_by_key(|edge| (edge.source, edge.target, edge.id));
+        for edge in edges {
+            let mut attributes = edge_attributes(edge);
+            if let Some(style) = &options.edge_style {
+                style(edge, &mut attributes);
+            }
+            let _ = writeln!(
+                dot,
+                "  {} -> {}{};",
+                quote(&edge.source.to_string()),
+                quote(&edge.target.to_string()),
+                attribute_list(&attributes)
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn write_node(
+        &self,
+        dot: &mut String,
+        node: &CompositionNode<N>,
+        options: &DotOptions<N, R>,
+        depth: usize,
+    ) {
+        let mut attributes = node_attributes(node);
+        if node.id == self.composition_root {
+            attributes.insert("peripheries".to_string(), "2".to_string());
+        }
+        if let Some(style) = &options.node_style {
+            style(node, &mut attributes);
+        }
+        let _ = writeln!(
+            dot,
+            "{}{}{};",
+            "  ".repeat(depth),
+            quote(&node.id.to_string()),
+            attribute_list(&attributes)
+        );
+    }
+
+    fn write_cluster(
+        &self,
+        dot: &mut String,
+        clusters: &[(Cluster, Option<usize>)],
+        by_cluster: &[Vec<&CompositionNode<N>>],
+        index: usize,
+        options: &DotOptions<N, R>,
+        depth: usize,
+    ) {
+        let indent = "  ".repeat(depth);
+        let (cluster, _) = &clusters[index];
+        let _ = writeln!(dot, "{indent}subgraph cluster_{index} {{");
+        let _ = writeln!(dot, "{indent}  label={};", quote(&cluster.label));
+        let _ = writeln!(dot, "{indent}  style={};", quote(cluster.style));
+        for node in &by_cluster[index] {
+            self.write_node(dot, node, options, depth + 1);
+        }
+        for child in (0..clusters.len()).filter(|i| clusters[*i].1 == Some(index)) {
+            self.write_cluster(dot, clusters, by_cluster, child, options, depth + 1);
+        }
+        let _ = writeln!(dot, "{indent}}}");
+    }
+
+    /// Clusters for the branches of every fork, outermost first, each with
+    /// the index of the cluster it is nested in
+    ///
+    /// A branch holds the nodes reachable from its entry but from no other
+    /// branch of the same fork, so the stages after a fork joins stay
+    /// outside. Branches that would partly overlap an earlier cluster are
+    /// left undrawn, since DOT clusters must nest.
+    fn dot_clusters(&self) -> Vec<(Cluster, Option<usize>)> {
+        let mut forks: Vec<(&CompositionNode<N>, &'static str)> = self
+            .nodes
+            .values()
+            .filter_map(|node| match fork_kind(node) {
+                Some("Parallel") => Some((node, "rounded")),
+                Some("Choice") => Some((node, "dashed")),
+                _ => None,
+            })
+            .collect();
+        forks.sort_by_key(|(node, _)| node.id);
+
+        let mut candidates = Vec::new();
+        for (fork, style) in forks {
+            let mut entries: Vec<NodeId> =
+                self.out_edges(fork.id).iter().map(|e| e.target).collect();
+            entries.sort();
+            entries.dedup();
+            let reached: Vec<HashSet<NodeId>> = entries
+                .iter()
+                .map(|entry| self.reached_without(*entry, fork.id))
+                .collect();
+            for (index, entry) in entries.iter().enumerate() {
+                let members: BTreeSet<NodeId> = reached[index]
+                    .iter()
+                    .filter(|node_id| {
+                        reached
+                            .iter()
+                            .enumerate()
+                            .all(|(other, set)| other == index || !set.contains(node_id))
+                    })
+                    .copied()
+                    .collect();
+                if !members.is_empty() {
+                    candidates.push(Cluster {
+                        label: self.nodes.get(entry).map(display_label).unwrap_or_default(),
+                        style,
+                        members,
+                    });
+                }
+            }
+        }
+        candidates.sort_by_key(|cluster| Reverse(cluster.members.len()));
+
+        let mut clusters: Vec<(Cluster, Option<usize>)> = Vec::new();
+        for cluster in candidates {
+            let mut parent = None;
+            let mut nests = true;
+            for (index, (other, _)) in clusters.iter().enumerate() {
+                if cluster.members.is_subset(&other.members) {
+                    parent = Some(index);
+                    nests = cluster.members != other.members;
+                } else if !cluster.members.is_disjoint(&other.members) {
+                    nests = false;
+                }
+                if !nests {
+                    break;
+                }
+            }
+            if nests {
+                clusters.push((cluster, parent));
+            }
+        }
+        clusters
+    }
+
+    /// Nodes reachable from `start` without passing through `barrier` or
+    /// following a back-edge
+    fn reached_without(&self, start: NodeId, barrier: NodeId) -> HashSet<NodeId> {
+        let mut reached = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(node_id) = queue.pop_front() {
+            for edge in self.out_edges(node_id) {
+                if !edge.relationship.is_back_edge()
+                    && edge.target != barrier
+                    && reached.insert(edge.target)
+                {
+                    queue.push_back(edge.target);
+                }
+            }
+        }
+        reached
+    }
+}
+
+/// Graph name stored on the root of a composed graph
+pub(crate) fn graph_name<N>(node: &CompositionNode<N>) -> Option<&str> {
+    node.metadata
+        .get(GRAPH_NAME_KEY)
+        .and_then(JsonValue::as_str)
+}
+
+/// The name a node is drawn with: its label, or its graph name for the
+/// unlabelled root of a composed graph
+pub(crate) fn display_label<N>(node: &CompositionNode<N>) -> String {
+    match graph_name(node) {
+        Some(name) if node.label == "root" => name.to_string(),
+        _ => node.label.clone(),
+    }
+}
+
+/// The variant name of a serialized enum value, such as `Custom` for `Custom("x")`
+pub(crate) fn variant_name(value: &JsonValue) -> Option<&str> {
+    match value {
+        JsonValue::String(name) => Some(name),
+        JsonValue::Object(fields) if fields.len() == 1 => fields.keys().next().map(String::as_str),
+        _ => None,
+    }
+}
+
+/// A readable name for a serialized type, such as `x` for `Custom("x")`
+pub(crate) fn type_label(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(name) => name.clone(),
+        JsonValue::Object(fields) if fields.len() == 1 => match fields.iter().next() {
+            Some((_, JsonValue::String(inner))) => inner.clone(),
+            Some((name, _)) => name.clone(),
+            None => String::new(),
+        },
+        other => other.to_string(),
+    }
+}
+
+fn node_attributes<N: Serialize>(node: &CompositionNode<N>) -> DotAttributes {
+    let node_type = serde_json::to_value(&node.node_type).unwrap_or(JsonValue::Null);
+    let (shape, style) = match variant_name(&node_type) {
+        Some("Value") => ("ellipse", None),
+        Some("EntityReference") => ("note", None),
+        Some("Entity") => ("box", Some("rounded")),
+        Some("Aggregate") => ("box3d", None),
+        Some("Service") => ("component", None),
+        Some("Command") => ("cds", None),
+        Some("Event") => ("parallelogram", None),
+        Some("Custom") => ("hexagon", None),
+        _ => ("box", None),
+    };
+
+    let mut attributes = DotAttributes::new();
+    attributes.insert("label".to_string(), display_label(node));
+    attributes.insert("shape".to_string(), shape.to_string());
+    if let Some(style) = style {
+        attributes.insert("style".to_string(), style.to_string());
+    }
+    attributes.insert("tooltip".to_string(), type_label(&node_type));
+    attributes
+}
+
+fn edge_attributes<R: Serialize>(edge: &CompositionEdge<R>) -> DotAttributes {
+    let relationship_type =
+        serde_json::to_value(&edge.relationship.relationship_type).unwrap_or(JsonValue::Null);
+    let styles: &[(&str, &str)] = match variant_name(&relationship_type) {
+        Some("Contains") => &[("style", "bold")],
+        Some("References") => &[("style", "dashed")],
+        Some("DependsOn") => &[("style", "dotted")],
+        Some("Parallel") => &[("color", "blue")],
+        Some("Choice") => &[("arrowtail", "odiamond"), ("dir", "both")],
+        Some("Hierarchy") => &[("arrowhead", "empty")],
+        Some("Loop") => &[("color", "gray"), ("style", "dashed")],
+        Some("Custom") => &[("arrowhead", "vee")],
+        _ => &[],
+    };
+
+    let mut attributes: DotAttributes = styles
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    attributes.insert("label".to_string(), type_label(&relationship_type));
+    if edge.relationship.bidirectional {
+        attributes.insert("dir".to_string(), "both".to_string());
+    }
+    if edge.relationship.is_back_edge() {
+        // Keep loops from pulling the header below its body
+        attributes.insert("constraint".to_string(), "false".to_string());
+    }
+    attributes
+}
+
+fn attribute_list(attributes: &DotAttributes) -> String {
+    if attributes.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = attributes
+        .iter()
+        .map(|(key, value)| format!("{key}={}", quote(value)))
+        .collect();
+    format!(" [{}]", pairs.join(", "))
+}
+
+/// A DOT double-quoted string
+fn quote(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The line declaring a node
+    fn node_line(dot: &str, node_id: NodeId) -> &str {
+        let id = quote(&node_id.to_string());
+        dot.lines()
+            .find(|line| line.trim_start().starts_with(&id) && !line.contains("->"))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_to_dot_styles_by_type() {
+        let graph = GraphComposition::aggregate("Order", "o-1")
+            .add_node(BaseNodeType::Value, "total", json!(250))
+            .add_node(BaseNodeType::Event, "say \"placed\"", json!({}))
+            .add_node(BaseNodeType::Custom("Policy".into()), "pricing", json!({}))
+            .add_edge_by_label("root", "total", BaseRelationshipType::Contains)
+            .add_edge_by_label("root", "pricing", BaseRelationshipType::References);
+        let (total, pricing) = (
+            graph.node_by_label("total").unwrap().id,
+            graph.node_by_label("pricing").unwrap().id,
+        );
+        let graph = graph.add_edge_with_relationship(
+            total,
+            pricing,
+            Relationship::new(BaseRelationshipType::DependsOn).bidirectional(),
+        );
+        let id = |label: &str| graph.node_by_label(label).unwrap().id;
+        let dot = graph.to_dot();
+
+        assert!(dot.starts_with("digraph \"Order\" {\n"));
+        let root = node_line(&dot, graph.composition_root);
+        assert!(root.contains("label=\"Order\""));
+        assert!(root.contains("shape=\"box3d\""));
+        assert!(root.contains("peripheries=\"2\""));
+        assert!(node_line(&dot, id("total")).contains("shape=\"ellipse\""));
+        assert!(node_line(&dot, id("pricing")).contains("shape=\"hexagon\""));
+        assert!(node_line(&dot, id("pricing")).contains("tooltip=\"Policy\""));
+        assert!(node_line(&dot, id("say \"placed\"")).contains("label=\"say \\\"placed\\\"\""));
+        assert!(dot.contains("label=\"Contains\", style=\"bold\"]"));
+        assert!(dot.contains("label=\"References\", style=\"dashed\"]"));
+        assert!(dot.contains("dir=\"both\", label=\"DependsOn\", style=\"dotted\"]"));
+        assert_eq!(dot, graph.to_dot());
+    }
+
+    #[test]
+    fn test_fork_clusters_and_custom_styles() {
+        let stage = |name: &str| {
+            GraphComposition::composite(name).add_node(BaseNodeType::Command, "run", json!({}))
+        };
+        let checks = stage("Lint").parallel(&stage("Test")).unwrap();
+        let pipeline = checks.then(&stage("Deploy")).unwrap();
+        let dot = pipeline.to_dot();
+
+        // One cluster per branch; the stage after the join is in neither
+        assert_eq!(dot.matches("subgraph cluster_").count(), 2);
+        assert!(dot.contains("label=\"Lint\";"));
+        assert!(dot.contains("label=\"Test\";"));
+        let deploy = pipeline
+            .nodes
+            .values()
+            .find(|node| graph_name(node) == Some("Deploy"))
+            .unwrap();
+        assert!(node_line(&dot, deploy.id).starts_with("  \""));
+        assert!(dot.contains("color=\"blue\", label=\"Parallel\""));
+        assert!(!pipeline
+            .to_dot_with(&DotOptions::new().without_clusters())
+            .contains("subgraph"));
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        enum Step {
+            Task(u8),
+        }
+        let custom = GraphComposition::<Step, String>::new(
+            Step::Task(0),
+            crate::composition::CompositionType::Atomic {
+                value_type: "Task".to_string(),
+            },
+        );
+        let options = DotOptions::new()
+            .node_style(
+                |node: &CompositionNode<Step>, attributes: &mut DotAttributes| {
+                    let Step::Task(priority) = node.node_type;
+                    attributes.insert("fillcolor".to_string(), format!("/blues9/{}", priority + 1));
+                },
+            )
+            .edge_style(|_: &CompositionEdge<String>, _: &mut DotAttributes| {});
+        let dot = custom.to_dot_with(&options);
+        let root = node_line(&dot, custom.composition_root);
+        assert!(root.contains("fillcolor=\"/blues9/1\""));
+        assert!(root.contains("shape=\"box\""));
+        assert!(root.contains("tooltip=\"Task\""));
+    }
+}
@@ -0,0 +1,285 @@
+//! GEXF export and import
+//!
+//! GEXF 1.3 is Gephi's native format. Composition attributes are declared as
+//! GEXF node and edge attributes (see the `xml` module for how values are
+//! written), and the composition header is the description in the file's
+//! `meta` element. Exporting and importing a graph gives back the same graph,
+//! with the same IDs.
+
+use crate::composition::{CompositionError, GraphComposition};
+use crate::xml::{self, ImportedEdge, ImportedNode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Attributes declared on export, as `(class, id and title, type)`
+const ATTRIBUTES: [(&str, &str, &str); 6] = [
+    ("node", xml::NODE_TYPE, "string"),
+    ("node", xml::DATA, "string"),
+    ("node", xml::METADATA, "string"),
+    ("edge", xml::RELATIONSHIP_TYPE, "string"),
+    ("edge", xml::BIDIRECTIONAL, "boolean"),
+    ("edge", xml::METADATA, "string"),
+];
+
+impl<N, R> GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Write the composition as GEXF 1.3, with nodes and edges sorted by ID
+    ///
+    /// Edges are labelled with their relationship type, and bidirectional
+    /// edges have the `mutual` edge type.
+    pub fn to_gexf(&self) -> String {
+        let mut gexf = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n",
+            "  <meta>\n",
+            "    <creator>cim-compose</creator>\n",
+        ));
+        let _ = writeln!(
+            gexf,
+            "    <description>{}</description>\n  </meta>",
+            xml::escape(&xml::header(self))
+        );
+        gexf.push_str("  <graph mode=\"static\" defaultedgetype=\"directed\">\n");
+        for class in ["node", "edge"] {
+            let _ = writeln!(gexf, "    <attributes class=\"{class}\">");
+            for (_, id, kind) in ATTRIBUTES.iter().filter(|(c, _, _)| *c == class) {
+                let _ = writeln!(
+                    gexf,
+                    "      <attribute id=\"{id}\" title=\"{id}\" type=\"{kind}\"/>"
+                );
+            }
+            gexf.push_str("    </attributes>\n");
+        }
+
+        let attvalues = |values: [(&str, String); 3]| {
+            let mut text = String::from("<attvalues>");
+            for (id, value) in values {
+                let _ = write!(
+                    text,
+                    "<attvalue for=\"{id}\" value=\"{}\"/>",
+                    xml::escape(&value)
+                );
+            }
+            text.push_str("</attvalues>");
+            text
+        };
+
+        let (nodes, edges) = xml::sorted(self);
+        gexf.push_str("    <nodes>\n");
+        for node in nodes {
+            let _ = writeln!(
+                gexf,
+                "      <node id=\"{}\" label=\"{}\">{}</node>",
+                node.id,
+                xml::escape(&node.label),
+                attvalues([
+                    (xml::NODE_TYPE, xml::type_text(&node.node_type)),
+                    (xml::DATA, node.data.to_string()),
+                    (
+                        xml::METADATA,
+                        serde_json::to_string(&node.metadata).unwrap_or_default()
+                    ),
+                ])
+            );
+        }
+        gexf.push_str("    </nodes>\n    <edges>\n");
+        for edge in edges {
+            let relationship = &edge.relationship;
+            let relationship_type = xml::type_text(&relationship.relationship_type);
+            let kind = if relationship.bidirectional {
+                " type=\"mutual\""
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                gexf,
+                "      <edge id=\"{}\" source=\"{}\" target=\"{}\" label=\"{}\"{kind}>{}</edge>",
+                edge.id,
+                edge.source,
+                edge.target,
+                xml::escape(&relationship_type),
+                attvalues([
+                    (xml::RELATIONSHIP_TYPE, relationship_type.clone()),
+                    (xml::BIDIRECTIONAL, relationship.bidirectional.to_string()),
+                    (
+                        xml::METADATA,
+                        serde_json::to_string(&relationship.metadata).unwrap_or_default()
+                    ),
+                ])
+            );
+        }
+        gexf.push_str("    </edges>\n  </graph>\n</gexf>\n");
+        gexf
+    }
+
+    /// Read a GEXF file
+    ///
+    /// Attribute values are matched through the `title` of their attribute,
+    /// so files saved by Gephi are read as long as they keep the attributes.
+    /// Nodes need a `node_type`; edges without a `relationship_type` use
+    /// their label, and without a `bidirectional` value are bidirectional
+    /// when their type is `mutual` or `undirected`. IDs that are not UUIDs
+    /// are turned into UUIDs derived from them.
+    pub fn from_gexf(text: &str) -> Result<Self, CompositionError> {
+        let document = roxmltree::Document::parse(text)
+            .map_err(|e| CompositionError::ParseError(e.to_string()))?;
+        let root = document.root_element();
+        let graph = child(root, "graph")
+            .ok_or_else(|| CompositionError::ParseError("GEXF file has no graph".to_string()))?;
+
+        // Attribute titles by class and attribute ID
+        let mut titles: HashMap<(&str, &str), &str> = HashMap::new();
+        for attributes in graph
+            .children()
+            .filter(|child| child.tag_name().name() == "attributes")
+        {
+            let class = attributes.attribute("class").unwrap_or("node");
+            for attribute in attributes.children().filter(|child| child.is_element()) {
+                if let Some(id) = attribute.attribute("id") {
+                    titles.insert((class, id), attribute.attribute("title").unwrap_or(id));
+                }
+            }
+        }
+        let attvalues = |element: roxmltree::Node<'_, '_>, class: &str| {
+            let mut values = HashMap::new();
+            if let Some(attvalues) = child(element, "attvalues") {
+                for attvalue in attvalues.children().filter(|child| child.is_element()) {
+                    if let (Some(id), Some(value)) =
+                        (attvalue.attribute("for"), attvalue.attribute("value"))
+                    {
+                        let title = titles.get(&(class, id)).copied().unwrap_or(id);
+                        values.insert(title.to_string(), value.to_string());
+                    }
+                }
+            }
+            values
+        };
+        let elements = |name: &str| {
+            child(graph, name)
+                .into_iter()
+                .flat_map(|list| list.children().filter(|child| child.is_element()))
+        };
+        let undirected = graph.attribute("defaultedgetype") == Some("undirected");
+
+        let mut nodes = Vec::new();
+        for element in elements("nodes") {
+            let mut values = attvalues(element, "node");
+            nodes.push(ImportedNode {
+                id: xml::required(element, "id")?,
+                label: element.attribute("label").map(str::to_string),
+                node_type: values.remove(xml::NODE_TYPE),
+                data: values.remove(xml::DATA),
+                metadata: values.remove(xml::METADATA),
+            });
+        }
+        let mut edges = Vec::new();
+        for element in elements("edges") {
+            let mut values = attvalues(element, "edge");
+            let two_way = match element.attribute("type") {
+                Some(kind) => kind == "mutual" || kind == "undirected",
+                None => undirected,
+            };
+            edges.push(ImportedEdge {
+                id: element.attribute("id").map(str::to_string),
+                source: xml::required(element, "source")?,
+                target: xml::required(element, "target")?,
+                relationship_type: values
+                    .remove(xml::RELATIONSHIP_TYPE)
+                    .or_else(|| element.attribute("label").map(str::to_string)),
+                bidirectional: Some(
+                    values
+                        .remove(xml::BIDIRECTIONAL)
+                        .map_or(two_way, |value| value == "true"),
+                ),
+                metadata: values.remove(xml::METADATA),
+            });
+        }
+
+        // Only a description written by `to_gexf` is a header
+        let header = child(root, "meta")
+            .and_then(|meta| child(meta, "description"))
+            .and_then(|description| description.text())
+            .filter(|text| {
+                serde_json::from_str::<JsonValue>(text).is_ok_and(|value| value.is_object())
+            });
+        xml::assemble("GEXF", header, nodes, edges)
+    }
+}
+
+/// The first child element with this name
+fn child<'a, 'input>(
+    parent: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    parent
+        .children()
+        .find(|child| child.tag_name().name() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_types::*;
+
+    #[test]
+    fn test_gexf_round_trip_is_lossless() {
+        let graph = xml::sample();
+        let gexf = graph.to_gexf();
+        assert!(gexf.contains("<attribute id=\"node_type\" title=\"node_type\" type=\"string\"/>"));
+        assert!(gexf.contains("label=\"Contains\">"));
+        assert!(gexf.contains("type=\"mutual\""));
+
+        let read: GraphComposition = GraphComposition::from_gexf(&gexf).unwrap();
+        assert_eq!(read.to_interchange_json(), graph.to_interchange_json());
+        assert_eq!(read.to_gexf(), gexf);
+        assert_eq!(read.to_graphml(), graph.to_graphml());
+    }
+
+    #[test]
+    fn test_gexf_from_gephi() {
+        let gexf = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <gexf xmlns="http://gexf.net/1.3" version="1.3">
+              <meta><creator>Gephi 0.10</creator><description>Orders</description></meta>
+              <graph defaultedgetype="undirected">
+                <attributes class="node">
+                  <attribute id="0" title="node_type" type="string"/>
+                </attributes>
+                <nodes>
+                  <node id="a" label="Order">
+                    <attvalues><attvalue for="0" value="Aggregate"/></attvalues>
+                  </node>
+                  <node id="b" label="total">
+                    <attvalues><attvalue for="0" value="Value"/></attvalues>
+                  </node>
+                </nodes>
+                <edges>
+                  <edge id="0" source="a" target="b" label="Contains"/>
+                </edges>
+              </graph>
+            </gexf>"#;
+        let graph: GraphComposition = GraphComposition::from_gexf(gexf).unwrap();
+        assert_eq!(graph.nodes[&graph.composition_root].label, "Order");
+        assert_eq!(graph.metadata.name, "GEXF");
+        assert_eq!(
+            graph.node_by_label("total").unwrap().node_type,
+            BaseNodeType::Value
+        );
+        let edge = graph.edges.values().next().unwrap();
+        assert_eq!(
+            edge.relationship.relationship_type,
+            BaseRelationshipType::Contains
+        );
+        assert!(edge.relationship.bidirectional);
+
+        let untyped = gexf.replace(r#"<attvalue for="0" value="Value"/>"#, "");
+        assert!(matches!(
+            GraphComposition::<BaseNodeType, BaseRelationshipType>::from_gexf(&untyped),
+            Err(CompositionError::ParseError(message)) if message.contains("node_type")
+        ));
+    }
+}
@@ -0,0 +1,246 @@
+//! GraphML export and import
+//!
+//! GraphML is read by yEd, Gephi, Cytoscape and NetworkX. Each composition
+//! attribute is a GraphML key (see the `xml` module for how values are
+//! written), and the composition header is the `composition` key of the
+//! graph. Exporting and importing a graph gives back the same graph, with the
+//! same IDs.
+
+use crate::composition::{CompositionError, GraphComposition};
+use crate::xml::{self, ImportedEdge, ImportedNode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Key of the graph's composition header
+const COMPOSITION: &str = "composition";
+
+/// Key of edge metadata; node metadata uses `metadata`
+const EDGE_METADATA: &str = "edge_metadata";
+
+/// GraphML keys written on export, as `(id, for, attr.name, attr.type)`
+const KEYS: [(&str, &str, &str, &str); 8] = [
+    (COMPOSITION, "graph", COMPOSITION, "string"),
+    (xml::LABEL, "node", xml::LABEL, "string"),
+    (xml::NODE_TYPE, "node", xml::NODE_TYPE, "string"),
+    (xml::DATA, "node", xml::DATA, "string"),
+    (xml::METADATA, "node", xml::METADATA, "string"),
+    (
+        xml::RELATIONSHIP_TYPE,
+        "edge",
+        xml::RELATIONSHIP_TYPE,
+        "string",
+    ),
+    (xml::BIDIRECTIONAL, "edge", xml::BIDIRECTIONAL, "boolean"),
+    (EDGE_METADATA, "edge", xml::METADATA, "string"),
+];
+
+impl<N, R> GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Write the composition as GraphML, with nodes and edges sorted by ID
+    pub fn to_graphml(&self) -> String {
+        let mut graphml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+        ));
+        for (id, domain, name, kind) in KEYS {
+            let _ = writeln!(
+                graphml,
+                "  <key id=\"{id}\" for=\"{domain}\" attr.name=\"{name}\" attr.type=\"{kind}\"/>"
+            );
+        }
+
+        let _ = writeln!(
+            graphml,
+            "  <graph id=\"{}\" edgedefault=\"directed\">",
+            xml::escape(&self.id.to_string())
+        );
+        let data =
+            |key: &str, value: &str| format!("<data key=\"{key}\">{}</data>", xml::escape(value));
+        let _ = writeln!(graphml, "    {}", data(COMPOSITION, &xml::header(self)));
+
+        let (nodes, edges) = xml::sorted(self);
+        for node in nodes {
+            let _ = writeln!(graphml, "    <node id=\"{}\">", node.id);
+            for (key, value) in [
+                (xml::LABEL, node.label.clone()),
+                (xml::NODE_TYPE, xml::type_text(&node.node_type)),
+                (xml::DATA, node.data.to_string()),
+                (
+                    xml::METADATA,
+                    serde_json::to_string(&node.metadata).unwrap_or_default(),
+                ),
+            ] {
+                let _ = writeln!(graphml, "      {}", data(key, &value));
+            }
+            graphml.push_str("    </node>\n");
+        }
+        for edge in edges {
+            let relationship = &edge.relationship;
+            let _ = writeln!(
+                graphml,
+                "    <edge id=\"{}\" source=\"{}\" target=\"{}\">",
+                edge.id, edge.source, edge.target
+            );
+            for (key, value) in [
+                (
+                    xml::RELATIONSHIP_TYPE,
+                    xml::type_text(&relationship.relationship_type),
+                ),
+                (xml::BIDIRECTIONAL, relationship.bidirectional.to_string()),
+                (
+                    EDGE_METADATA,
+                    serde_json::to_string(&relationship.metadata).unwrap_or_default(),
+                ),
+            ] {
+                let _ = writeln!(graphml, "      {}", data(key, &value));
+            }
+            graphml.push_str("    </edge>\n");
+        }
+        graphml.push_str("  </graph>\n</graphml>\n");
+        graphml
+    }
+
+    /// Read a GraphML file
+    ///
+    /// Data is matched to attributes through the `attr.name` of its key, so
+    /// files saved by other tools are read as long as they keep the keys.
+    /// Nodes need a `node_type` and edges a `relationship_type`; an edge
+    /// without one of its own is bidirectional when it has
+    /// `directed="false"`. IDs that are not UUIDs are turned into UUIDs
+    /// derived from them.
+    pub fn from_graphml(text: &str) -> Result<Self, CompositionError> {
+        let document = roxmltree::Document::parse(text)
+            .map_err(|e| CompositionError::ParseError(e.to_string()))?;
+        let root = document.root_element();
+        let graph = root
+            .children()
+            .find(|child| child.tag_name().name() == "graph")
+            .ok_or_else(|| CompositionError::ParseError("GraphML file has no graph".to_string()))?;
+
+        // Attribute names by key ID
+        let names: HashMap<&str, &str> = root
+            .children()
+            .filter(|child| child.tag_name().name() == "key")
+            .filter_map(|key| Some((key.attribute("id")?, key.attribute("attr.name")?)))
+            .collect();
+        let data = |element: roxmltree::Node<'_, '_>| -> HashMap<String, String> {
+            element
+                .children()
+                .filter(|child| child.tag_name().name() == "data")
+                .filter_map(|data| {
+                    let key = data.attribute("key")?;
+                    let name = names.get(key).copied().unwrap_or(key);
+                    Some((
+                        name.to_string(),
+                        data.text().unwrap_or_default().to_string(),
+                    ))
+                })
+                .collect()
+        };
+        let undirected = graph.attribute("edgedefault") == Some("undirected");
+
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+        for element in graph.children().filter(|child| child.is_element()) {
+            match element.tag_name().name() {
+                "node" => {
+                    let mut data = data(element);
+                    nodes.push(ImportedNode {
+                        id: xml::required(element, "id")?,
+                        label: data.remove(xml::LABEL),
+                        node_type: data.remove(xml::NODE_TYPE),
+                        data: data.remove(xml::DATA),
+                        metadata: data.remove(xml::METADATA),
+                    });
+                }
+                "edge" => {
+                    let mut data = data(element);
+                    let directed = element
+                        .attribute("directed")
+                        .map_or(!undirected, |directed| directed == "true");
+                    edges.push(ImportedEdge {
+                        id: element.attribute("id").map(str::to_string),
+                        source: xml::required(element, "source")?,
+                        target: xml::required(element, "target")?,
+                        relationship_type: data.remove(xml::RELATIONSHIP_TYPE),
+                        bidirectional: Some(
+                            data.remove(xml::BIDIRECTIONAL)
+                                .map_or(!directed, |value| value == "true"),
+                        ),
+                        metadata: data.remove(xml::METADATA),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let header = graph
+            .children()
+            .filter(|child| child.tag_name().name() == "data")
+            .find(|data| data.attribute("key").and_then(|key| names.get(key)) == Some(&COMPOSITION))
+            .map(|data| data.text().unwrap_or_default());
+        xml::assemble("GraphML", header, nodes, edges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_types::*;
+
+    #[test]
+    fn test_graphml_round_trip_is_lossless() {
+        let graph = xml::sample();
+        let graphml = graph.to_graphml();
+        assert!(graphml.contains("attr.name=\"node_type\""));
+        assert!(graphml
+            .contains("<data key=\"node_type\">{&quot;Custom&quot;:&quot;Policy&quot;}</data>"));
+        assert!(graphml.contains("<data key=\"relationship_type\">Contains</data>"));
+
+        let read: GraphComposition = GraphComposition::from_graphml(&graphml).unwrap();
+        assert_eq!(read.to_interchange_json(), graph.to_interchange_json());
+        assert_eq!(read.to_graphml(), graphml);
+    }
+
+    #[test]
+    fn test_graphml_from_other_tools() {
+        let graphml = r#"<?xml version="1.0"?>
+            <graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+              <key id="d0" for="node" attr.name="node_type" attr.type="string"/>
+              <key id="d1" for="node" attr.name="label" attr.type="string"/>
+              <key id="d2" for="edge" attr.name="relationship_type" attr.type="string"/>
+              <key id="d3" for="node" attr.name="x" attr.type="double"/>
+              <graph id="G" edgedefault="directed">
+                <node id="n0"><data key="d0">Aggregate</data><data key="d1">Order</data></node>
+                <node id="n1"><data key="d0">Value</data><data key="d3">1.5</data></node>
+                <edge source="n0" target="n1" directed="false"><data key="d2">Contains</data></edge>
+              </graph>
+            </graphml>"#;
+        let graph: GraphComposition = GraphComposition::from_graphml(graphml).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+        let root = &graph.nodes[&graph.composition_root];
+        assert_eq!(root.label, "Order");
+        assert_eq!(root.node_type, BaseNodeType::Aggregate);
+        // Nodes without a label are labelled with their GraphML ID
+        assert_eq!(
+            graph.node_by_label("n1").unwrap().node_type,
+            BaseNodeType::Value
+        );
+        let edge = graph.edges.values().next().unwrap();
+        assert!(edge.relationship.bidirectional);
+
+        let untyped = graphml.replace("<data key=\"d0\">Value</data>", "");
+        assert!(matches!(
+            GraphComposition::<BaseNodeType, BaseRelationshipType>::from_graphml(&untyped),
+            Err(CompositionError::ParseError(message)) if message.contains("node_type")
+        ));
+        assert!(
+            GraphComposition::<BaseNodeType, BaseRelationshipType>::from_graphml("<graphml>")
+                .is_err()
+        );
+    }
+}
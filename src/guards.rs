@@ -0,0 +1,234 @@
+//! Guards and probabilities on the branches of a choice
+//!
+//! A `Guard` says when a branch of a choice is taken: a `Condition` over a
+//! JSON context, an optional probability, or both. `choice_with` stores each
+//! guard in the metadata of the edge leading to its branch, and
+//! `GraphComposition::evaluate_choice` reads it back to resolve a choice for
+//! a given context.
+//!
+//! Conditions address values in the context with JSON pointers, such as
+//! `"/order/total"`.
+
+use crate::base_types::*;
+use crate::composition::CompositionError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// Edge metadata key holding the serialized `Condition` of a choice branch
+pub const GUARD_KEY: &str = "guard";
+
+/// Edge metadata key holding the probability of a choice branch
+pub const PROBABILITY_KEY: &str = "probability";
+
+/// Edge metadata key holding the position of a choice branch
+pub const BRANCH_KEY: &str = "branch";
+
+/// Tolerance when checking that probabilities sum to 1
+const PROBABILITY_TOLERANCE: f64 = 1e-9;
+
+/// A predicate over a JSON context
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    /// Always holds
+    Always,
+    /// Holds when no other branch of the same choice holds
+    Otherwise,
+    /// The value at the pointer equals the given value
+    Equals(String, JsonValue),
+    /// The number at the pointer is greater than the bound
+    GreaterThan(String, f64),
+    /// The number at the pointer is less than the bound
+    LessThan(String, f64),
+    /// There is a value at the pointer
+    Exists(String),
+    /// Every condition holds
+    All(Vec<Condition>),
+    /// At least one condition holds
+    Any(Vec<Condition>),
+    /// The condition does not hold
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn equals(pointer: impl Into<String>, value: impl Into<JsonValue>) -> Self {
+        Condition::Equals(pointer.into(), value.into())
+    }
+
+    pub fn greater_than(pointer: impl Into<String>, bound: f64) -> Self {
+        Condition::GreaterThan(pointer.into(), bound)
+    }
+
+    pub fn less_than(pointer: impl Into<String>, bound: f64) -> Self {
+        Condition::LessThan(pointer.into(), bound)
+    }
+
+    pub fn exists(pointer: impl Into<String>) -> Self {
+        Condition::Exists(pointer.into())
+    }
+
+    /// Whether the condition holds for `context`
+    ///
+    /// `Otherwise` depends on the other branches and never holds on its own.
+    pub fn holds(&self, context: &JsonValue) -> bool {
+        let number = |pointer: &str| context.pointer(pointer).and_then(JsonValue::as_f64);
+        match self {
+            Condition::Always => true,
+            Condition::Otherwise => false,
+            Condition::Equals(pointer, value) => context.pointer(pointer) == Some(value),
+            Condition::GreaterThan(pointer, bound) => number(pointer).is_some_and(|n| n > *bound),
+            Condition::LessThan(pointer, bound) => number(pointer).is_some_and(|n| n < *bound),
+            Condition::Exists(pointer) => context.pointer(pointer).is_some(),
+            Condition::All(conditions) => conditions.iter().all(|c| c.holds(context)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.holds(context)),
+            Condition::Not(condition) => !condition.holds(context),
+        }
+    }
+}
+
+/// When a branch of a choice is taken
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Guard {
+    pub condition: Condition,
+    /// Chance of taking the branch; within one choice, either every branch
+    /// has a probability and they sum to 1, or none has
+    pub probability: Option<f64>,
+}
+
+impl Guard {
+    /// A branch that is always enabled
+    pub fn always() -> Self {
+        Self::when(Condition::Always)
+    }
+
+    /// A branch enabled when `condition` holds
+    pub fn when(condition: Condition) -> Self {
+        Self {
+            condition,
+            probability: None,
+        }
+    }
+
+    /// A branch enabled when no other branch is
+    pub fn otherwise() -> Self {
+        Self::when(Condition::Otherwise)
+    }
+
+    /// A branch that is always enabled and taken with the given probability
+    pub fn probability(probability: f64) -> Self {
+        Self::always().with_probability(probability)
+    }
+
+    pub fn with_probability(mut self, probability: f64) -> Self {
+        self.probability = Some(probability);
+        self
+    }
+
+    /// The guard stored on a choice edge; edges without one are always enabled
+    pub fn from_relationship<R>(relationship: &Relationship<R>) -> Result<Self, CompositionError> {
+        let condition = match relationship.metadata.get(GUARD_KEY) {
+            Some(guard) => serde_json::from_value(guard.clone())
+                .map_err(|e| CompositionError::InvalidComposition(format!("invalid guard: {e}")))?,
+            None => Condition::Always,
+        };
+        let probability = match relationship.metadata.get(PROBABILITY_KEY) {
+            Some(probability) => Some(probability.as_f64().ok_or_else(|| {
+                CompositionError::InvalidProbability(format!("{probability} is not a number"))
+            })?),
+            None => None,
+        };
+        Ok(Self {
+            condition,
+            probability,
+        })
+    }
+
+    /// `relationship` with this guard stored as the branch at `position`
+    pub fn annotate<R>(&self, relationship: Relationship<R>, position: usize) -> Relationship<R> {
+        let mut relationship = relationship.with_metadata(BRANCH_KEY.to_string(), position.into());
+        if self.condition != Condition::Always {
+            let condition = serde_json::to_value(&self.condition).unwrap_or(JsonValue::Null);
+            relationship = relationship.with_metadata(GUARD_KEY.to_string(), condition);
+        }
+        if let Some(probability) = self.probability {
+            relationship =
+                relationship.with_metadata(PROBABILITY_KEY.to_string(), probability.into());
+        }
+        relationship
+    }
+}
+
+/// Check that either no guard has a probability, or all have and they sum to 1
+pub fn check_probabilities<'a, I>(guards: I) -> Result<(), CompositionError>
+where
+    I: IntoIterator<Item = &'a Guard>,
+{
+    let probabilities: Vec<Option<f64>> = guards.into_iter().map(|g| g.probability).collect();
+    if probabilities.iter().all(Option::is_none) {
+        return Ok(());
+    }
+    if probabilities.iter().any(Option::is_none) {
+        return Err(CompositionError::InvalidProbability(
+            "either every branch or no branch has a probability".to_string(),
+        ));
+    }
+
+    let probabilities: Vec<f64> = probabilities.into_iter().flatten().collect();
+    if let Some(p) = probabilities.iter().find(|p| !(0.0..=1.0).contains(*p)) {
+        return Err(CompositionError::InvalidProbability(format!(
+            "{p} is not between 0 and 1"
+        )));
+    }
+    let sum: f64 = probabilities.iter().sum();
+    if (sum - 1.0).abs() > PROBABILITY_TOLERANCE {
+        return Err(CompositionError::InvalidProbability(format!(
+            "probabilities sum to {sum}, not 1"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_conditions() {
+        let context = json!({ "order": { "total": 250.0, "country": "NL" } });
+
+        assert!(Condition::greater_than("/order/total", 100.0).holds(&context));
+        assert!(!Condition::less_than("/order/total", 100.0).holds(&context));
+        assert!(Condition::equals("/order/country", "NL").holds(&context));
+        assert!(!Condition::exists("/order/coupon").holds(&context));
+        assert!(Condition::All(vec![
+            Condition::exists("/order"),
+            Condition::Not(Box::new(Condition::equals("/order/country", "US"))),
+        ])
+        .holds(&context));
+        assert!(!Condition::Otherwise.holds(&context));
+        // Non-numbers never compare
+        assert!(!Condition::greater_than("/order/country", 0.0).holds(&context));
+    }
+
+    #[test]
+    fn test_probabilities_and_edge_round_trip() {
+        let fair = [Guard::probability(0.5), Guard::probability(0.5)];
+        assert!(check_probabilities(&fair).is_ok());
+        assert!(check_probabilities(&[Guard::always(), Guard::otherwise()]).is_ok());
+        assert!(matches!(
+            check_probabilities(&[Guard::probability(0.5), Guard::probability(0.4)]),
+            Err(CompositionError::InvalidProbability(_))
+        ));
+        assert!(check_probabilities(&[Guard::probability(1.0), Guard::always()]).is_err());
+        assert!(check_probabilities(&[Guard::probability(1.5), Guard::probability(-0.5)]).is_err());
+
+        let guard = Guard::when(Condition::exists("/vip")).with_probability(0.25);
+        let relationship = guard.annotate(Relationship::new(BaseRelationshipType::Choice), 2);
+        assert_eq!(relationship.metadata[BRANCH_KEY], 2);
+        assert_eq!(Guard::from_relationship(&relationship).unwrap(), guard);
+        assert_eq!(
+            Guard::from_relationship(&Relationship::new(BaseRelationshipType::Choice)).unwrap(),
+            Guard::always()
+        );
+    }
+}
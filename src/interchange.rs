@@ -0,0 +1,258 @@
+//! Versioned JSON interchange format
+//!
+//! The derived serde form of `GraphComposition` is for this crate's own use:
+//! nodes and edges are maps keyed by UUID, and nothing marks which version
+//! of the format a document follows. An `InterchangeDocument` is the form to
+//! exchange with other services. It lists nodes and edges as arrays sorted by
+//! ID, carries a `schema_version`, and is described by the JSON Schema from
+//! `interchange_schema`. Written as JSON, every object has its keys sorted,
+//! so the same graph always gives the same text.
+//!
+//! Documents of older versions are migrated on load. Version 0 is the
+//! derived serde form, without a `schema_version` field.
+
+use crate::base_types::*;
+use crate::composition::{
+    CompositionEdge, CompositionError, CompositionNode, CompositionType, GraphComposition, Ports,
+};
+use crate::invariants::Invariant;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+/// The interchange schema version written by this crate
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Key of the schema version in an interchange document
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Upgrades a document by one version; entry `i` upgrades version `i`
+type Migration = fn(JsonValue) -> Result<JsonValue, CompositionError>;
+
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [from_unversioned];
+
+/// A composition in the interchange format
+///
+/// Nodes and edges are sorted by ID when written by
+/// `GraphComposition::to_interchange`; readers should not rely on the order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(title = "GraphComposition")]
+pub struct InterchangeDocument<N = BaseNodeType, R = BaseRelationshipType> {
+    /// Version of the interchange schema the document follows
+    pub schema_version: u32,
+    #[schemars(with = "Uuid")]
+    pub id: GraphId,
+    /// ID of the node the composition is rooted at
+    pub composition_root: NodeId,
+    pub composition_type: CompositionType,
+    pub metadata: Metadata,
+    pub nodes: Vec<CompositionNode<N>>,
+    pub edges: Vec<CompositionEdge<R>>,
+    /// Declarative invariants the composition must satisfy
+    #[serde(default)]
+    pub invariants: Vec<Invariant>,
+    /// Named entry and exit nodes used when composing
+    #[serde(default)]
+    pub ports: Ports,
+    /// Whether node labels must be unique
+    #[serde(default)]
+    pub unique_labels: bool,
+    /// How IDs are generated for added elements
+    #[serde(default)]
+    pub id_strategy: IdStrategy,
+}
+
+impl<N: JsonSchema, R: JsonSchema> InterchangeDocument<N, R> {
+    /// JSON Schema of documents with these node and relationship types
+    pub fn json_schema() -> JsonValue {
+        schemars::schema_for!(Self).to_value()
+    }
+}
+
+/// JSON Schema of interchange documents with the base node and relationship types
+pub fn interchange_schema() -> JsonValue {
+    <InterchangeDocument>::json_schema()
+}
+
+/// Upgrade an interchange document of any supported version to the current one
+pub fn migrate(mut document: JsonValue) -> Result<JsonValue, CompositionError> {
+    let version = match document.get(SCHEMA_VERSION_KEY) {
+        None => 0,
+        Some(version) => version.as_u64().ok_or_else(|| {
+            CompositionError::ParseError(format!("schema_version {version} is not a number"))
+        })?,
+    };
+    if version > u64::from(SCHEMA_VERSION) {
+        return Err(CompositionError::UnsupportedSchemaVersion(version));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        document = migration(document)?;
+    }
+    Ok(document)
+}
+
+/// Version 0 to 1: node and edge maps keyed by ID become arrays sorted by ID
+fn from_unversioned(mut document: JsonValue) -> Result<JsonValue, CompositionError> {
+    let object = document
+        .as_object_mut()
+        .ok_or_else(|| CompositionError::ParseError("document is not an object".to_string()))?;
+
+    for key in ["nodes", "edges"] {
+        if let Some(JsonValue::Object(elements)) = object.get_mut(key) {
+            // Map keys iterate in sorted order, so the array is sorted by ID
+            let elements: Vec<JsonValue> = std::mem::take(elements)
+                .into_iter()
+                .map(|(id, mut element)| {
+                    if let JsonValue::Object(fields) = &mut element {
+                        fields.entry("id").or_insert(JsonValue::String(id));
+                    }
+                    element
+                })
+                .collect();
+            object.insert(key.to_string(), JsonValue::Array(elements));
+        }
+    }
+
+    object.insert(SCHEMA_VERSION_KEY.to_string(), 1.into());
+    Ok(document)
+}
+
+impl<N, R> GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    /// The graph as an interchange document in JSON, with sorted keys
+    pub fn to_interchange_json(&self) -> JsonValue {
+        serde_json::to_value(self.to_interchange()).unwrap_or(JsonValue::Null)
+    }
+
+    /// The graph as pretty-printed interchange JSON
+    pub fn to_interchange_string(&self) -> String {
+        serde_json::to_string_pretty(&self.to_interchange_json()).unwrap_or_default()
+    }
+
+    /// Read an interchange document of any supported version
+    pub fn from_interchange_json(document: JsonValue) -> Result<Self, CompositionError> {
+        let document = serde_json::from_value(migrate(document)?)
+            .map_err(|e| CompositionError::ParseError(e.to_string()))?;
+        Self::from_interchange(document)
+    }
+
+    /// Read interchange JSON text of any supported version
+    pub fn from_interchange_str(text: &str) -> Result<Self, CompositionError> {
+        let document =
+            serde_json::from_str(text).map_err(|e| CompositionError::ParseError(e.to_string()))?;
+        Self::from_interchange_json(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn order() -> GraphComposition {
+        GraphComposition::aggregate("Order", "o-1")
+            .with_id_strategy(IdStrategy::derived(Uuid::nil()))
+            .add_node(
+                BaseNodeType::Value,
+                "total",
+                json!({ "amount": 250, "currency": "EUR" }),
+            )
+            .add_node(BaseNodeType::Entity, "customer", json!({ "id": "c-9" }))
+            .add_edge_by_label("root", "total", BaseRelationshipType::Contains)
+            .add_edge_by_label("root", "customer", BaseRelationshipType::References)
+            .with_invariant_rule(Invariant::required_labels(["total"]))
+            .with_output_port_by_label("out", "total")
+            .unwrap()
+    }
+
+    fn read(document: JsonValue) -> Result<GraphComposition, CompositionError> {
+        GraphComposition::from_interchange_json(document)
+    }
+
+    #[test]
+    fn test_interchange_round_trip_is_stable() {
+        let graph = order();
+        let document = graph.to_interchange_json();
+        assert_eq!(document["schema_version"], 1);
+        let ids: Vec<&str> = document["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| node["id"].as_str().unwrap())
+            .collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(document["invariants"][0]["name"], "required_labels");
+
+        let text = graph.to_interchange_string();
+        assert_eq!(text, order().to_interchange_string());
+        let restored: GraphComposition = GraphComposition::from_interchange_str(&text).unwrap();
+        assert_eq!(restored.to_interchange_string(), text);
+        assert_eq!(restored.invariants(), graph.invariants());
+        assert_eq!(restored.ports(), graph.ports());
+        assert_eq!(restored.id_strategy(), graph.id_strategy());
+    }
+
+    #[test]
+    fn test_migration_and_rejected_documents() {
+        let graph = order();
+
+        // The derived serde form is version 0
+        let legacy = serde_json::to_value(&graph).unwrap();
+        assert!(legacy["nodes"].is_object());
+        let migrated = read(legacy).unwrap();
+        assert_eq!(
+            migrated.to_interchange_string(),
+            graph.to_interchange_string()
+        );
+
+        let mut future = graph.to_interchange_json();
+        future["schema_version"] = 7.into();
+        assert_eq!(
+            read(future).unwrap_err(),
+            CompositionError::UnsupportedSchemaVersion(7)
+        );
+
+        let mut dangling = graph.to_interchange_json();
+        dangling["edges"][0]["target"] = json!(Uuid::nil());
+        assert!(matches!(
+            read(dangling),
+            Err(CompositionError::DanglingEdge(..))
+        ));
+
+        let mut duplicated = graph.to_interchange_json();
+        let first = duplicated["nodes"][0].clone();
+        duplicated["nodes"].as_array_mut().unwrap().push(first);
+        assert!(matches!(
+            read(duplicated),
+            Err(CompositionError::DuplicateNodeId(_))
+        ));
+
+        assert!(matches!(
+            read(json!({ "schema_version": 1 })),
+            Err(CompositionError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_checked_in_schema_is_current() {
+        let schema = interchange_schema();
+        assert_eq!(schema["title"], "GraphComposition");
+        assert_eq!(schema["properties"]["nodes"]["type"], "array");
+        assert!(schema["required"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("schema_version")));
+
+        let checked_in: JsonValue =
+            serde_json::from_str(include_str!("../schema/graph-composition.schema.json")).unwrap();
+        assert_eq!(
+            checked_in, schema,
+            "regenerate with `cargo run --example interchange_schema > schema/graph-composition.schema.json`"
+        );
+    }
+}
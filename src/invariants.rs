@@ -0,0 +1,342 @@
+//! Declarative invariants that travel with a composition
+//!
+//! Unlike closures added with `with_invariant`, an `Invariant` is plain data:
+//! it survives `clone`, serialization and node mapping, and reports every
+//! violation together with the nodes and edges responsible.
+
+use crate::base_types::*;
+use crate::composition::{CompositionError, GraphComposition};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::fmt;
+
+/// The condition an invariant checks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum InvariantRule {
+    /// The graph has no cycle
+    Acyclic,
+    /// No node has more outgoing edges than this
+    MaxOutDegree(usize),
+    /// Each label (or qualified label path) names a node in the graph
+    RequiredLabels(Vec<String>),
+    /// Every node type is one of these, compared in serialized form
+    AllowedNodeTypes(Vec<JsonValue>),
+    /// Every node can be reached from the composition root
+    RootReachesAll,
+}
+
+/// A named, described rule a graph must satisfy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Invariant {
+    pub name: String,
+    pub description: String,
+    pub rule: InvariantRule,
+}
+
+impl Invariant {
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        rule: InvariantRule,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            rule,
+        }
+    }
+
+    /// The graph must not contain a cycle
+    pub fn acyclic() -> Self {
+        Self::new("acyclic", "The graph has no cycle", InvariantRule::Acyclic)
+    }
+
+    /// No node may have more than `max` outgoing edges
+    pub fn max_out_degree(max: usize) -> Self {
+        Self::new(
+            "max_out_degree",
+            format!("No node has more than {max} outgoing edges"),
+            InvariantRule::MaxOutDegree(max),
+        )
+    }
+
+    /// The graph must contain nodes with these labels
+    pub fn required_labels<I, S>(labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let labels: Vec<String> = labels.into_iter().map(Into::into).collect();
+        Self::new(
+            "required_labels",
+            format!("The graph has nodes labelled {}", labels.join(", ")),
+            InvariantRule::RequiredLabels(labels),
+        )
+    }
+
+    /// Every node must have one of these node types
+    pub fn node_types<N: Serialize>(allowed: &[N]) -> Self {
+        let allowed: Vec<JsonValue> = allowed
+            .iter()
+            .filter_map(|node_type| serde_json::to_value(node_type).ok())
+            .collect();
+        Self::new(
+            "node_types",
+            "Every node has an allowed node type",
+            InvariantRule::AllowedNodeTypes(allowed),
+        )
+    }
+
+    /// Every node must be reachable from the composition root
+    pub fn root_reaches_all() -> Self {
+        Self::new(
+            "root_reachability",
+            "Every node is reachable from the composition root",
+            InvariantRule::RootReachesAll,
+        )
+    }
+
+    /// Rename the invariant, for instance to hold two rules of one kind
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Replace the description
+    pub fn described(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Every way `graph` breaks this invariant
+    pub fn check<N, R>(&self, graph: &GraphComposition<N, R>) -> Vec<Violation>
+    where
+        N: Clone + Serialize + for<'de> Deserialize<'de>,
+        R: Clone + Serialize + for<'de> Deserialize<'de>,
+    {
+        let violation = |message: String, nodes: Vec<NodeId>, edges: Vec<EdgeId>| Violation {
+            invariant: self.name.clone(),
+            message,
+            nodes,
+            edges,
+        };
+
+        match &self.rule {
+            InvariantRule::Acyclic => graph
+                .find_cycles()
+                .into_iter()
+                .map(|cycle| {
+                    let edges = cycle_edges(graph, &cycle);
+                    violation(format!("cycle through {} nodes", cycle.len()), cycle, edges)
+                })
+                .collect(),
+            InvariantRule::MaxOutDegree(max) => {
+                let mut violations: Vec<Violation> = graph
+                    .nodes
+                    .keys()
+                    .filter(|node_id| graph.out_degree(**node_id) > *max)
+                    .map(|node_id| {
+                        let mut edges: Vec<EdgeId> =
+                            graph.out_edges(*node_id).iter().map(|e| e.id).collect();
+                        edges.sort();
+                        violation(
+                            format!("{} outgoing edges, at most {max} allowed", edges.len()),
+                            vec![*node_id],
+                            edges,
+                        )
+                    })
+                    .collect();
+                violations.sort_by(|a, b| a.nodes.cmp(&b.nodes));
+                violations
+            }
+            InvariantRule::RequiredLabels(labels) => labels
+                .iter()
+                .filter(|label| {
+                    matches!(
+                        graph.node_by_label(label),
+                        Err(CompositionError::LabelNotFound(_))
+                    )
+                })
+                .map(|label| violation(format!("no node labelled {label}"), Vec::new(), Vec::new()))
+                .collect(),
+            InvariantRule::AllowedNodeTypes(allowed) => {
+                let mut nodes: Vec<NodeId> = graph
+                    .nodes
+                    .values()
+                    .filter(|node| {
+                        serde_json::to_value(&node.node_type)
+                            .map_or(true, |node_type| !allowed.contains(&node_type))
+                    })
+                    .map(|node| node.id)
+                    .collect();
+                nodes.sort();
+                if nodes.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![violation(
+                        format!("{} nodes have a disallowed type", nodes.len()),
+                        nodes,
+                        Vec::new(),
+                    )]
+                }
+            }
+            InvariantRule::RootReachesAll => {
+                let reachable = graph.reachable_from(graph.composition_root);
+                let mut nodes: Vec<NodeId> = graph
+                    .nodes
+                    .keys()
+                    .filter(|node_id| {
+                        **node_id != graph.composition_root && !reachable.contains(node_id)
+                    })
+                    .copied()
+                    .collect();
+                nodes.sort();
+                if nodes.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![violation(
+                        format!("{} nodes are unreachable from the root", nodes.len()),
+                        nodes,
+                        Vec::new(),
+                    )]
+                }
+            }
+        }
+    }
+}
+
+/// Edges joining consecutive nodes of a cycle, including the closing edge
+fn cycle_edges<N, R>(graph: &GraphComposition<N, R>, cycle: &[NodeId]) -> Vec<EdgeId>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    cycle
+        .iter()
+        .zip(cycle.iter().cycle().skip(1))
+        .filter_map(|(source, target)| {
+            graph
+                .out_edges(*source)
+                .into_iter()
+                .filter(|edge| edge.target == *target && !edge.relationship.is_back_edge())
+                .map(|edge| edge.id)
+                .min()
+        })
+        .collect()
+}
+
+/// One way in which a graph breaks an invariant
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Violation {
+    /// Name of the broken invariant
+    pub invariant: String,
+    pub message: String,
+    /// Nodes responsible for the violation
+    pub nodes: Vec<NodeId>,
+    /// Edges responsible for the violation
+    pub edges: Vec<EdgeId>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.invariant, self.message)
+    }
+}
+
+impl From<Violation> for CompositionError {
+    fn from(violation: Violation) -> Self {
+        CompositionError::InvariantViolation(violation.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pipeline() -> GraphComposition {
+        GraphComposition::composite("Pipeline")
+            .add_node(BaseNodeType::Command, "ingest", json!({}))
+            .add_node(BaseNodeType::Command, "store", json!({}))
+            .add_edge_by_label("root", "ingest", BaseRelationshipType::Sequence)
+            .add_edge_by_label("ingest", "store", BaseRelationshipType::Sequence)
+            .with_invariant_rule(Invariant::acyclic())
+            .with_invariant_rule(Invariant::max_out_degree(1))
+            .with_invariant_rule(Invariant::required_labels(["ingest", "store"]))
+            .with_invariant_rule(Invariant::node_types(&[
+                BaseNodeType::Aggregate,
+                BaseNodeType::Command,
+            ]))
+            .with_invariant_rule(Invariant::root_reaches_all())
+    }
+
+    #[test]
+    fn test_builtin_invariants_hold() {
+        let graph = pipeline();
+        assert_eq!(graph.invariants().len(), 5);
+        assert!(graph.check_invariants().is_ok());
+        assert!(graph.check_all_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_check_all_invariants_reports_every_violation() {
+        let graph = pipeline()
+            .add_node(BaseNodeType::Event, "stray", json!({}))
+            .add_edge_by_label("store", "ingest", BaseRelationshipType::Sequence)
+            .add_edge_by_label("ingest", "root", BaseRelationshipType::Sequence);
+        let id = |label: &str| graph.node_by_label(label).unwrap().id;
+
+        let violations = graph.check_all_invariants().unwrap_err();
+        let named = |name: &str| -> Vec<&Violation> {
+            violations.iter().filter(|v| v.invariant == name).collect()
+        };
+
+        let cycles = named("acyclic");
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].nodes.len(), 2);
+        assert_eq!(cycles[0].edges.len(), 2);
+        assert!(cycles[0].nodes.contains(&id("ingest")));
+
+        let fan_out = named("max_out_degree");
+        assert_eq!(fan_out.len(), 1);
+        assert_eq!(fan_out[0].nodes, vec![id("ingest")]);
+        assert_eq!(fan_out[0].edges.len(), 2);
+
+        assert_eq!(named("node_types")[0].nodes, vec![id("stray")]);
+        assert_eq!(named("root_reachability")[0].nodes, vec![id("stray")]);
+        assert!(named("required_labels").is_empty());
+
+        assert_eq!(
+            graph.check_invariants().unwrap_err(),
+            CompositionError::CycleDetected
+        );
+    }
+
+    #[test]
+    fn test_invariants_survive_clone_serde_and_mapping() {
+        let graph = pipeline().with_invariant_rule(
+            Invariant::required_labels(["audit"])
+                .named("auditing")
+                .described("Pipelines must be audited"),
+        );
+
+        let json = serde_json::to_string(&graph).unwrap();
+        let restored: GraphComposition = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.invariants(), graph.invariants());
+        assert_eq!(graph.clone().invariants(), graph.invariants());
+
+        let mapped = graph.map_nodes(|node| node.clone());
+        let violations = mapped.check_all_invariants().unwrap_err();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].invariant, "auditing");
+        assert_eq!(
+            violations[0].to_string(),
+            "auditing: no node labelled audit"
+        );
+
+        let mut relaxed = mapped;
+        assert!(relaxed.remove_invariant("auditing").is_some());
+        assert!(relaxed.check_all_invariants().is_ok());
+    }
+}
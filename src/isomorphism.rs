@@ -0,0 +1,606 @@
+//! Structural comparison of compositions, independent of IDs
+//!
+//! Two graphs built by identical code get different random `NodeId`s and
+//! `EdgeId`s, so `PartialEq` never considers them equal. The functions here
+//! compare graphs by shape instead: node types, labels, data and relationship
+//! types, with configurable strictness.
+//!
+//! `structural_hash` is an isomorphism invariant computed by color refinement
+//! (Weisfeiler-Lehman): isomorphic graphs always hash the same, but equal hashes
+//! do not prove isomorphism. `is_isomorphic_to` performs the exact check.
+
+use crate::base_types::*;
+use crate::composition::{CompositionNode, GraphComposition};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+
+/// Which attributes must agree for two nodes or edges to correspond
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IsomorphismOptions {
+    pub node_types: bool,
+    pub labels: bool,
+    pub data: bool,
+    pub node_metadata: bool,
+    /// Relationship type and bidirectionality
+    pub relationship_types: bool,
+    pub edge_metadata: bool,
+    /// The composition roots must correspond to each other
+    pub root: bool,
+}
+
+impl Default for IsomorphismOptions {
+    /// Everything except metadata, which mostly carries bookkeeping
+    fn default() -> Self {
+        Self {
+            node_types: true,
+            labels: true,
+            data: true,
+            node_metadata: false,
+            relationship_types: true,
+            edge_metadata: false,
+            root: true,
+        }
+    }
+}
+
+impl IsomorphismOptions {
+    /// Compare every attribute, including metadata
+    pub fn strict() -> Self {
+        Self {
+            node_metadata: true,
+            edge_metadata: true,
+            ..Self::default()
+        }
+    }
+
+    /// Compare only the shape of the graph
+    pub fn shape_only() -> Self {
+        Self {
+            node_types: false,
+            labels: false,
+            data: false,
+            node_metadata: false,
+            relationship_types: false,
+            edge_metadata: false,
+            root: false,
+        }
+    }
+}
+
+impl<N, R> GraphComposition<N, R>
+where
+    N: Serialize,
+    R: Serialize,
+{
+    /// Whether the two graphs have the same structure under `options`
+    pub fn is_isomorphic_to(&self, other: &Self, options: IsomorphismOptions) -> bool {
+        let a = Fingerprint::new(self, options);
+        let b = Fingerprint::new(other, options);
+
+        if a.attrs.len() != b.attrs.len()
+            || a.edge_count != b.edge_count
+            || a.root.is_some() != b.root.is_some()
+        {
+            return false;
+        }
+
+        let (colors_a, rounds_a) = a.refine();
+        let (colors_b, rounds_b) = b.refine();
+        if rounds_a != rounds_b || histogram(&colors_a) != histogram(&colors_b) {
+            return false;
+        }
+
+        let mut search = Search {
+            a: &a,
+            b: &b,
+            colors_a: &colors_a,
+            colors_b: &colors_b,
+            forward: vec![None; a.attrs.len()],
+            backward: vec![None; b.attrs.len()],
+        };
+
+        if let (Some(root_a), Some(root_b)) = (a.root, b.root) {
+            if !search.compatible(root_a, root_b) {
+                return false;
+            }
+            search.assign(root_a, root_b);
+        }
+
+        let order = a.search_order(&colors_a);
+        search.extend(&order, 0)
+    }
+
+    /// Hash of the graph's structure under `options`
+    ///
+    /// Uses a fixed hash function rather than a randomly seeded one, so the
+    /// value can key caches that outlive the process. Isomorphic graphs hash
+    /// equally; confirm a match with `is_isomorphic_to`.
+    pub fn structural_hash(&self, options: IsomorphismOptions) -> u64 {
+        let fingerprint = Fingerprint::new(self, options);
+        let (colors, rounds) = fingerprint.refine();
+
+        let mut sorted = colors.clone();
+        sorted.sort_unstable();
+
+        let mut hasher = Fnv::default();
+        (rounds as u64).hash(&mut hasher);
+        (fingerprint.edge_count as u64).hash(&mut hasher);
+        sorted.hash(&mut hasher);
+        fingerprint.root.map(|r| colors[r]).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// A composition compared and hashed by structure
+///
+/// Isomorphic compositions land on the same `HashMap` or `HashSet` entry,
+/// which makes deduplication and caching by shape straightforward.
+#[derive(Debug, Clone)]
+pub struct StructuralKey<N = BaseNodeType, R = BaseRelationshipType> {
+    graph: GraphComposition<N, R>,
+    options: IsomorphismOptions,
+    hash: u64,
+}
+
+impl<N, R> StructuralKey<N, R>
+where
+    N: Serialize,
+    R: Serialize,
+{
+    pub fn new(graph: GraphComposition<N, R>, options: IsomorphismOptions) -> Self {
+        let hash = graph.structural_hash(options);
+        Self {
+            graph,
+            options,
+            hash,
+        }
+    }
+
+    pub fn graph(&self) -> &GraphComposition<N, R> {
+        &self.graph
+    }
+
+    pub fn into_inner(self) -> GraphComposition<N, R> {
+        self.graph
+    }
+
+    pub fn structural_hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl<N, R> PartialEq for StructuralKey<N, R>
+where
+    N: Serialize,
+    R: Serialize,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.options == other.options
+            && self.hash == other.hash
+            && self.graph.is_isomorphic_to(&other.graph, self.options)
+    }
+}
+
+impl<N, R> Eq for StructuralKey<N, R>
+where
+    N: Serialize,
+    R: Serialize,
+{
+}
+
+impl<N, R> Hash for StructuralKey<N, R> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+/// 64-bit FNV-1a, used instead of `DefaultHasher` so hashes are reproducible
+#[derive(Debug)]
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+fn fnv<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = Fnv::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn histogram(colors: &[u64]) -> BTreeMap<u64, usize> {
+    let mut counts = BTreeMap::new();
+    for color in colors {
+        *counts.entry(*color).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Serialize with sorted keys so equal values always produce equal strings
+fn canonical_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .map(|v| v.to_string())
+        .unwrap_or_default()
+}
+
+/// The attributes of a graph that take part in a comparison, by node index
+struct Fingerprint {
+    /// Attribute key of each node
+    attrs: Vec<String>,
+    root: Option<usize>,
+    /// Sorted edge keys for every ordered pair of connected nodes
+    pairs: HashMap<(usize, usize), Vec<String>>,
+    outgoing: Vec<Vec<(usize, String)>>,
+    incoming: Vec<Vec<(usize, String)>>,
+    /// Neighbors in either direction
+    neighbors: Vec<Vec<usize>>,
+    edge_count: usize,
+}
+
+impl Fingerprint {
+    fn new<N: Serialize, R: Serialize>(
+        graph: &GraphComposition<N, R>,
+        options: IsomorphismOptions,
+    ) -> Self {
+        let ids: Vec<NodeId> = graph.nodes.keys().copied().collect();
+        let index: HashMap<NodeId, usize> =
+            ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+        let attrs = ids
+            .iter()
+            .map(|id| node_key(&graph.nodes[id], options))
+            .collect();
+        let root = if options.root {
+            index.get(&graph.composition_root).copied()
+        } else {
+            None
+        };
+
+        let mut fingerprint = Self {
+            attrs,
+            root,
+            pairs: HashMap::new(),
+            outgoing: vec![Vec::new(); ids.len()],
+            incoming: vec![Vec::new(); ids.len()],
+            neighbors: vec![Vec::new(); ids.len()],
+            edge_count: 0,
+        };
+
+        for edge in graph.edges.values() {
+            let (Some(&source), Some(&target)) = (index.get(&edge.source), index.get(&edge.target))
+            else {
+                continue;
+            };
+
+            let mut key = String::new();
+            if options.relationship_types {
+                key.push_str(&canonical_json(&edge.relationship.relationship_type));
+                key.push_str(if edge.relationship.bidirectional {
+                    "<>"
+                } else {
+                    ">"
+                });
+            }
+            if options.edge_metadata {
+                key.push_str(&canonical_json(&edge.relationship.metadata));
+            }
+
+            fingerprint
+                .pairs
+                .entry((source, target))
+                .or_default()
+                .push(key.clone());
+            fingerprint.outgoing[source].push((target, key.clone()));
+            fingerprint.incoming[target].push((source, key));
+            fingerprint.neighbors[source].push(target);
+            fingerprint.neighbors[target].push(source);
+            fingerprint.edge_count += 1;
+        }
+
+        for keys in fingerprint.pairs.values_mut() {
+            keys.sort();
+        }
+        for neighbors in &mut fingerprint.neighbors {
+            neighbors.sort_unstable();
+            neighbors.dedup();
+        }
+
+        fingerprint
+    }
+
+    /// Color refinement until the partition stops splitting
+    ///
+    /// Returns the final colors and the number of rounds it took, which is
+    /// itself an isomorphism invariant.
+    fn refine(&self) -> (Vec<u64>, usize) {
+        let mut colors: Vec<u64> = self
+            .attrs
+            .iter()
+            .enumerate()
+            .map(|(i, attrs)| fnv(&(attrs, self.root == Some(i))))
+            .collect();
+        let mut classes = colors.iter().collect::<HashSet<_>>().len();
+
+        for round in 0..self.attrs.len() {
+            let next: Vec<u64> = (0..self.attrs.len())
+                .map(|i| {
+                    let mut out: Vec<(u64, &str)> = self.outgoing[i]
+                        .iter()
+                        .map(|(t, key)| (colors[*t], key.as_str()))
+                        .collect();
+                    let mut inc: Vec<(u64, &str)> = self.incoming[i]
+                        .iter()
+                        .map(|(s, key)| (colors[*s], key.as_str()))
+                        .collect();
+                    out.sort_unstable();
+                    inc.sort_unstable();
+                    fnv(&(colors[i], out, inc))
+                })
+                .collect();
+
+            let next_classes = next.iter().collect::<HashSet<_>>().len();
+            colors = next;
+            if next_classes == classes {
+                return (colors, round + 1);
+            }
+            classes = next_classes;
+        }
+
+        (colors, self.attrs.len())
+    }
+
+    /// Nodes ordered so each is connected to earlier ones where possible,
+    /// starting from the root or the rarest color
+    fn search_order(&self, colors: &[u64]) -> Vec<usize> {
+        let counts = histogram(colors);
+        let mut by_rarity: Vec<usize> = (0..self.attrs.len()).collect();
+        by_rarity.sort_by_key(|i| counts[&colors[*i]]);
+        if let Some(root) = self.root {
+            by_rarity.retain(|i| *i != root);
+            by_rarity.insert(0, root);
+        }
+
+        let mut order = Vec::with_capacity(self.attrs.len());
+        let mut seen = vec![false; self.attrs.len()];
+        for start in by_rarity {
+            if seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut queue = VecDeque::from([start]);
+            while let Some(current) = queue.pop_front() {
+                order.push(current);
+                for next in &self.neighbors[current] {
+                    if !seen[*next] {
+                        seen[*next] = true;
+                        queue.push_back(*next);
+                    }
+                }
+            }
+        }
+        order
+    }
+
+    fn edges_between(&self, source: usize, target: usize) -> &[String] {
+        self.pairs.get(&(source, target)).map_or(&[], Vec::as_slice)
+    }
+}
+
+fn node_key<N: Serialize>(node: &CompositionNode<N>, options: IsomorphismOptions) -> String {
+    let mut parts = Vec::new();
+    if options.node_types {
+        parts.push(canonical_json(&node.node_type));
+    }
+    if options.labels {
+        parts.push(canonical_json(&node.label));
+    }
+    if options.data {
+        parts.push(node.data.to_string());
+    }
+    if options.node_metadata {
+        parts.push(canonical_json(&node.metadata));
+    }
+    parts.join("|")
+}
+
+/// Backtracking search for a node bijection that preserves all edges
+struct Search<'a> {
+    a: &'a Fingerprint,
+    b: &'a Fingerprint,
+    colors_a: &'a [u64],
+    colors_b: &'a [u64],
+    forward: Vec<Option<usize>>,
+    backward: Vec<Option<usize>>,
+}
+
+impl Search<'_> {
+    fn extend(&mut self, order: &[usize], depth: usize) -> bool {
+        let Some(&a) = order.get(depth) else {
+            return true;
+        };
+        if self.forward[a].is_some() {
+            return self.extend(order, depth + 1);
+        }
+
+        for b in 0..self.b.attrs.len() {
+            if self.backward[b].is_none() && self.compatible(a, b) {
+                self.assign(a, b);
+                if self.extend(order, depth + 1) {
+                    return true;
+                }
+                self.forward[a] = None;
+                self.backward[b] = None;
+            }
+        }
+        false
+    }
+
+    fn assign(&mut self, a: usize, b: usize) {
+        self.forward[a] = Some(b);
+        self.backward[b] = Some(a);
+    }
+
+    /// Whether mapping `a` to `b` agrees with every pair mapped so far
+    fn compatible(&self, a: usize, b: usize) -> bool {
+        if self.colors_a[a] != self.colors_b[b] || self.a.attrs[a] != self.b.attrs[b] {
+            return false;
+        }
+        if self.a.edges_between(a, a) != self.b.edges_between(b, b) {
+            return false;
+        }
+
+        let agrees = |a2: usize, b2: usize| {
+            self.a.edges_between(a, a2) == self.b.edges_between(b, b2)
+                && self.a.edges_between(a2, a) == self.b.edges_between(b2, b)
+        };
+
+        let mapped_from_a = self.a.neighbors[a]
+            .iter()
+            .filter_map(|a2| self.forward[*a2].map(|b2| (*a2, b2)));
+        let mapped_from_b = self.b.neighbors[b]
+            .iter()
+            .filter_map(|b2| self.backward[*b2].map(|a2| (a2, *b2)));
+
+        mapped_from_a
+            .chain(mapped_from_b)
+            .all(|(a2, b2)| agrees(a2, b2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn address() -> GraphComposition {
+        GraphComposition::composite("Address")
+            .add_node(BaseNodeType::Value, "street", "123 Main St")
+            .add_node(BaseNodeType::Value, "city", "Springfield")
+            .add_edge_by_label("root", "street", BaseRelationshipType::Contains)
+            .add_edge_by_label("root", "city", BaseRelationshipType::Contains)
+    }
+
+    #[test]
+    fn test_identically_built_graphs_are_isomorphic() {
+        let a = address();
+        let b = address();
+
+        assert_ne!(a, b);
+        assert!(a.is_isomorphic_to(&b, IsomorphismOptions::default()));
+        assert_eq!(
+            a.structural_hash(IsomorphismOptions::default()),
+            b.structural_hash(IsomorphismOptions::default())
+        );
+    }
+
+    #[test]
+    fn test_strictness_controls_what_must_match() {
+        let a = address();
+        let b = GraphComposition::composite("Address")
+            .add_node(BaseNodeType::Value, "street", "742 Evergreen Terrace")
+            .add_node(BaseNodeType::Value, "city", "Springfield")
+            .add_edge_by_label("root", "street", BaseRelationshipType::Contains)
+            .add_edge_by_label("root", "city", BaseRelationshipType::Contains);
+
+        assert!(!a.is_isomorphic_to(&b, IsomorphismOptions::default()));
+        assert!(a.is_isomorphic_to(
+            &b,
+            IsomorphismOptions {
+                data: false,
+                ..IsomorphismOptions::default()
+            }
+        ));
+
+        let c = GraphComposition::composite("Other")
+            .add_node(BaseNodeType::Event, "x", json!(1))
+            .add_node(BaseNodeType::Event, "y", json!(2))
+            .add_edge_by_label("root", "x", BaseRelationshipType::Sequence)
+            .add_edge_by_label("root", "y", BaseRelationshipType::Sequence);
+        assert!(!a.is_isomorphic_to(&c, IsomorphismOptions::default()));
+        assert!(a.is_isomorphic_to(&c, IsomorphismOptions::shape_only()));
+    }
+
+    #[test]
+    fn test_edge_direction_and_multiplicity_matter() {
+        let forward = GraphComposition::composite("Chain")
+            .add_node(BaseNodeType::Value, "a", json!(null))
+            .add_edge_by_label("root", "a", BaseRelationshipType::Sequence);
+        let backward = GraphComposition::composite("Chain")
+            .add_node(BaseNodeType::Value, "a", json!(null))
+            .add_edge_by_label("a", "root", BaseRelationshipType::Sequence);
+        let doubled =
+            forward
+                .clone()
+                .add_edge_by_label("root", "a", BaseRelationshipType::Sequence);
+
+        let options = IsomorphismOptions::shape_only();
+        let rooted = IsomorphismOptions {
+            root: true,
+            ..options
+        };
+        assert!(forward.is_isomorphic_to(&backward, options));
+        assert!(!forward.is_isomorphic_to(&backward, rooted));
+        assert!(!forward.is_isomorphic_to(&doubled, options));
+    }
+
+    #[test]
+    fn test_symmetric_graphs_with_ambiguous_colors() {
+        // Two directed 3-cycles vs one 6-cycle: same degrees everywhere
+        let cycle = |pairs: &[(usize, usize)]| {
+            let mut graph = GraphComposition::<BaseNodeType, BaseRelationshipType>::new(
+                BaseNodeType::Value,
+                crate::CompositionType::Composite {
+                    structure_type: "Cycle".to_string(),
+                },
+            );
+            let mut ids = vec![graph.composition_root];
+            for _ in 1..6 {
+                let id = NodeId::new();
+                graph = graph.add_node_with_id(id, BaseNodeType::Value, "root", json!({}));
+                ids.push(id);
+            }
+            for (s, t) in pairs {
+                graph = graph.add_edge(ids[*s], ids[*t], BaseRelationshipType::Sequence);
+            }
+            graph
+        };
+
+        let two_triangles = cycle(&[(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)]);
+        let other_triangles = cycle(&[(0, 2), (2, 4), (4, 0), (1, 3), (3, 5), (5, 1)]);
+        let hexagon = cycle(&[(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+
+        let options = IsomorphismOptions::default();
+        assert!(two_triangles.is_isomorphic_to(&other_triangles, options));
+        assert!(!two_triangles.is_isomorphic_to(&hexagon, options));
+    }
+
+    #[test]
+    fn test_structural_key_deduplicates() {
+        let mut seen = HashSet::new();
+        let options = IsomorphismOptions::default();
+
+        assert!(seen.insert(StructuralKey::new(address(), options)));
+        assert!(!seen.insert(StructuralKey::new(address(), options)));
+        assert!(seen.insert(StructuralKey::new(
+            address().add_node(BaseNodeType::Value, "zip", "12345"),
+            options
+        )));
+        assert_eq!(seen.len(), 2);
+    }
+}
@@ -16,15 +16,45 @@
 //! - **CompositionNode**: Nodes within a graph (can reference domain entities)
 //! - **CompositionEdge**: Relationships between nodes
 //! - **Category Theory Operations**: Morphisms, Functors, and Monads for graph transformation
+//! - **DOT Export**: GraphViz rendering styled by node and relationship type
+//! - **GraphML and GEXF**: Lossless export and import for graph analysis tools
+//! - **Guards**: Conditions and probabilities that decide which branch of a choice is taken
+//! - **Interchange**: Versioned JSON format with migrations and a JSON Schema
+//! - **Invariants**: Named, serializable rules a graph must satisfy, with violation reports
+//! - **Mermaid**: Flowchart export and import
+//! - **RDF**: Turtle and JSON-LD export and N-Triples import for linked data
+//! - **Loops**: Repetition, while and for-each loops whose back-edges are not treated as cycles
+//! - **Isomorphism**: Structural equality and hashing independent of node and edge IDs
+//! - **Traversal**: Breadth- and depth-first walks, reachability and weighted paths
 //! - **Domain Compositions**: Feature-gated traits for composing specific domain aggregates
 
 pub mod base_types;
 pub mod composition;
+pub mod dot;
+pub mod gexf;
+pub mod graphml;
+pub mod guards;
+pub mod interchange;
+pub mod invariants;
+pub mod isomorphism;
+pub mod loops;
 pub mod mapping;
+pub mod mermaid;
+pub mod rdf;
+pub mod traversal;
+mod xml;
 pub mod domain_compositions;
 
 // Re-export main types
 pub use base_types::*;
 pub use composition::*;
+pub use dot::*;
+pub use guards::*;
+pub use interchange::*;
+pub use invariants::*;
+pub use isomorphism::*;
+pub use loops::*;
 pub use mapping::*;
+pub use rdf::*;
+pub use traversal::*;
 pub use domain_compositions::{Composable, Decomposable};
@@ -0,0 +1,84 @@
+//! Loops built by `repeat`, `while_guard` and `for_each`
+//!
+//! A loop is a header node that enters the body, with a back-edge from each
+//! of the body's exits to the header. The header records a `LoopKind` under
+//! `LOOP_KEY` in its metadata and is also the loop's exit. Back-edges are
+//! marked with `Relationship::back_edge`, so cycle detection, topological
+//! ordering and the acyclicity invariant treat the loop as intentional.
+
+use crate::guards::Guard;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// Node metadata key holding the `LoopKind` of a loop header
+pub const LOOP_KEY: &str = "loop";
+
+/// How often a loop runs its body
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LoopKind {
+    /// A fixed number of times
+    Repeat { times: usize },
+    /// As long as the guard's condition holds for the context
+    While { guard: Guard },
+    /// Once per element of the array at a JSON pointer in the context
+    ForEach { collection: String },
+}
+
+impl LoopKind {
+    /// How many times the body runs for `context`, if known up front
+    ///
+    /// A `ForEach` over a missing or non-array value runs zero times; a
+    /// `While` loop depends on how the context changes and returns `None`.
+    pub fn iterations(&self, context: &JsonValue) -> Option<usize> {
+        match self {
+            LoopKind::Repeat { times } => Some(*times),
+            LoopKind::While { .. } => None,
+            LoopKind::ForEach { collection } => Some(
+                context
+                    .pointer(collection)
+                    .and_then(JsonValue::as_array)
+                    .map_or(0, Vec::len),
+            ),
+        }
+    }
+
+    /// Whether the body runs again after `completed` iterations
+    pub fn continues(&self, context: &JsonValue, completed: usize) -> bool {
+        match self {
+            LoopKind::While { guard } => guard.condition.holds(context),
+            _ => self
+                .iterations(context)
+                .is_some_and(|iterations| completed < iterations),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guards::Condition;
+    use serde_json::json;
+
+    #[test]
+    fn test_loop_kinds() {
+        let context = json!({ "order": { "lines": [1, 2, 3] }, "pending": true });
+
+        let retry = LoopKind::Repeat { times: 3 };
+        assert_eq!(retry.iterations(&context), Some(3));
+        assert!(retry.continues(&context, 2));
+        assert!(!retry.continues(&context, 3));
+
+        let lines = LoopKind::ForEach {
+            collection: "/order/lines".to_string(),
+        };
+        assert_eq!(lines.iterations(&context), Some(3));
+        assert_eq!(lines.iterations(&json!({})), Some(0));
+
+        let poll = LoopKind::While {
+            guard: Guard::when(Condition::equals("/pending", true)),
+        };
+        assert_eq!(poll.iterations(&context), None);
+        assert!(poll.continues(&context, 100));
+        assert!(!poll.continues(&json!({ "pending": false }), 0));
+    }
+}
@@ -65,6 +65,7 @@ impl DomainRelationshipMapping {
             "parallel" => BaseRelationshipType::Parallel,
             "choice" => BaseRelationshipType::Choice,
             "hierarchy" => BaseRelationshipType::Hierarchy,
+            "loop" => BaseRelationshipType::Loop,
             _ => BaseRelationshipType::Custom(type_str.to_string()),
         }
     }
@@ -79,11 +80,29 @@ impl DomainRelationshipMapping {
             BaseRelationshipType::Parallel => "parallel".to_string(),
             BaseRelationshipType::Choice => "choice".to_string(),
             BaseRelationshipType::Hierarchy => "hierarchy".to_string(),
+            BaseRelationshipType::Loop => "loop".to_string(),
             BaseRelationshipType::Custom(s) => s.clone(),
         }
     }
 }
 
+/// A variant name in the style of `DomainRelationshipMapping`, such as
+/// `depends_on` for `DependsOn`
+pub(crate) fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if index > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
 /// Trait for types that can be mapped to/from domain types
 pub trait DomainMappable<T> {
     type Error;
@@ -0,0 +1,695 @@
+//! Mermaid flowchart export and import
+//!
+//! `to_mermaid` writes a `graph LR` flowchart with one shape per node type.
+//! Choice forks become decision diamonds, parallel forks become fork bars,
+//! and loop back-edges are drawn as dotted `loop` links. `from_mermaid`
+//! reads such a chart, or a hand-written one, back into a `GraphComposition`.
+//!
+//! Flowcharts keep labels, node types and relationship types, but not node
+//! data or edge metadata such as guards.
+
+use crate::base_types::*;
+use crate::composition::{
+    fork_kind, CompositionEdge, CompositionError, CompositionNode, CompositionType,
+    GraphComposition, FORK_KEY, GRAPH_NAME_KEY,
+};
+use crate::dot::{display_label, type_label, variant_name};
+use crate::mapping::{snake_case, DomainNodeMapping, DomainRelationshipMapping};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Graph name of a composition read from a flowchart
+const FLOWCHART: &str = "Flowchart";
+
+impl<N, R> GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Render the composition as a Mermaid flowchart
+    ///
+    /// The composition root is declared first, so `from_mermaid` roots the
+    /// chart at the same node. Sequence edges are unlabelled; other edges
+    /// are labelled with their relationship type in `DomainRelationshipMapping`
+    /// form, such as `depends_on`.
+    pub fn to_mermaid(&self) -> String {
+        let mut nodes: Vec<&CompositionNode<N>> = self
+            .nodes
+            .values()
+            .filter(|node| node.id != self.composition_root)
+            .collect();
+        nodes.sort_by_key(|node| (display_label(node), node.id));
+        nodes.splice(0..0, self.nodes.get(&self.composition_root));
+        let names: HashMap<NodeId, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.id, index))
+            .collect();
+
+        let mut chart = String::from("graph LR\n");
+        for (index, node) in nodes.iter().enumerate() {
+            let _ = writeln!(chart, "    n{index}{}", node_shape(node));
+        }
+
+        let mut edges: Vec<&CompositionEdge<R>> = self.edges.values().collect();
+        edges.sort_by_key(|edge| (names.get(&edge.source), names.get(&edge.target), edge.id));
+        for edge in edges {
+            let (Some(source), Some(target)) = (names.get(&edge.source), names.get(&edge.target))
+            else {
+                continue;
+            };
+            let relationship = &edge.relationship;
+            let arrow = match (relationship.bidirectional, relationship.is_back_edge()) {
+                (false, false) => "-->",
+                (true, false) => "<-->",
+                (false, true) => "-.->",
+                (true, true) => "<-.->",
+            };
+            let relationship_type =
+                serde_json::to_value(&relationship.relationship_type).unwrap_or(JsonValue::Null);
+            let label = match &relationship_type {
+                JsonValue::String(name) => snake_case(name),
+                other => type_label(other),
+            };
+            // Unlabelled edges leaving a fork are read back as fork edges
+            let from_fork = self
+                .nodes
+                .get(&edge.source)
+                .is_some_and(|node| fork_kind(node).is_some());
+            if label == "sequence" && !from_fork {
+                let _ = writeln!(chart, "    n{source} {arrow} n{target}");
+            } else {
+                let _ = writeln!(chart, "    n{source} {arrow}|{}| n{target}", quote(&label));
+            }
+        }
+        chart
+    }
+}
+
+impl GraphComposition<BaseNodeType, BaseRelationshipType> {
+    /// Read a Mermaid flowchart
+    ///
+    /// The first node in the chart becomes the composition root. Node shapes
+    /// give node types as `to_mermaid` writes them, and a `:::class` overrides
+    /// the shape through `DomainNodeMapping::from_string`. Edge labels map
+    /// through `DomainRelationshipMapping::from_string`; unlabelled edges are
+    /// sequences, or choice and parallel edges when they leave a decision
+    /// diamond or fork bar. Dotted edges labelled `loop` are back-edges; other
+    /// dotted edges are read as plain edges, so real cycles are still
+    /// reported. `<-->` and `---` are bidirectional.
+    ///
+    /// Subgraphs are read as if their nodes were declared outside them, and
+    /// styling statements are ignored.
+    pub fn from_mermaid(chart: &str) -> Result<Self, CompositionError> {
+        let mut statements = chart
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with("%%"));
+
+        match statements.next() {
+            Some((_, header)) if header.starts_with("graph") || header.starts_with("flowchart") => {
+            }
+            Some((line, _)) => {
+                return Err(parse_error(
+                    line,
+                    "expected a `graph` or `flowchart` header",
+                ))
+            }
+            None => return Err(parse_error(0, "empty flowchart")),
+        }
+
+        let mut flowchart = Flowchart::default();
+        for (line, text) in statements {
+            for statement in statements_in(text) {
+                let ignored = [
+                    "classDef ",
+                    "class ",
+                    "style ",
+                    "linkStyle ",
+                    "click ",
+                    "direction ",
+                ];
+                if statement.is_empty()
+                    || statement == "end"
+                    || statement.starts_with("subgraph")
+                    || ignored.iter().any(|keyword| statement.starts_with(keyword))
+                {
+                    continue;
+                }
+                flowchart
+                    .statement(statement)
+                    .map_err(|message| parse_error(line, &message))?;
+            }
+        }
+        flowchart.into_graph()
+    }
+}
+
+/// How a flowchart node is drawn, and so which node it becomes
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    Plain,
+    Node(BaseNodeType),
+    Decision,
+    Fork,
+}
+
+/// A node declared in a flowchart
+#[derive(Debug)]
+struct Declared {
+    id: String,
+    text: Option<String>,
+    shape: Shape,
+    class: Option<String>,
+}
+
+/// An edge between two declared nodes
+#[derive(Debug)]
+struct Link {
+    source: usize,
+    target: usize,
+    label: Option<String>,
+    dotted: bool,
+    bidirectional: bool,
+}
+
+/// Nodes and edges read so far
+#[derive(Debug, Default)]
+struct Flowchart {
+    nodes: Vec<Declared>,
+    by_id: HashMap<String, usize>,
+    links: Vec<Link>,
+}
+
+impl Flowchart {
+    /// Read one statement: groups of `&`-joined nodes separated by links
+    fn statement(&mut self, statement: &str) -> Result<(), String> {
+        let mut cursor = Cursor::new(statement);
+        let mut previous = self.group(&mut cursor)?;
+        while !cursor.at_end() {
+            let (label, dotted, bidirectional) = cursor.link()?;
+            let next = self.group(&mut cursor)?;
+            for source in &previous {
+                for target in &next {
+                    self.links.push(Link {
+                        source: *source,
+                        target: *target,
+                        label: label.clone(),
+                        dotted,
+                        bidirectional,
+                    });
+                }
+            }
+            previous = next;
+        }
+        Ok(())
+    }
+
+    fn group(&mut self, cursor: &mut Cursor) -> Result<Vec<usize>, String> {
+        let mut group = vec![self.node(cursor)?];
+        while cursor.eat("&") {
+            group.push(self.node(cursor)?);
+        }
+        Ok(group)
+    }
+
+    /// Read a node reference, declaring the node on first use
+    ///
+    /// A later reference with a shape or class fills in what earlier bare
+    /// references left out.
+    fn node(&mut self, cursor: &mut Cursor) -> Result<usize, String> {
+        let id = cursor.identifier();
+        if id.is_empty() {
+            return Err(format!("expected a node id at `{}`", cursor.rest()));
+        }
+        let (shape, text) = cursor.shape()?;
+        let class = if cursor.eat(":::") {
+            Some(cursor.identifier()).filter(|class| !class.is_empty())
+        } else {
+            None
+        };
+
+        let index = match self.by_id.get(&id) {
+            Some(index) => *index,
+            None => {
+                self.nodes.push(Declared {
+                    id: id.clone(),
+                    text: None,
+                    shape: Shape::Plain,
+                    class: None,
+                });
+                self.by_id.insert(id, self.nodes.len() - 1);
+                self.nodes.len() - 1
+            }
+        };
+        let declared = &mut self.nodes[index];
+        if declared.shape == Shape::Plain {
+            declared.shape = shape;
+        }
+        declared.text = declared.text.take().or(text);
+        declared.class = declared.class.take().or(class);
+        Ok(index)
+    }
+
+    fn into_graph(self) -> Result<GraphComposition, CompositionError> {
+        if self.nodes.is_empty() {
+            return Err(CompositionError::ParseError(
+                "flowchart has no nodes".to_string(),
+            ));
+        }
+
+        let mut graph = GraphComposition::new(
+            BaseNodeType::Aggregate,
+            CompositionType::Composite {
+                structure_type: FLOWCHART.to_string(),
+            },
+        );
+        graph.metadata.name = FLOWCHART.to_string();
+        graph.nodes.clear();
+
+        let mut ids = Vec::with_capacity(self.nodes.len());
+        for declared in &self.nodes {
+            let fork = match &declared.shape {
+                Shape::Decision => Some("Choice"),
+                Shape::Fork => Some("Parallel"),
+                _ => None,
+            };
+            let node_type = match (&declared.class, &declared.shape) {
+                (Some(class), _) => DomainNodeMapping::from_string(class),
+                (None, Shape::Node(node_type)) => node_type.clone(),
+                (None, Shape::Decision | Shape::Fork) => BaseNodeType::Aggregate,
+                (None, Shape::Plain) => BaseNodeType::Command,
+            };
+            let label = declared
+                .text
+                .clone()
+                .or_else(|| fork.map(str::to_string))
+                .unwrap_or_else(|| declared.id.clone());
+
+            let mut node =
+                CompositionNode::new(node_type, label, JsonValue::Object(Default::default()));
+            if let Some(kind) = fork {
+                for key in [GRAPH_NAME_KEY, FORK_KEY] {
+                    node.metadata
+                        .insert(key.to_string(), JsonValue::String(kind.to_string()));
+                }
+            }
+            ids.push(node.id);
+            graph.nodes.insert(node.id, node);
+        }
+        graph.composition_root = ids[0];
+
+        for link in &self.links {
+            let relationship_type = match (&link.label, &self.nodes[link.source].shape) {
+                (Some(label), _) => DomainRelationshipMapping::from_string(label),
+                (None, Shape::Decision) => BaseRelationshipType::Choice,
+                (None, Shape::Fork) => BaseRelationshipType::Parallel,
+                (None, _) => BaseRelationshipType::Sequence,
+            };
+            let mut relationship = Relationship::new(relationship_type);
+            if link.bidirectional {
+                relationship = relationship.bidirectional();
+            }
+            if link.dotted && relationship.relationship_type == BaseRelationshipType::Loop {
+                relationship = relationship.back_edge();
+            }
+            let edge = CompositionEdge {
+                id: EdgeId::new(),
+                source: ids[link.source],
+                target: ids[link.target],
+                relationship,
+            };
+            graph.edges.insert(edge.id, edge);
+        }
+
+        graph.rebuild_indexes();
+        Ok(graph)
+    }
+}
+
+/// Position within one flowchart statement
+struct Cursor<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, position: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.rest().is_empty()
+    }
+
+    /// Consume `token` after optional whitespace, if it is next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume a run of characters matching `accept`
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let length = rest.find(|c: char| !accept(c)).unwrap_or(rest.len());
+        self.position += length;
+        &rest[..length]
+    }
+
+    fn identifier(&mut self) -> String {
+        self.skip_whitespace();
+        self.take_while(|c| c.is_alphanumeric() || c == '_')
+            .to_string()
+    }
+
+    /// Read the shape and text following a node id, if any
+    fn shape(&mut self) -> Result<(Shape, Option<String>), String> {
+        const SHAPES: [(&str, &str, Shape); 10] = [
+            ("([", "])", Shape::Node(BaseNodeType::Value)),
+            ("[[", "]]", Shape::Node(BaseNodeType::Aggregate)),
+            ("((", "))", Shape::Node(BaseNodeType::Event)),
+            ("{{", "}}", Shape::Node(BaseNodeType::Service)),
+            ("[(", ")]", Shape::Node(BaseNodeType::Entity)),
+            (">", "]", Shape::Node(BaseNodeType::EntityReference)),
+            ("(", ")", Shape::Node(BaseNodeType::Entity)),
+            ("[", "]", Shape::Node(BaseNodeType::Command)),
+            ("{", "}", Shape::Decision),
+            ("@{", "}", Shape::Plain),
+        ];
+
+        let rest = self.rest();
+        // Longer openers come first, so `((` is not read as `(`
+        let Some((open, close, shape)) = SHAPES.iter().find(|(open, _, _)| rest.starts_with(open))
+        else {
+            return Ok((Shape::Plain, None));
+        };
+        self.position += open.len();
+
+        // Quoted text may contain the closing bracket
+        let body = self.rest();
+        let end = if let Some(quoted) = body.strip_prefix('"') {
+            quoted.find('"').and_then(|quote| {
+                let after = &body[quote + 2..];
+                let gap = after.len() - after.trim_start().len();
+                after[gap..].starts_with(close).then_some(quote + 2 + gap)
+            })
+        } else {
+            body.find(close)
+        };
+        let end = end.ok_or_else(|| format!("missing `{close}` after `{open}{body}`"))?;
+        self.position += end + close.len();
+        let inner = body[..end].trim();
+
+        if *open == "@{" {
+            return Ok(attribute_shape(inner));
+        }
+        Ok((shape.clone(), Some(unquote(inner))))
+    }
+
+    /// Read a link and its optional label, as `(label, dotted, bidirectional)`
+    fn link(&mut self) -> Result<(Option<String>, bool, bool), String> {
+        self.skip_whitespace();
+        let is_link = |c: char| matches!(c, '<' | '>' | '-' | '.' | '=');
+        let mut arrow = self.take_while(is_link).to_string();
+        if arrow.is_empty() {
+            return Err(format!("expected a link at `{}`", self.rest()));
+        }
+
+        // `A -- label --> B` and `A -. label .-> B` put the label inside the link
+        let mut label = None;
+        if !arrow.contains('>') && matches!(arrow.as_str(), "--" | "-." | "==") {
+            let rest = self.rest();
+            let close = ["-->", "---", ".->", ".-", "==>", "==="]
+                .iter()
+                .filter_map(|close| rest.find(close))
+                .min()
+                .ok_or_else(|| format!("unterminated link label `{}`", rest.trim()))?;
+            label = Some(unquote(rest[..close].trim()));
+            self.position += close;
+            arrow.push_str(self.take_while(is_link));
+        }
+
+        if self.eat("|") {
+            let rest = self.rest();
+            let close = rest
+                .find('|')
+                .ok_or_else(|| format!("missing `|` after `|{rest}`"))?;
+            label = Some(unquote(rest[..close].trim()));
+            self.position += close + 1;
+        }
+
+        let dotted = arrow.contains('.');
+        let bidirectional = arrow.starts_with('<') || !arrow.ends_with('>');
+        Ok((
+            label.filter(|label| !label.is_empty()),
+            dotted,
+            bidirectional,
+        ))
+    }
+}
+
+/// The `;`-separated statements on a line, ignoring `;` in quoted text
+fn statements_in(line: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let (mut start, mut quoted) = (0, false);
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                statements.push(line[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(line[start..].trim());
+    statements
+}
+
+/// Shape and text of a node declared with `@{ shape: ..., label: ... }`
+fn attribute_shape(attributes: &str) -> (Shape, Option<String>) {
+    let mut shape = Shape::Plain;
+    let mut text = None;
+    for attribute in attributes.split(',') {
+        let Some((key, value)) = attribute.split_once(':') else {
+            continue;
+        };
+        let value = unquote(value.trim());
+        match key.trim() {
+            "label" => text = Some(value),
+            "shape" => {
+                shape = match value.as_str() {
+                    "fork" | "join" => Shape::Fork,
+                    "diam" | "diamond" | "decision" => Shape::Decision,
+                    "stadium" | "pill" | "terminal" => Shape::Node(BaseNodeType::Value),
+                    "rounded" => Shape::Node(BaseNodeType::Entity),
+                    "subproc" | "subroutine" => Shape::Node(BaseNodeType::Aggregate),
+                    "hex" | "hexagon" | "prepare" => Shape::Node(BaseNodeType::Service),
+                    "circle" | "circ" => Shape::Node(BaseNodeType::Event),
+                    "flag" | "asymmetric" => Shape::Node(BaseNodeType::EntityReference),
+                    _ => Shape::Node(BaseNodeType::Command),
+                }
+            }
+            _ => {}
+        }
+    }
+    (shape, text)
+}
+
+/// Mermaid text for a node: its shape, quoted label and class
+fn node_shape<N: Serialize>(node: &CompositionNode<N>) -> String {
+    let text = quote(&display_label(node));
+    match fork_kind(node) {
+        Some("Choice") => return format!("{{{text}}}"),
+        Some("Parallel") => return "@{ shape: fork }".to_string(),
+        _ => {}
+    }
+
+    let node_type = serde_json::to_value(&node.node_type).unwrap_or(JsonValue::Null);
+    match variant_name(&node_type) {
+        Some("Value") => format!("([{text}])"),
+        Some("EntityReference") => format!(">{text}]"),
+        Some("Entity") => format!("({text})"),
+        Some("Aggregate") => format!("[[{text}]]"),
+        Some("Service") => format!("{{{{{text}}}}}"),
+        Some("Command") => format!("[{text}]"),
+        Some("Event") => format!("(({text}))"),
+        _ => {
+            let class = type_label(&node_type);
+            if !class.is_empty() && class.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                format!("[{text}]:::{class}")
+            } else {
+                format!("[{text}]")
+            }
+        }
+    }
+}
+
+/// Mermaid quoted text, with quotes written as the `#quot;` entity
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "#quot;"))
+}
+
+fn unquote(text: &str) -> String {
+    let text = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text);
+    text.replace("#quot;", "\"")
+}
+
+fn parse_error(line: usize, message: &str) -> CompositionError {
+    CompositionError::ParseError(format!("line {line}: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn relationship_types(graph: &GraphComposition) -> Vec<String> {
+        let mut types: Vec<String> = graph
+            .edges
+            .values()
+            .map(|edge| DomainRelationshipMapping::to_string(&edge.relationship.relationship_type))
+            .collect();
+        types.sort();
+        types
+    }
+
+    #[test]
+    fn test_to_mermaid_round_trip() {
+        let stage = |name: &str| {
+            GraphComposition::composite(name).add_node(BaseNodeType::Command, "run", json!({}))
+        };
+        let review = GraphComposition::aggregate("Order", "o-1")
+            .add_node(BaseNodeType::Value, "total", json!(250))
+            .add_node(
+                BaseNodeType::Custom("Policy".into()),
+                "say \"hi\"",
+                json!({}),
+            )
+            .add_edge_by_label("root", "total", BaseRelationshipType::Contains)
+            .add_edge_by_label("root", "say \"hi\"", BaseRelationshipType::DependsOn);
+        let graph = review
+            .then(&stage("Pack").choice(&stage("Ship")).unwrap())
+            .unwrap()
+            .then(&stage("Lint").parallel(&stage("Test")).unwrap())
+            .unwrap()
+            .then(&stage("Poll").repeat(3).unwrap())
+            .unwrap();
+
+        let chart = graph.to_mermaid();
+        assert!(chart.starts_with("graph LR\n    n0[[\"Order\"]]\n"));
+        assert!(chart.contains("{\"Choice\"}"));
+        assert!(chart.contains("@{ shape: fork }"));
+        assert!(chart.contains("([\"total\"])"));
+        assert!(chart.contains("[\"say #quot;hi#quot;\"]:::Policy"));
+        assert!(chart.contains(" -->|\"contains\"| "));
+        assert!(chart.contains(" -.->|\"loop\"| "));
+        assert_eq!(chart, graph.to_mermaid());
+
+        let read = GraphComposition::from_mermaid(&chart).unwrap();
+        assert_eq!(read.nodes.len(), graph.nodes.len());
+        assert_eq!(relationship_types(&read), relationship_types(&graph));
+        assert_eq!(
+            read.composition_root,
+            read.node_by_label("Order").unwrap().id
+        );
+        assert_eq!(
+            read.node_by_label("say \"hi\"").unwrap().node_type,
+            BaseNodeType::Custom("Policy".into())
+        );
+        assert_eq!(
+            read.node_by_label("total").unwrap().node_type,
+            BaseNodeType::Value
+        );
+        let choice = read.node_by_label("Choice").unwrap().id;
+        assert_eq!(read.choice_branches(choice).unwrap().len(), 2);
+        assert!(read.find_cycles().is_empty());
+    }
+
+    #[test]
+    fn test_from_mermaid_hand_written() {
+        let chart = r#"
+            %% Review flow
+            flowchart TD
+                A[Test Start] --> B{Valid?}
+                B --> C((Accepted)) & D>Rejected]
+                C -- depends_on --> E([Score]):::value_object
+                D <--> E -. references .-> B
+                subgraph audit
+                    E -.->|loop| A; F[[Ledger]] --- E
+                end
+                classDef done fill:#9f9
+        "#;
+        let graph = GraphComposition::from_mermaid(chart).unwrap();
+        let node = |label: &str| graph.node_by_label(label).unwrap();
+        let edge = |source: &str, target: &str| {
+            graph
+                .edges
+                .values()
+                .find(|e| e.source == node(source).id && e.target == node(target).id)
+                .unwrap()
+        };
+
+        assert_eq!(graph.nodes.len(), 6);
+        assert_eq!(graph.composition_root, node("Test Start").id);
+        assert_eq!(node("Test Start").node_type, BaseNodeType::Command);
+        assert_eq!(node("Accepted").node_type, BaseNodeType::Event);
+        assert_eq!(node("Rejected").node_type, BaseNodeType::EntityReference);
+        assert_eq!(node("Score").node_type, BaseNodeType::Value);
+        assert_eq!(node("Ledger").node_type, BaseNodeType::Aggregate);
+        assert_eq!(
+            edge("Test Start", "Valid?").relationship.relationship_type,
+            BaseRelationshipType::Sequence
+        );
+        assert_eq!(graph.choice_branches(node("Valid?").id).unwrap().len(), 2);
+        assert_eq!(
+            edge("Accepted", "Score").relationship.relationship_type,
+            BaseRelationshipType::DependsOn
+        );
+        assert!(edge("Rejected", "Score").relationship.bidirectional);
+        let optional = &edge("Score", "Valid?").relationship;
+        assert_eq!(optional.relationship_type, BaseRelationshipType::References);
+        assert!(!optional.is_back_edge());
+        assert!(!graph.find_cycles().is_empty());
+        assert!(edge("Ledger", "Score").relationship.bidirectional);
+        let back = &edge("Score", "Test Start").relationship;
+        assert_eq!(back.relationship_type, BaseRelationshipType::Loop);
+        assert!(back.is_back_edge());
+
+        for (chart, line) in [
+            ("A --> B", 1),
+            ("graph LR\n  A[Start --> B", 2),
+            ("graph LR\n  A --> \n", 2),
+            ("graph LR\n  A ~~ B", 2),
+        ] {
+            match GraphComposition::from_mermaid(chart) {
+                Err(CompositionError::ParseError(message)) => {
+                    assert!(message.starts_with(&format!("line {line}:")), "{message}")
+                }
+                other => panic!("expected a parse error, got {other:?}"),
+            }
+        }
+        assert!(GraphComposition::from_mermaid("graph LR\n").is_err());
+    }
+}
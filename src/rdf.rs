@@ -0,0 +1,923 @@
+//! RDF export and import
+//!
+//! Publishes compositions as linked data. Each node is named by a
+//! `urn:uuid:` IRI of its node ID, except entity references, which are named
+//! by their `entity_id` under the entity namespace of `RdfOptions`, so
+//! references to the same entity from different graphs meet. Node types
+//! become classes and relationship types become predicates under the
+//! vocabulary namespace, such as `cim:Aggregate` and `cim:depends_on`, and
+//! the fields of node data become literals. The composition itself is a
+//! `cim:Composition` named by the `urn:uuid:` IRI of its `GraphId`, with
+//! its name and `cim:root`.
+//!
+//! `to_turtle`, `to_jsonld` and `to_ntriples` write the same statements, and
+//! `from_ntriples` reads N-Triples back. RDF statements have no place for
+//! edge metadata, back-edges or bidirectional edges, so those are not kept,
+//! and edges of the same type between the same nodes become one statement.
+
+use crate::base_types::*;
+use crate::composition::{
+    CompositionEdge, CompositionError, CompositionNode, CompositionType, GraphComposition,
+    ENTITY_ID_KEY,
+};
+use crate::dot::{display_label, type_label, variant_name};
+use crate::mapping::{snake_case, DomainRelationshipMapping};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Number, Value as JsonValue};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Default namespace of the classes and predicates written
+pub const DEFAULT_RDF_NAMESPACE: &str = "urn:cim-compose:vocab:";
+
+/// Default namespace of entity reference IRIs
+pub const DEFAULT_ENTITY_NAMESPACE: &str = "urn:cim-compose:entity:";
+
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const RDFS: &str = "http://www.w3.org/2000/01/rdf-schema#";
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+const UUID_SCHEME: &str = "urn:uuid:";
+
+/// Class of the resource describing the composition
+const COMPOSITION_CLASS: &str = "Composition";
+
+/// Predicate linking the composition to its root
+const ROOT_PREDICATE: &str = "root";
+
+/// Graph name of a composition read without a `cim:Composition`
+const RDF_GRAPH: &str = "RDF";
+
+/// Namespaces of the RDF formats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RdfOptions {
+    namespace: String,
+    entity_namespace: String,
+}
+
+impl RdfOptions {
+    pub fn new() -> Self {
+        Self {
+            namespace: DEFAULT_RDF_NAMESPACE.to_string(),
+            entity_namespace: DEFAULT_ENTITY_NAMESPACE.to_string(),
+        }
+    }
+
+    /// Write classes and predicates under this namespace, bound to the `cim` prefix
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Name entity references by their `entity_id` under this namespace
+    pub fn entity_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.entity_namespace = namespace.into();
+        self
+    }
+
+    fn vocabulary(&self, local: &str) -> String {
+        format!("{}{}", self.namespace, encode(local))
+    }
+
+    /// Prefixes used by Turtle and JSON-LD
+    fn prefixes(&self) -> [(&str, &str); 4] {
+        [
+            ("cim", &self.namespace),
+            ("rdf", RDF),
+            ("rdfs", RDFS),
+            ("xsd", XSD),
+        ]
+    }
+}
+
+impl Default for RdfOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The object of a statement
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    /// An IRI, or a blank node label starting with `_:`
+    Iri(String),
+    Literal(JsonValue),
+}
+
+impl Term {
+    fn to_ntriples(&self) -> String {
+        match self {
+            Term::Iri(iri) if iri.starts_with("_:") => iri.clone(),
+            Term::Iri(iri) => format!("<{iri}>"),
+            Term::Literal(value) => match lexical(value) {
+                (text, None) => quote(&text),
+                (text, Some(datatype)) => format!("{}^^<{datatype}>", quote(&text)),
+            },
+        }
+    }
+}
+
+/// Predicates and objects of each subject, the composition first and its
+/// root second
+type Statements = Vec<(String, Vec<(String, Term)>)>;
+
+impl<N, R> GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Write the composition as Turtle with the default namespaces
+    pub fn to_turtle(&self) -> String {
+        self.to_turtle_with(&RdfOptions::new())
+    }
+
+    /// Write the composition as Turtle
+    pub fn to_turtle_with(&self, options: &RdfOptions) -> String {
+        let prefixes = options.prefixes();
+        let mut turtle = String::new();
+        for (prefix, namespace) in prefixes {
+            let _ = writeln!(turtle, "@prefix {prefix}: <{namespace}> .");
+        }
+        for (subject, statements) in self.statements(options) {
+            let _ = write!(turtle, "\n{}", turtle_iri(&subject, &prefixes));
+            for (index, (predicate, object)) in statements.iter().enumerate() {
+                let predicate = if *predicate == format!("{RDF}type") {
+                    "a".to_string()
+                } else {
+                    turtle_iri(predicate, &prefixes)
+                };
+                let object = match object {
+                    Term::Iri(iri) => turtle_iri(iri, &prefixes),
+                    Term::Literal(value) => turtle_literal(value, &prefixes),
+                };
+                let separator = if index == 0 { " " } else { " ;\n    " };
+                let _ = write!(turtle, "{separator}{predicate} {object}");
+            }
+            turtle.push_str(" .\n");
+        }
+        turtle
+    }
+
+    /// Write the composition as JSON-LD 1.1 with the default namespaces
+    pub fn to_jsonld(&self) -> JsonValue {
+        self.to_jsonld_with(&RdfOptions::new())
+    }
+
+    /// Write the composition as JSON-LD 1.1
+    ///
+    /// Node data fields holding objects or arrays are `@json` literals.
+    pub fn to_jsonld_with(&self, options: &RdfOptions) -> JsonValue {
+        let prefixes = options.prefixes();
+        let mut context = Map::new();
+        context.insert("@version".to_string(), json!(1.1));
+        for (prefix, namespace) in prefixes {
+            context.insert(prefix.to_string(), json!(namespace));
+        }
+
+        let mut graph = Vec::new();
+        for (subject, statements) in self.statements(options) {
+            let mut resource = Map::new();
+            resource.insert("@id".to_string(), json!(subject));
+            for (predicate, object) in statements {
+                let (key, value) = match object {
+                    Term::Iri(class) if predicate == format!("{RDF}type") => (
+                        "@type".to_string(),
+                        json!(compact(&class, &prefixes).unwrap_or(class)),
+                    ),
+                    Term::Iri(iri) => (predicate, json!({ "@id": iri })),
+                    Term::Literal(value @ (JsonValue::Object(_) | JsonValue::Array(_))) => {
+                        (predicate, json!({ "@type": "@json", "@value": value }))
+                    }
+                    Term::Literal(value) => (predicate, value),
+                };
+                let key = compact(&key, &prefixes).unwrap_or(key);
+                match resource.get_mut(&key) {
+                    None => {
+                        resource.insert(key, value);
+                    }
+                    Some(JsonValue::Array(values)) => values.push(value),
+                    Some(existing) => *existing = json!([existing.take(), value]),
+                }
+            }
+            graph.push(JsonValue::Object(resource));
+        }
+        json!({ "@context": context, "@graph": graph })
+    }
+
+    /// Write the composition as N-Triples with the default namespaces
+    pub fn to_ntriples(&self) -> String {
+        self.to_ntriples_with(&RdfOptions::new())
+    }
+
+    /// Write the composition as N-Triples, one statement per line
+    pub fn to_ntriples_with(&self, options: &RdfOptions) -> String {
+        let mut ntriples = String::new();
+        for (subject, statements) in self.statements(options) {
+            for (predicate, object) in statements {
+                let _ = writeln!(
+                    ntriples,
+                    "<{subject}> <{predicate}> {} .",
+                    object.to_ntriples()
+                );
+            }
+        }
+        ntriples
+    }
+
+    /// The statements of every format, sorted so output is stable
+    fn statements(&self, options: &RdfOptions) -> Statements {
+        let mut subjects: BTreeMap<String, Vec<(String, Term)>> = BTreeMap::new();
+        for node in self.nodes.values() {
+            let node_type = serde_json::to_value(&node.node_type).unwrap_or(JsonValue::Null);
+            let statements = subjects.entry(node_iri(node, options)).or_default();
+            statements.push((
+                format!("{RDF}type"),
+                Term::Iri(options.vocabulary(&type_label(&node_type))),
+            ));
+            statements.push((
+                format!("{RDFS}label"),
+                Term::Literal(JsonValue::String(display_label(node))),
+            ));
+            match &node.data {
+                JsonValue::Object(fields) => {
+                    statements.extend(fields.iter().filter(|(_, value)| !value.is_null()).map(
+                        |(key, value)| (options.vocabulary(key), Term::Literal(value.clone())),
+                    ))
+                }
+                JsonValue::Null => {}
+                value => statements.push((format!("{RDF}value"), Term::Literal(value.clone()))),
+            }
+        }
+        for edge in self.edges.values() {
+            let (Some(source), Some(target)) =
+                (self.nodes.get(&edge.source), self.nodes.get(&edge.target))
+            else {
+                continue;
+            };
+            let relationship_type = serde_json::to_value(&edge.relationship.relationship_type)
+                .unwrap_or(JsonValue::Null);
+            let predicate = match &relationship_type {
+                JsonValue::String(name) => snake_case(name),
+                other => type_label(other),
+            };
+            subjects
+                .entry(node_iri(source, options))
+                .or_default()
+                .push((
+                    options.vocabulary(&predicate),
+                    Term::Iri(node_iri(target, options)),
+                ));
+        }
+
+        let root = self
+            .nodes
+            .get(&self.composition_root)
+            .map(|node| node_iri(node, options));
+        let mut composition = vec![
+            (
+                format!("{RDF}type"),
+                Term::Iri(options.vocabulary(COMPOSITION_CLASS)),
+            ),
+            (
+                format!("{RDFS}label"),
+                Term::Literal(JsonValue::String(self.metadata.name.clone())),
+            ),
+        ];
+        composition.extend(
+            root.clone()
+                .map(|root| (options.vocabulary(ROOT_PREDICATE), Term::Iri(root))),
+        );
+
+        let mut ordered = vec![(format!("{UUID_SCHEME}{}", self.id.as_uuid()), composition)];
+        if let Some(statements) = root.and_then(|root| subjects.remove_entry(&root)) {
+            ordered.push(statements);
+        }
+        ordered.extend(subjects);
+        for (_, statements) in &mut ordered {
+            statements.sort_by_cached_key(|(predicate, object)| {
+                (predicate.clone(), object.to_ntriples())
+            });
+            statements.dedup();
+        }
+        ordered
+    }
+}
+
+impl GraphComposition<BaseNodeType, BaseRelationshipType> {
+    /// Read N-Triples with the default namespaces
+    pub fn from_ntriples(text: &str) -> Result<Self, CompositionError> {
+        Self::from_ntriples_with(text, &RdfOptions::new())
+    }
+
+    /// Read N-Triples
+    ///
+    /// Every IRI or blank node in subject or object position becomes a node;
+    /// `urn:uuid:` IRIs keep their UUID as node ID and other IRIs get an ID
+    /// derived from them. Classes and predicates in the vocabulary namespace
+    /// map back to node and relationship types, and literals become node
+    /// data fields. Resources typed outside the namespace get a custom type
+    /// named by the class IRI, and untyped resources are entity references
+    /// whose `entity_id` is taken from their IRI. The root is the `cim:root`
+    /// of a `cim:Composition`, or else the first subject.
+    pub fn from_ntriples_with(text: &str, options: &RdfOptions) -> Result<Self, CompositionError> {
+        let mut triples = Vec::new();
+        let mut seen = HashSet::new();
+        for (index, line) in text.lines().enumerate() {
+            let Some(triple) = parse_triple(line).map_err(|message| {
+                CompositionError::ParseError(format!("line {}: {message}", index + 1))
+            })?
+            else {
+                continue;
+            };
+            let key = (
+                triple.subject.clone(),
+                triple.predicate.clone(),
+                triple.object.to_ntriples(),
+            );
+            if seen.insert(key) {
+                triples.push(triple);
+            }
+        }
+
+        let rdf_type = format!("{RDF}type");
+        let composition_class = Term::Iri(options.vocabulary(COMPOSITION_CLASS));
+        let compositions: HashSet<&str> = triples
+            .iter()
+            .filter(|triple| triple.predicate == rdf_type && triple.object == composition_class)
+            .map(|triple| triple.subject.as_str())
+            .collect();
+
+        let mut resources: Vec<Resource> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        let mut position = |iri: &str, resources: &mut Vec<Resource>| {
+            *positions.entry(iri.to_string()).or_insert_with(|| {
+                resources.push(Resource::new(iri));
+                resources.len() - 1
+            })
+        };
+        let mut composition: Option<(&str, Option<String>, Option<String>)> = None;
+        let mut links = Vec::new();
+
+        for triple in &triples {
+            if compositions.contains(triple.subject.as_str()) {
+                let (_, name, root) = composition.get_or_insert((&triple.subject, None, None));
+                match &triple.object {
+                    Term::Literal(JsonValue::String(label))
+                        if triple.predicate == format!("{RDFS}label") =>
+                    {
+                        name.get_or_insert_with(|| label.clone());
+                    }
+                    Term::Iri(iri) if triple.predicate == options.vocabulary(ROOT_PREDICATE) => {
+                        root.get_or_insert_with(|| iri.clone());
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            let subject = position(&triple.subject, &mut resources);
+            match &triple.object {
+                Term::Iri(class) if triple.predicate == rdf_type => {
+                    let resource = &mut resources[subject];
+                    if resource.node_type.is_none() {
+                        resource.node_type = Some(match local_name(class, &options.namespace) {
+                            Some(name) => serde_json::from_value(JsonValue::String(name.clone()))
+                                .unwrap_or(BaseNodeType::Custom(name)),
+                            None => BaseNodeType::Custom(class.clone()),
+                        });
+                    }
+                }
+                Term::Iri(target) => {
+                    let target = position(target, &mut resources);
+                    let relationship_type = match local_name(&triple.predicate, &options.namespace)
+                    {
+                        Some(name) => DomainRelationshipMapping::from_string(&name),
+                        None => BaseRelationshipType::Custom(triple.predicate.clone()),
+                    };
+                    links.push((subject, target, relationship_type));
+                }
+                Term::Literal(JsonValue::String(label))
+                    if triple.predicate == format!("{RDFS}label") =>
+                {
+                    resources[subject]
+                        .label
+                        .get_or_insert_with(|| label.clone());
+                }
+                Term::Literal(value) if triple.predicate == format!("{RDF}value") => {
+                    resources[subject].value = Some(value.clone());
+                }
+                Term::Literal(value) => {
+                    let key = local_name(&triple.predicate, &options.namespace)
+                        .unwrap_or_else(|| triple.predicate.clone());
+                    let fields = &mut resources[subject].fields;
+                    match fields.get_mut(&key) {
+                        None => {
+                            fields.insert(key, value.clone());
+                        }
+                        Some(JsonValue::Array(values)) => values.push(value.clone()),
+                        Some(existing) => *existing = json!([existing.take(), value]),
+                    }
+                }
+            }
+        }
+
+        if resources.is_empty() {
+            return Err(CompositionError::ParseError(
+                "N-Triples document has no nodes".to_string(),
+            ));
+        }
+        let root = composition
+            .as_ref()
+            .and_then(|(_, _, root)| root.as_ref())
+            .and_then(|root| positions.get(root).copied())
+            .unwrap_or(0);
+
+        let mut graph = GraphComposition::new(
+            BaseNodeType::Aggregate,
+            CompositionType::Composite {
+                structure_type: RDF_GRAPH.to_string(),
+            },
+        );
+        graph.nodes.clear();
+        graph.metadata.name = RDF_GRAPH.to_string();
+        if let Some((iri, name, _)) = composition {
+            if let Some(id) = iri
+                .strip_prefix(UUID_SCHEME)
+                .and_then(|id| Uuid::parse_str(id).ok())
+            {
+                graph.id = GraphId::from_uuid(id);
+            }
+            if let Some(name) = name {
+                graph.metadata.name = name;
+            }
+        }
+
+        let ids: Vec<NodeId> = resources
+            .into_iter()
+            .map(|resource| {
+                let node = resource.into_node(&options.entity_namespace);
+                let id = node.id;
+                graph.nodes.insert(id, node);
+                id
+            })
+            .collect();
+        graph.composition_root = ids[root];
+        for (source, target, relationship_type) in links {
+            let edge = CompositionEdge::new(ids[source], ids[target], relationship_type);
+            graph.edges.insert(edge.id, edge);
+        }
+
+        graph.rebuild_indexes();
+        Ok(graph)
+    }
+}
+
+/// A node being read, with everything stated about it
+struct Resource {
+    iri: String,
+    node_type: Option<BaseNodeType>,
+    label: Option<String>,
+    fields: Map<String, JsonValue>,
+    value: Option<JsonValue>,
+}
+
+impl Resource {
+    fn new(iri: &str) -> Self {
+        Self {
+            iri: iri.to_string(),
+            node_type: None,
+            label: None,
+            fields: Map::new(),
+            value: None,
+        }
+    }
+
+    fn into_node(mut self, entity_namespace: &str) -> CompositionNode {
+        let entity_id = local_name(&self.iri, entity_namespace).unwrap_or_else(|| self.iri.clone());
+        let node_type = self.node_type.unwrap_or(BaseNodeType::EntityReference);
+        if node_type == BaseNodeType::EntityReference {
+            self.fields
+                .entry(ENTITY_ID_KEY)
+                .or_insert_with(|| JsonValue::String(entity_id.clone()));
+        }
+        let data = match self.value {
+            Some(value) if self.fields.is_empty() => value,
+            _ => JsonValue::Object(self.fields),
+        };
+
+        let mut node = CompositionNode::new(node_type, self.label.unwrap_or(entity_id), data);
+        node.id = NodeId::from_uuid(
+            self.iri
+                .strip_prefix(UUID_SCHEME)
+                .and_then(|id| Uuid::parse_str(id).ok())
+                .unwrap_or_else(|| Uuid::new_v5(&Uuid::NAMESPACE_URL, self.iri.as_bytes())),
+        );
+        node
+    }
+}
+
+/// The IRI naming a node
+fn node_iri<N: Serialize>(node: &CompositionNode<N>, options: &RdfOptions) -> String {
+    let node_type = serde_json::to_value(&node.node_type).unwrap_or(JsonValue::Null);
+    match node.data.get(ENTITY_ID_KEY) {
+        Some(entity_id) if variant_name(&node_type) == Some("EntityReference") => {
+            let entity_id = entity_id
+                .as_str()
+                .map_or_else(|| entity_id.to_string(), str::to_string);
+            format!("{}{}", options.entity_namespace, encode(&entity_id))
+        }
+        _ => format!("{UUID_SCHEME}{}", node.id),
+    }
+}
+
+/// Text percent-encoded for use at the end of an IRI
+fn encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// Inverse of `encode`; invalid escapes are kept as written
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| text.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The decoded name of an IRI within a namespace
+fn local_name(iri: &str, namespace: &str) -> Option<String> {
+    iri.strip_prefix(namespace)
+        .filter(|local| !local.is_empty())
+        .map(decode)
+}
+
+/// The lexical form and datatype IRI of a literal; plain strings have no datatype
+fn lexical(value: &JsonValue) -> (String, Option<String>) {
+    match value {
+        JsonValue::String(text) => (text.clone(), None),
+        JsonValue::Bool(flag) => (flag.to_string(), Some(format!("{XSD}boolean"))),
+        JsonValue::Number(number) if number.is_f64() => {
+            (number.to_string(), Some(format!("{XSD}double")))
+        }
+        JsonValue::Number(number) => (number.to_string(), Some(format!("{XSD}integer"))),
+        other => (other.to_string(), Some(format!("{RDF}JSON"))),
+    }
+}
+
+/// The JSON value of a literal read with this datatype
+fn literal_value(text: String, datatype: Option<&str>) -> Result<JsonValue, String> {
+    let Some(datatype) = datatype else {
+        return Ok(JsonValue::String(text));
+    };
+    let value = match datatype.strip_prefix(XSD) {
+        Some("integer" | "int" | "long" | "short" | "byte" | "nonNegativeInteger") => {
+            text.parse::<i64>().ok().map(JsonValue::from)
+        }
+        Some("double" | "float" | "decimal") => text
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(JsonValue::Number),
+        Some("boolean") => match text.as_str() {
+            "true" | "1" => Some(JsonValue::Bool(true)),
+            "false" | "0" => Some(JsonValue::Bool(false)),
+            _ => None,
+        },
+        _ if datatype == format!("{RDF}JSON") => {
+            Some(serde_json::from_str(&text).map_err(|e| format!("invalid JSON literal: {e}"))?)
+        }
+        _ => None,
+    };
+    Ok(value.unwrap_or(JsonValue::String(text)))
+}
+
+/// An IRI as a prefixed name, if it is in one of the namespaces
+fn compact(iri: &str, prefixes: &[(&str, &str)]) -> Option<String> {
+    prefixes.iter().find_map(|(prefix, namespace)| {
+        let local = iri.strip_prefix(namespace)?;
+        let mut chars = local.chars();
+        let valid = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        valid.then(|| format!("{prefix}:{local}"))
+    })
+}
+
+fn turtle_iri(iri: &str, prefixes: &[(&str, &str)]) -> String {
+    compact(iri, prefixes).unwrap_or_else(|| format!("<{iri}>"))
+}
+
+fn turtle_literal(value: &JsonValue, prefixes: &[(&str, &str)]) -> String {
+    match value {
+        JsonValue::Bool(_) => value.to_string(),
+        JsonValue::Number(number) if !number.is_f64() => number.to_string(),
+        _ => match lexical(value) {
+            (text, None) => quote(&text),
+            (text, Some(datatype)) => {
+                format!("{}^^{}", quote(&text), turtle_iri(&datatype, prefixes))
+            }
+        },
+    }
+}
+
+/// A double-quoted N-Triples and Turtle string
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// One N-Triples statement
+struct Triple {
+    subject: String,
+    predicate: String,
+    object: Term,
+}
+
+/// Read one line of N-Triples; blank lines and comments have no statement
+fn parse_triple(line: &str) -> Result<Option<Triple>, String> {
+    let mut reader = Reader { rest: line };
+    reader.skip_space();
+    if reader.rest.is_empty() || reader.rest.starts_with('#') {
+        return Ok(None);
+    }
+
+    let subject = match reader.term()? {
+        Term::Iri(iri) => iri,
+        Term::Literal(_) => return Err("a literal cannot be a subject".to_string()),
+    };
+    let predicate = match reader.term()? {
+        Term::Iri(iri) if !iri.starts_with("_:") => iri,
+        _ => return Err("the predicate must be an IRI".to_string()),
+    };
+    let object = reader.term()?;
+    reader.skip_space();
+    reader.rest = reader
+        .rest
+        .strip_prefix('.')
+        .ok_or_else(|| "expected `.` after the object".to_string())?;
+    reader.skip_space();
+    if !reader.rest.is_empty() && !reader.rest.starts_with('#') {
+        return Err(format!("unexpected `{}`", reader.rest));
+    }
+    Ok(Some(Triple {
+        subject,
+        predicate,
+        object,
+    }))
+}
+
+/// Position within one line of N-Triples
+struct Reader<'a> {
+    rest: &'a str,
+}
+
+impl Reader<'_> {
+    fn skip_space(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn term(&mut self) -> Result<Term, String> {
+        self.skip_space();
+        if let Some(rest) = self.rest.strip_prefix('<') {
+            let end = rest.find('>').ok_or("unterminated IRI")?;
+            self.rest = &rest[end + 1..];
+            Ok(Term::Iri(rest[..end].to_string()))
+        } else if self.rest.starts_with("_:") {
+            let end = self.rest[2..]
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.')))
+                .map_or(self.rest.len(), |end| end + 2);
+            // A label cannot end with `.`, which ends the statement instead
+            let label = self.rest[..end].trim_end_matches('.');
+            self.rest = &self.rest[label.len()..];
+            Ok(Term::Iri(label.to_string()))
+        } else if let Some(rest) = self.rest.strip_prefix('"') {
+            self.rest = rest;
+            let text = self.string()?;
+            if let Some(rest) = self.rest.strip_prefix("^^<") {
+                let end = rest.find('>').ok_or("unterminated datatype IRI")?;
+                self.rest = &rest[end + 1..];
+                literal_value(text, Some(&rest[..end])).map(Term::Literal)
+            } else {
+                // Language tags are dropped
+                if let Some(rest) = self.rest.strip_prefix('@') {
+                    let end = rest
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+                        .unwrap_or(rest.len());
+                    self.rest = &rest[end..];
+                }
+                Ok(Term::Literal(JsonValue::String(text)))
+            }
+        } else {
+            Err(format!(
+                "expected an IRI, blank node or literal at `{}`",
+                self.rest
+            ))
+        }
+    }
+
+    /// The rest of a quoted string whose opening quote was read
+    fn string(&mut self) -> Result<String, String> {
+        let mut text = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[index + 1..];
+                    return Ok(text);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('f') => '\u{c}',
+                        Some(c @ ('"' | '\'' | '\\')) => c,
+                        Some(kind @ ('u' | 'U')) => {
+                            let length = if kind == 'u' { 4 } else { 8 };
+                            let hex: String = chars.by_ref().take(length).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| format!("invalid escape `\\{kind}{hex}`"))?
+                        }
+                        other => {
+                            return Err(format!(
+                                "invalid escape `\\{}`",
+                                other.map(String::from).unwrap_or_default()
+                            ))
+                        }
+                    };
+                    text.push(escaped);
+                }
+                c => text.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An order with an entity reference, data of every kind and a custom type
+    fn order() -> GraphComposition {
+        GraphComposition::aggregate("Order", "o-1")
+            .add_node(
+                BaseNodeType::Value,
+                "total",
+                json!({ "amount": 250.5, "items": 3, "paid": false, "lines": [1, 2] }),
+            )
+            .add_node(
+                BaseNodeType::EntityReference,
+                "buyer",
+                json!({ "entity_id": "Ada L", "note": "say \"hi\"\n" }),
+            )
+            .add_node(BaseNodeType::Custom("Policy".into()), "pricing", json!(7))
+            .add_edge_by_label("root", "total", BaseRelationshipType::Contains)
+            .add_edge_by_label("root", "buyer", BaseRelationshipType::References)
+            .add_edge_by_label("total", "pricing", BaseRelationshipType::DependsOn)
+            .add_edge_by_label(
+                "pricing",
+                "buyer",
+                BaseRelationshipType::Custom("bills".into()),
+            )
+    }
+
+    #[test]
+    fn test_turtle_and_jsonld_export() {
+        let graph = order();
+        let root = format!("<urn:uuid:{}>", graph.composition_root);
+        let turtle = graph.to_turtle();
+        assert!(turtle.starts_with("@prefix cim: <urn:cim-compose:vocab:> .\n"));
+        assert!(turtle.contains(&format!(
+            "\n<urn:uuid:{}> a cim:Composition ;\n    rdfs:label \"Order\" ;\n    cim:root {root} .\n",
+            graph.id.as_uuid()
+        )));
+        assert!(turtle.contains(&format!(
+            "\n{root} a cim:Aggregate ;\n    rdfs:label \"Order\" ;"
+        )));
+        assert!(turtle.contains("cim:references <urn:cim-compose:entity:Ada%20L>"));
+        assert!(turtle.contains("cim:amount \"250.5\"^^xsd:double ;"));
+        assert!(turtle.contains("cim:items 3 ;"));
+        assert!(turtle.contains("cim:paid false ."));
+        assert!(turtle.contains("cim:lines \"[1,2]\"^^rdf:JSON"));
+        assert!(turtle.contains("cim:note \"say \\\"hi\\\"\\n\""));
+        assert!(turtle.contains("a cim:Policy ;"));
+        assert!(turtle.contains("rdf:value 7"));
+        assert!(turtle.contains("cim:bills <urn:cim-compose:entity:Ada%20L>"));
+
+        let jsonld = graph.to_jsonld();
+        assert_eq!(jsonld["@context"]["cim"], DEFAULT_RDF_NAMESPACE);
+        let resources = jsonld["@graph"].as_array().unwrap();
+        assert_eq!(resources[0]["@type"], "cim:Composition");
+        assert_eq!(resources[1]["@type"], "cim:Aggregate");
+        assert_eq!(resources[1]["cim:id"], "o-1");
+        assert_eq!(
+            resources[1]["cim:contains"]["@id"].as_str().unwrap().len(),
+            45
+        );
+        let total = resources
+            .iter()
+            .find(|r| r["rdfs:label"] == "total")
+            .unwrap();
+        assert_eq!(total["cim:amount"], 250.5);
+        assert_eq!(
+            total["cim:lines"],
+            json!({ "@type": "@json", "@value": [1, 2] })
+        );
+
+        let options = RdfOptions::new()
+            .namespace("https://example.org/orders#")
+            .entity_namespace("https://example.org/people/");
+        let ntriples = graph.to_ntriples_with(&options);
+        assert!(ntriples.contains(" <https://example.org/orders#contains> "));
+        assert!(ntriples.contains(" <https://example.org/people/Ada%20L> ."));
+        assert_eq!(graph.to_turtle(), turtle);
+    }
+
+    #[test]
+    fn test_ntriples_round_trip_and_foreign_data() {
+        let graph = order();
+        let ntriples = graph.to_ntriples();
+        let read = GraphComposition::from_ntriples(&ntriples).unwrap();
+        assert_eq!(read.to_ntriples(), ntriples);
+        assert_eq!(read.id, graph.id);
+        assert_eq!(read.composition_root, graph.composition_root);
+        assert_eq!(read.metadata.name, "Order");
+        assert_eq!(read.nodes.len(), 4);
+        assert_eq!(read.edges.len(), 4);
+        let total = read.node_by_label("total").unwrap();
+        assert_eq!(total.data, graph.node_by_label("total").unwrap().data);
+        let pricing = read.node_by_label("pricing").unwrap();
+        assert_eq!(pricing.node_type, BaseNodeType::Custom("Policy".into()));
+        assert_eq!(pricing.data, json!(7));
+
+        let foreign = r#"
+            # People from another vocabulary
+            <https://example.org/ada> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://xmlns.com/foaf/0.1/Person> .
+            <https://example.org/ada> <http://xmlns.com/foaf/0.1/name> "Ada"@en .
+            <https://example.org/ada> <http://xmlns.com/foaf/0.1/knows> _:b0 .
+            _:b0 <urn:cim-compose:vocab:age> "36"^^<http://www.w3.org/2001/XMLSchema#integer> .
+        "#;
+        let people = GraphComposition::from_ntriples(foreign).unwrap();
+        let ada = &people.nodes[&people.composition_root];
+        assert_eq!(
+            ada.node_type,
+            BaseNodeType::Custom("http://xmlns.com/foaf/0.1/Person".into())
+        );
+        assert_eq!(ada.data["http://xmlns.com/foaf/0.1/name"], "Ada");
+        let friend = people.node_by_label("_:b0").unwrap();
+        assert_eq!(friend.node_type, BaseNodeType::EntityReference);
+        assert_eq!(friend.data, json!({ "age": 36, "entity_id": "_:b0" }));
+        let knows = people.edges.values().next().unwrap();
+        assert_eq!(
+            knows.relationship.relationship_type,
+            BaseRelationshipType::Custom("http://xmlns.com/foaf/0.1/knows".into())
+        );
+
+        let broken = "<urn:a> <urn:b> <urn:c> .\n<urn:a> <urn:b> \"open .\n";
+        assert_eq!(
+            GraphComposition::from_ntriples(broken).unwrap_err(),
+            CompositionError::ParseError("line 2: unterminated string".to_string())
+        );
+        assert!(GraphComposition::from_ntriples("# nothing\n").is_err());
+    }
+}
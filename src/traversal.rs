@@ -0,0 +1,491 @@
+//! Walking compositions and finding paths between nodes
+//!
+//! Traversals follow edges in a chosen `Direction`, optionally restricted to
+//! some relationship types. Bidirectional edges are followed both ways, as in
+//! `get_connected_nodes`.
+//!
+//! Path costs come from an edge metadata key, such as the `"weight"` set with
+//! `Relationship::with_metadata`. Edges without the key cost 1, so a path
+//! query without a key counts hops.
+
+use crate::base_types::*;
+use crate::composition::{CompositionError, GraphComposition};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+/// Which way edges are followed during a traversal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Direction {
+    /// From source to target
+    #[default]
+    Outgoing,
+    /// From target to source
+    Incoming,
+    /// Either way
+    Both,
+}
+
+/// The order in which a `Walk` visits nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WalkOrder {
+    BreadthFirst,
+    DepthFirst,
+}
+
+/// Predicate selecting which relationships a traversal follows
+type RelationshipFilter<'a, R> = Box<dyn Fn(&R) -> bool + 'a>;
+
+/// Iterator over the nodes reachable from a start node
+///
+/// Created by [`GraphComposition::bfs`] and [`GraphComposition::dfs`]. Yields
+/// the start node first and every reachable node once.
+pub struct Walk<'a, N, R> {
+    graph: &'a GraphComposition<N, R>,
+    order: WalkOrder,
+    direction: Direction,
+    filter: Option<RelationshipFilter<'a, R>>,
+    pending: VecDeque<NodeId>,
+    visited: HashSet<NodeId>,
+}
+
+impl<'a, N, R> Walk<'a, N, R> {
+    fn new(graph: &'a GraphComposition<N, R>, start: NodeId, order: WalkOrder) -> Self {
+        let mut pending = VecDeque::new();
+        if graph.nodes.contains_key(&start) {
+            pending.push_back(start);
+        }
+        Self {
+            graph,
+            order,
+            direction: Direction::Outgoing,
+            filter: None,
+            pending,
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Follow edges in the given direction
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Only follow edges whose relationship passes `filter`
+    pub fn via<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&R) -> bool + 'a,
+    {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+}
+
+impl<N, R> Iterator for Walk<'_, N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        loop {
+            let node_id = match self.order {
+                WalkOrder::BreadthFirst => self.pending.pop_front()?,
+                WalkOrder::DepthFirst => self.pending.pop_back()?,
+            };
+            if !self.visited.insert(node_id) {
+                continue;
+            }
+
+            let mut next: Vec<NodeId> = self
+                .graph
+                .steps(node_id, self.direction, |r| {
+                    self.filter.as_ref().is_none_or(|filter| filter(r))
+                })
+                .into_iter()
+                .map(|(_, target)| target)
+                .filter(|target| !self.visited.contains(target))
+                .collect();
+            if self.order == WalkOrder::DepthFirst {
+                // Visit neighbors in edge order despite popping from the back
+                next.reverse();
+            }
+            self.pending.extend(next);
+
+            return Some(node_id);
+        }
+    }
+}
+
+/// A path through a composition
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphPath {
+    /// Nodes in order, from the start node to the end node
+    pub nodes: Vec<NodeId>,
+    /// Edges taken between consecutive nodes
+    pub edges: Vec<EdgeId>,
+    /// Sum of the edge weights
+    pub cost: f64,
+}
+
+impl GraphPath {
+    /// Number of edges in the path
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+}
+
+/// A node waiting in Dijkstra's queue, cheapest first
+struct Candidate {
+    cost: f64,
+    node: NodeId,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl<N, R> GraphComposition<N, R>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Breadth-first walk from `start`
+    pub fn bfs(&self, start: NodeId) -> Walk<'_, N, R> {
+        Walk::new(self, start, WalkOrder::BreadthFirst)
+    }
+
+    /// Depth-first walk from `start`
+    pub fn dfs(&self, start: NodeId) -> Walk<'_, N, R> {
+        Walk::new(self, start, WalkOrder::DepthFirst)
+    }
+
+    /// Nodes reachable from `start` along outgoing edges, other than `start`
+    pub fn reachable_from(&self, start: NodeId) -> HashSet<NodeId> {
+        self.bfs(start).skip(1).collect()
+    }
+
+    /// Whether `to` can be reached from `from` along outgoing edges
+    pub fn is_reachable(&self, from: NodeId, to: NodeId) -> bool {
+        self.bfs(from).any(|node_id| node_id == to)
+    }
+
+    /// Nodes below `start` along edges of one relationship type, in breadth-first order
+    ///
+    /// `descendants_via(root, BaseRelationshipType::Contains)` lists everything
+    /// an aggregate contains, directly or transitively.
+    pub fn descendants_via(&self, start: NodeId, relationship: R) -> Vec<NodeId>
+    where
+        R: PartialEq,
+    {
+        self.bfs(start)
+            .via(move |r| *r == relationship)
+            .skip(1)
+            .collect()
+    }
+
+    /// Cheapest path from `from` to `to` along outgoing edges
+    ///
+    /// Edges cost the number stored under `weight_key` in their relationship
+    /// metadata, or 1 if the key is missing or `None` is given. Returns
+    /// `Ok(None)` if `to` is unreachable, and `InvalidWeight` if a weight is
+    /// not a non-negative number.
+    pub fn shortest_path(
+        &self,
+        from: NodeId,
+        to: NodeId,
+        weight_key: Option<&str>,
+    ) -> Result<Option<GraphPath>, CompositionError> {
+        if !self.nodes.contains_key(&from) {
+            return Err(CompositionError::NodeNotFound(from));
+        }
+
+        let mut best: HashMap<NodeId, f64> = HashMap::from([(from, 0.0)]);
+        let mut previous: HashMap<NodeId, (NodeId, EdgeId)> = HashMap::new();
+        let mut queue = BinaryHeap::from([Candidate {
+            cost: 0.0,
+            node: from,
+        }]);
+
+        while let Some(Candidate { cost, node }) = queue.pop() {
+            if node == to {
+                let mut nodes = vec![to];
+                let mut edges = Vec::new();
+                while let Some((before, edge_id)) = previous.get(nodes.last().unwrap_or(&to)) {
+                    nodes.push(*before);
+                    edges.push(*edge_id);
+                }
+                nodes.reverse();
+                edges.reverse();
+                return Ok(Some(GraphPath { nodes, edges, cost }));
+            }
+            if best.get(&node).is_some_and(|known| cost > *known) {
+                continue;
+            }
+
+            for (edge_id, next) in self.steps(node, Direction::Outgoing, |_| true) {
+                let next_cost = cost + self.edge_weight(edge_id, weight_key)?;
+                if best.get(&next).is_none_or(|known| next_cost < *known) {
+                    best.insert(next, next_cost);
+                    previous.insert(next, (node, edge_id));
+                    queue.push(Candidate {
+                        cost: next_cost,
+                        node: next,
+                    });
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Every simple path from `from` to `to` along outgoing edges, cheapest first
+    ///
+    /// Costs are computed as in [`GraphComposition::shortest_path`]. The number
+    /// of simple paths can grow exponentially with the size of the graph.
+    pub fn all_paths(
+        &self,
+        from: NodeId,
+        to: NodeId,
+        weight_key: Option<&str>,
+    ) -> Result<Vec<GraphPath>, CompositionError> {
+        if !self.nodes.contains_key(&from) {
+            return Err(CompositionError::NodeNotFound(from));
+        }
+        if from == to {
+            return Ok(vec![GraphPath {
+                nodes: vec![from],
+                edges: Vec::new(),
+                cost: 0.0,
+            }]);
+        }
+
+        let mut paths = Vec::new();
+        let mut nodes = vec![from];
+        let mut edges = Vec::new();
+        let mut on_path = HashSet::from([from]);
+        // Outgoing steps still to try from each node on the current path
+        let mut pending = vec![self.steps(from, Direction::Outgoing, |_| true)];
+
+        while let Some(steps) = pending.last_mut() {
+            let Some((edge_id, next)) = steps.pop() else {
+                pending.pop();
+                edges.pop();
+                if let Some(node_id) = nodes.pop() {
+                    on_path.remove(&node_id);
+                }
+                continue;
+            };
+            if on_path.contains(&next) {
+                continue;
+            }
+
+            edges.push(edge_id);
+            nodes.push(next);
+            if next == to {
+                let cost = edges
+                    .iter()
+                    .map(|edge_id| self.edge_weight(*edge_id, weight_key))
+                    .sum::<Result<f64, _>>()?;
+                paths.push(GraphPath {
+                    nodes: nodes.clone(),
+                    edges: edges.clone(),
+                    cost,
+                });
+                nodes.pop();
+                edges.pop();
+            } else {
+                on_path.insert(next);
+                pending.push(self.steps(next, Direction::Outgoing, |_| true));
+            }
+        }
+
+        paths.sort_by(|a, b| {
+            a.cost
+                .total_cmp(&b.cost)
+                .then_with(|| a.len().cmp(&b.len()))
+        });
+        Ok(paths)
+    }
+
+    /// Edges that can be taken from `node_id`, with the node each leads to
+    fn steps<F>(&self, node_id: NodeId, direction: Direction, filter: F) -> Vec<(EdgeId, NodeId)>
+    where
+        F: Fn(&R) -> bool,
+    {
+        let forward = direction != Direction::Incoming;
+        let backward = direction != Direction::Outgoing;
+
+        let outgoing = self
+            .out_edges(node_id)
+            .into_iter()
+            .filter(|edge| forward || edge.relationship.bidirectional)
+            .filter(|edge| filter(&edge.relationship.relationship_type))
+            .map(|edge| (edge.id, edge.target));
+        let incoming = self
+            .in_edges(node_id)
+            .into_iter()
+            .filter(|edge| backward || edge.relationship.bidirectional)
+            .filter(|edge| filter(&edge.relationship.relationship_type))
+            .map(|edge| (edge.id, edge.source));
+
+        let mut steps: Vec<(EdgeId, NodeId)> = outgoing.chain(incoming).collect();
+        steps.sort();
+        steps.dedup();
+        steps
+    }
+
+    /// The cost of an edge under `weight_key`
+    fn edge_weight(
+        &self,
+        edge_id: EdgeId,
+        weight_key: Option<&str>,
+    ) -> Result<f64, CompositionError> {
+        let Some(value) =
+            weight_key.and_then(|key| self.edges.get(&edge_id)?.relationship.metadata.get(key))
+        else {
+            return Ok(1.0);
+        };
+        match value.as_f64() {
+            Some(weight) if weight.is_finite() && weight >= 0.0 => Ok(weight),
+            _ => Err(CompositionError::InvalidWeight(edge_id, value.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn weighted(weight: f64) -> Relationship {
+        Relationship::new(BaseRelationshipType::Sequence)
+            .with_metadata("weight".to_string(), json!(weight))
+    }
+
+    /// root -> a -> c and root -> b -> c, where the route through b is cheaper
+    fn diamond() -> (GraphComposition, [NodeId; 4]) {
+        let graph = GraphComposition::composite("Route")
+            .add_node(BaseNodeType::Value, "a", json!({}))
+            .add_node(BaseNodeType::Value, "b", json!({}))
+            .add_node(BaseNodeType::Value, "c", json!({}));
+        let id = |label: &str| graph.node_by_label(label).unwrap().id;
+        let ids = [graph.composition_root, id("a"), id("b"), id("c")];
+        let [root, a, b, c] = ids;
+        let graph = graph
+            .add_edge_with_relationship(root, a, weighted(1.0))
+            .add_edge_with_relationship(a, c, weighted(5.0))
+            .add_edge_with_relationship(root, b, weighted(2.0))
+            .add_edge_with_relationship(b, c, weighted(1.0));
+        (graph, ids)
+    }
+
+    #[test]
+    fn test_walks() {
+        let (graph, [root, a, b, c]) = diamond();
+
+        let bfs: Vec<NodeId> = graph.bfs(root).collect();
+        assert_eq!(bfs.len(), 4);
+        assert_eq!(bfs[0], root);
+        assert_eq!(bfs[3], c);
+
+        let dfs: Vec<NodeId> = graph.dfs(root).collect();
+        assert_eq!(dfs.len(), 4);
+        assert_eq!(dfs[0], root);
+
+        let upstream: HashSet<NodeId> = graph.bfs(c).direction(Direction::Incoming).collect();
+        assert_eq!(upstream, HashSet::from([root, a, b, c]));
+        assert_eq!(graph.bfs(a).direction(Direction::Both).count(), 4);
+        assert_eq!(
+            graph
+                .bfs(root)
+                .via(|r| *r == BaseRelationshipType::Contains)
+                .count(),
+            1
+        );
+
+        assert_eq!(graph.reachable_from(a), HashSet::from([c]));
+        assert!(graph.is_reachable(root, c));
+        assert!(!graph.is_reachable(c, root));
+        assert!(!graph.is_reachable(a, b));
+    }
+
+    #[test]
+    fn test_descendants_via_contains() {
+        let graph = GraphComposition::aggregate("Order", "order-1")
+            .add_node(BaseNodeType::Value, "items", json!({}))
+            .add_node(BaseNodeType::Value, "item", json!({}))
+            .add_node(BaseNodeType::EntityReference, "customer", json!({}))
+            .add_edge_by_label("root", "items", BaseRelationshipType::Contains)
+            .add_edge_by_label("items", "item", BaseRelationshipType::Contains)
+            .add_edge_by_label("item", "customer", BaseRelationshipType::References);
+
+        let contained =
+            graph.descendants_via(graph.composition_root, BaseRelationshipType::Contains);
+        assert_eq!(
+            contained,
+            vec![
+                graph.node_by_label("items").unwrap().id,
+                graph.node_by_label("item").unwrap().id,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shortest_and_all_paths() {
+        let (graph, [root, a, b, c]) = diamond();
+
+        let cheapest = graph
+            .shortest_path(root, c, Some("weight"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(cheapest.nodes, vec![root, b, c]);
+        assert_eq!(cheapest.len(), 2);
+        assert_eq!(cheapest.cost, 3.0);
+
+        let hops = graph.shortest_path(root, c, None).unwrap().unwrap();
+        assert_eq!(hops.cost, 2.0);
+        assert!(graph.shortest_path(c, root, None).unwrap().is_none());
+        assert!(graph
+            .shortest_path(root, root, None)
+            .unwrap()
+            .unwrap()
+            .is_empty());
+
+        let paths = graph.all_paths(root, c, Some("weight")).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].nodes, vec![root, b, c]);
+        assert_eq!(paths[1].nodes, vec![root, a, c]);
+        assert_eq!(paths[1].cost, 6.0);
+
+        let negative = graph.add_edge_with_relationship(a, b, weighted(-1.0));
+        assert!(matches!(
+            negative.shortest_path(root, c, Some("weight")),
+            Err(CompositionError::InvalidWeight(_, _))
+        ));
+    }
+}
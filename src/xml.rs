@@ -0,0 +1,272 @@
+//! Helpers shared by the GraphML and GEXF formats
+//!
+//! Both formats carry the same attributes: a node's label, `node_type`,
+//! `data` and `metadata`, and an edge's `relationship_type`, `bidirectional`
+//! flag and `metadata`. Node and relationship types are written as their
+//! serialized name, such as `Aggregate`, or as JSON for types with fields,
+//! such as `{"Custom":"Policy"}`; data and metadata are written as JSON.
+//!
+//! Everything else about the composition, such as its root, invariants and
+//! ports, travels as a header: its interchange document without nodes and
+//! edges. Imports rebuild the interchange document and load it with
+//! `GraphComposition::from_interchange_json`, so they are checked the same
+//! way. Files from other tools may lack the header; the first node then
+//! becomes the root.
+
+use crate::base_types::*;
+use crate::composition::{
+    CompositionEdge, CompositionError, CompositionNode, CompositionType, GraphComposition,
+};
+use crate::interchange::SCHEMA_VERSION;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value as JsonValue};
+use uuid::Uuid;
+
+/// Attribute names shared by both formats
+pub(crate) const LABEL: &str = "label";
+pub(crate) const NODE_TYPE: &str = "node_type";
+pub(crate) const DATA: &str = "data";
+pub(crate) const METADATA: &str = "metadata";
+pub(crate) const RELATIONSHIP_TYPE: &str = "relationship_type";
+pub(crate) const BIDIRECTIONAL: &str = "bidirectional";
+
+/// A node read from a file, with its attributes as text
+#[derive(Debug, Default)]
+pub(crate) struct ImportedNode {
+    pub id: String,
+    pub label: Option<String>,
+    pub node_type: Option<String>,
+    pub data: Option<String>,
+    pub metadata: Option<String>,
+}
+
+/// An edge read from a file, with its attributes as text
+#[derive(Debug, Default)]
+pub(crate) struct ImportedEdge {
+    pub id: Option<String>,
+    pub source: String,
+    pub target: String,
+    pub relationship_type: Option<String>,
+    pub bidirectional: Option<bool>,
+    pub metadata: Option<String>,
+}
+
+/// Text safe inside XML attributes and elements
+///
+/// Whitespace other than spaces is written as character references, since
+/// parsers normalize it to spaces in attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\t' => escaped.push_str("&#9;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A node or relationship type as attribute text
+pub(crate) fn type_text<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(JsonValue::String(name)) => name,
+        Ok(value) => value.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Inverse of `type_text`
+fn type_value(text: &str) -> JsonValue {
+    match serde_json::from_str::<JsonValue>(text) {
+        Ok(value) if value.is_object() || value.is_array() => value,
+        _ => JsonValue::String(text.to_string()),
+    }
+}
+
+/// The composition without its nodes and edges, as JSON text
+pub(crate) fn header<N, R>(graph: &GraphComposition<N, R>) -> String
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    let mut header = graph.to_interchange_json();
+    if let Some(fields) = header.as_object_mut() {
+        fields.remove("nodes");
+        fields.remove("edges");
+    }
+    header.to_string()
+}
+
+/// Nodes and edges in a stable order, for writing
+pub(crate) fn sorted<N, R>(
+    graph: &GraphComposition<N, R>,
+) -> (Vec<&CompositionNode<N>>, Vec<&CompositionEdge<R>>) {
+    let mut nodes: Vec<_> = graph.nodes.values().collect();
+    nodes.sort_by_key(|node| node.id);
+    let mut edges: Vec<_> = graph.edges.values().collect();
+    edges.sort_by_key(|edge| edge.id);
+    (nodes, edges)
+}
+
+/// An attribute the element must have
+pub(crate) fn required(
+    element: roxmltree::Node<'_, '_>,
+    name: &str,
+) -> Result<String, CompositionError> {
+    element.attribute(name).map(str::to_string).ok_or_else(|| {
+        CompositionError::ParseError(format!(
+            "<{}> has no {name} attribute",
+            element.tag_name().name()
+        ))
+    })
+}
+
+/// The UUID of an element, derived from its ID if that is not a UUID
+fn element_id(id: &str) -> String {
+    Uuid::parse_str(id)
+        .unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_OID, id.as_bytes()))
+        .to_string()
+}
+
+fn json_attribute(
+    owner: &str,
+    name: &str,
+    text: Option<&str>,
+) -> Result<JsonValue, CompositionError> {
+    match text {
+        Some(text) => serde_json::from_str(text)
+            .map_err(|e| CompositionError::ParseError(format!("{owner}: invalid {name}: {e}"))),
+        None => Ok(JsonValue::Object(Map::new())),
+    }
+}
+
+/// Build a composition from the elements of a file
+///
+/// `format` names the composition when the file has no header.
+pub(crate) fn assemble<N, R>(
+    format: &str,
+    header: Option<&str>,
+    nodes: Vec<ImportedNode>,
+    edges: Vec<ImportedEdge>,
+) -> Result<GraphComposition<N, R>, CompositionError>
+where
+    N: Clone + Serialize + for<'de> Deserialize<'de>,
+    R: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    let mut document = match header {
+        Some(header) => serde_json::from_str(header)
+            .map_err(|e| CompositionError::ParseError(format!("invalid header: {e}")))?,
+        None => {
+            let root = nodes.first().ok_or_else(|| {
+                CompositionError::ParseError(format!("{format} file has no nodes"))
+            })?;
+            json!({
+                "schema_version": SCHEMA_VERSION,
+                "id": Uuid::new_v4(),
+                "composition_root": element_id(&root.id),
+                "composition_type": CompositionType::Composite {
+                    structure_type: format.to_string(),
+                },
+                "metadata": Metadata {
+                    name: format.to_string(),
+                    ..Default::default()
+                },
+            })
+        }
+    };
+
+    let mut node_values = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let owner = format!("node {}", node.id);
+        let node_type = node
+            .node_type
+            .ok_or_else(|| CompositionError::ParseError(format!("{owner} has no {NODE_TYPE}")))?;
+        node_values.push(json!({
+            "id": element_id(&node.id),
+            "label": node.label.unwrap_or_else(|| node.id.clone()),
+            "node_type": type_value(&node_type),
+            "data": json_attribute(&owner, DATA, node.data.as_deref())?,
+            "metadata": json_attribute(&owner, METADATA, node.metadata.as_deref())?,
+        }));
+    }
+
+    let mut edge_values = Vec::with_capacity(edges.len());
+    for (index, edge) in edges.into_iter().enumerate() {
+        let id = edge
+            .id
+            .unwrap_or_else(|| format!("{}->{}#{index}", edge.source, edge.target));
+        let owner = format!("edge {id}");
+        let relationship_type = edge.relationship_type.ok_or_else(|| {
+            CompositionError::ParseError(format!("{owner} has no {RELATIONSHIP_TYPE}"))
+        })?;
+        edge_values.push(json!({
+            "id": element_id(&id),
+            "source": element_id(&edge.source),
+            "target": element_id(&edge.target),
+            "relationship": {
+                "relationship_type": type_value(&relationship_type),
+                "metadata": json_attribute(&owner, METADATA, edge.metadata.as_deref())?,
+                "bidirectional": edge.bidirectional.unwrap_or(false),
+            },
+        }));
+    }
+
+    let fields = document
+        .as_object_mut()
+        .ok_or_else(|| CompositionError::ParseError("header is not an object".to_string()))?;
+    fields.insert("nodes".to_string(), JsonValue::Array(node_values));
+    fields.insert("edges".to_string(), JsonValue::Array(edge_values));
+    GraphComposition::from_interchange_json(document)
+}
+
+/// A graph exercising every attribute, with text that needs escaping
+#[cfg(test)]
+pub(crate) fn sample() -> GraphComposition {
+    use crate::invariants::Invariant;
+
+    let graph = GraphComposition::aggregate("Order <&>", "o-1")
+        .add_node(
+            BaseNodeType::Value,
+            "total",
+            json!({ "amount": 250.5, "note": "a \"quoted\"\n<note> & 'more'\t" }),
+        )
+        .add_node(
+            BaseNodeType::Custom("Policy".into()),
+            "pricing",
+            json!(null),
+        )
+        .add_edge_by_label("root", "total", BaseRelationshipType::Contains)
+        .with_invariant_rule(Invariant::acyclic())
+        .with_output_port_by_label("out", "total")
+        .unwrap();
+    let (total, pricing) = (
+        graph.node_by_label("total").unwrap().id,
+        graph.node_by_label("pricing").unwrap().id,
+    );
+    let mut graph = graph
+        .add_edge_with_relationship(
+            total,
+            pricing,
+            Relationship::new(BaseRelationshipType::Custom("prices".into()))
+                .bidirectional()
+                .with_metadata("weight".to_string(), json!(2.5)),
+        )
+        .add_edge_with_relationship(
+            pricing,
+            total,
+            Relationship::new(BaseRelationshipType::Loop).back_edge(),
+        );
+    graph
+        .node_by_label_mut("pricing")
+        .unwrap()
+        .metadata
+        .insert("owner".to_string(), json!(["finance", 1]));
+    graph
+}
@@ -0,0 +1,94 @@
+//! Property tests for the GraphMonad laws
+//!
+//! The graphs here use random IDs, and each `bind` result gets a graph ID of
+//! its own, so the laws hold up to isomorphism rather than equality.
+
+use cim_compose::{
+    BaseNodeType, BaseRelationshipType, CompositionNode, GraphComposition, GraphMonad,
+    IsomorphismOptions,
+};
+use proptest::prelude::*;
+use serde_json::json;
+
+fn isomorphic(a: &GraphComposition, b: &GraphComposition) -> bool {
+    a.is_isomorphic_to(b, IsomorphismOptions::default())
+}
+
+/// A graph with `size` nodes labelled `n0..` and the given edges between them
+fn build_graph(size: usize, edges: &[(usize, usize)]) -> GraphComposition {
+    let root = CompositionNode::new(BaseNodeType::Value, "n0".to_string(), json!(0));
+    let mut graph = GraphComposition::pure(root);
+    for i in 1..size {
+        graph = graph.add_node(BaseNodeType::Value, &format!("n{i}"), json!(i));
+    }
+    for (source, target) in edges {
+        graph = graph.add_edge_by_label(
+            &format!("n{}", source % size),
+            &format!("n{}", target % size),
+            BaseRelationshipType::Sequence,
+        );
+    }
+    graph
+}
+
+/// Expand a node into a chain of `length` steps tagged with `tag`
+fn expand(node: &CompositionNode<BaseNodeType>, tag: &str, length: usize) -> GraphComposition {
+    let head = CompositionNode::new(
+        node.node_type.clone(),
+        format!("{}.{tag}0", node.label),
+        node.data.clone(),
+    );
+    let mut graph = GraphComposition::pure(head);
+    for i in 1..length {
+        graph = graph
+            .add_node(
+                BaseNodeType::Value,
+                &format!("{}.{tag}{i}", node.label),
+                json!(i),
+            )
+            .add_edge_by_label(
+                &format!("{}.{tag}{}", node.label, i - 1),
+                &format!("{}.{tag}{i}", node.label),
+                BaseRelationshipType::Contains,
+            );
+    }
+    graph
+}
+
+fn arb_graph() -> impl Strategy<Value = GraphComposition> {
+    (
+        1usize..6,
+        prop::collection::vec((0usize..6, 0usize..6), 0..8),
+    )
+        .prop_map(|(size, edges)| build_graph(size, &edges))
+}
+
+proptest! {
+    #[test]
+    fn left_identity(length in 1usize..4) {
+        let value = CompositionNode::new(BaseNodeType::Value, "a".to_string(), json!(1));
+        let f = |node: &CompositionNode<BaseNodeType>| expand(node, "f", length);
+
+        let bound = GraphComposition::pure(value.clone()).bind(f).unwrap();
+
+        prop_assert!(isomorphic(&bound, &f(&value)));
+    }
+
+    #[test]
+    fn right_identity(graph in arb_graph()) {
+        let bound = graph.bind(|node| GraphComposition::pure(node.clone())).unwrap();
+
+        prop_assert!(isomorphic(&bound, &graph));
+    }
+
+    #[test]
+    fn associativity(graph in arb_graph(), f_len in 1usize..4, g_len in 1usize..4) {
+        let f = |node: &CompositionNode<BaseNodeType>| expand(node, "f", f_len);
+        let g = |node: &CompositionNode<BaseNodeType>| expand(node, "g", g_len);
+
+        let left = graph.bind(f).unwrap().bind(g).unwrap();
+        let right = graph.bind(|node| f(node).bind(g).unwrap()).unwrap();
+
+        prop_assert!(isomorphic(&left, &right));
+    }
+}